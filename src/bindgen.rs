@@ -1,78 +1,91 @@
 //! Functionality related to running `wasm-bindgen`.
 
 use binary_install::Download;
+use build::WasmArtifact;
 use child;
 use command::build::{BuildProfile};
 use failure::{self, ResultExt};
-use manifest::CrateData;
+use manifest::{CrateData, CrateTargetKind};
 use std::path::{Path};
 use std::process::Command;
 
+/// How `wasm-bindgen` should generate the bindings.
+pub struct BindgenOptions<'a> {
+    /// The directory the bindings are written to.
+    pub out_dir: &'a Path,
+    /// The base name of the generated files, if not the crate's name.
+    pub out_name: &'a Option<String>,
+    /// Don't generate TypeScript declarations.
+    pub disable_dts: bool,
+    /// The profile whose `wasm-bindgen` settings apply.
+    pub profile: &'a BuildProfile,
+    /// The `--target` the bindings are generated for.
+    pub run_target: &'a str,
+    /// Generate bindings for an AOT-compiled module.
+    pub enable_aot: bool,
+    /// Generate bindings using the WasmEdge extensions.
+    pub enable_ext: bool,
+}
+
 /// Run the `wasm-bindgen` CLI to generate bindings for the current crate's
-/// `.wasm`.
+/// library `.wasm`. Binaries and examples in `artifacts` are skipped.
 pub fn wasm_bindgen_build(
     data: &CrateData,
     bindgen: &Download,
-    out_dir: &Path,
-    out_name: &Option<String>,
-    disable_dts: bool,
-    profile: BuildProfile,
-    target: &str,
-    run_target: &str,
-    enable_aot: bool,
-    enable_ext: bool,
+    artifacts: &[WasmArtifact],
+    options: &BindgenOptions,
 ) -> Result<(), failure::Error> {
-    let release_or_debug = match profile {
-        BuildProfile::Release | BuildProfile::Profiling => "release",
-        BuildProfile::Dev => "debug",
-    };
+    let bindgen_path = bindgen.binary("wasm-bindgen")?;
+
+    for artifact in artifacts.iter().filter(|a| a.kind == CrateTargetKind::Lib) {
+        let cmd = wasm_bindgen_command(data, &bindgen_path, &artifact.path, options);
+        child::run(cmd, "wasm-bindgen").context("Running the wasm-bindgen CLI")?;
+    }
 
-    let out_dir = out_dir.to_str().unwrap();
+    Ok(())
+}
 
-    let dts_arg = if disable_dts {
+/// The `wasm-bindgen` command that generates bindings for the library
+/// `wasm`.
+pub fn wasm_bindgen_command(
+    data: &CrateData,
+    bindgen_path: &Path,
+    wasm: &Path,
+    options: &BindgenOptions,
+) -> Command {
+    let dts_arg = if options.disable_dts {
         "--no-typescript"
     } else {
         "--typescript"
     };
-    let bindgen_path = bindgen.binary("wasm-bindgen")?;
-
-    for c in data.crate_name().iter() {
-        let mut cmd = Command::new(&bindgen_path);
-        cmd.arg("--out-dir")
-            .arg(out_dir)
-            .arg(dts_arg);
 
-        cmd.arg("--target").arg(run_target);
+    let mut cmd = Command::new(bindgen_path);
+    cmd.arg("--out-dir")
+        .arg(options.out_dir)
+        .arg(dts_arg);
 
-        if let Some(value) = out_name {
-            cmd.arg("--out-name").arg(value);
-        }
+    cmd.arg("--target").arg(options.run_target);
 
-        let profile = data.configured_profile(profile);
-        if profile.wasm_bindgen_debug_js_glue() {
-            cmd.arg("--debug");
-        }
-        if !profile.wasm_bindgen_demangle_name_section() {
-            cmd.arg("--no-demangle");
-        }
-        if profile.wasm_bindgen_dwarf_debug_info() {
-            cmd.arg("--keep-debug");
-        }
-        if enable_aot {
-            cmd.arg("--enable-aot");
-        }
-        if enable_ext {
-            cmd.arg("--enable-ext");
-        }
-        let wasm_path = data
-            .target_directory()
-            .join(target)
-            .join(release_or_debug)
-            .join(c.as_str())
-            .with_extension("wasm");
-        cmd.arg(&wasm_path);
-        child::run(cmd, "wasm-bindgen").context("Running the wasm-bindgen CLI")?;
+    if let Some(value) = options.out_name {
+        cmd.arg("--out-name").arg(value);
     }
 
-    Ok(())
+    let configured_profile = data.configured_profile(options.profile);
+    if configured_profile.wasm_bindgen_debug_js_glue() {
+        cmd.arg("--debug");
+    }
+    if !configured_profile.wasm_bindgen_demangle_name_section() {
+        cmd.arg("--no-demangle");
+    }
+    if configured_profile.wasm_bindgen_dwarf_debug_info() {
+        cmd.arg("--keep-debug");
+    }
+    if options.enable_aot {
+        cmd.arg("--enable-aot");
+    }
+    if options.enable_ext {
+        cmd.arg("--enable-ext");
+    }
+    cmd.arg(wasm);
+    cmd
 }
@@ -1,10 +1,15 @@
 //! Building a Rust crate into a `.wasm` binary.
 
+use cargo_metadata::{Message, PackageId};
 use child;
+use manifest::{CrateData, CrateTargetKind};
 use command::build::BuildProfile;
 use emoji;
+use error::ErrorCode;
 use failure::{Error, ResultExt};
-use std::path::Path;
+use reproducible::Reproducible;
+use std::io::Read;
+use std::path::{Path, PathBuf};
 use std::process::Command;
 use std::str;
 use PBAR;
@@ -17,7 +22,8 @@ pub fn check_rustc_version() -> Result<u32, Error> {
     match local_minor_version {
         Some(mv) => {
             if mv < 30 {
-                bail!(
+                bail_code!(
+                    ErrorCode::RustcTooOld,
                     "Your version of Rust, '1.{}', is not supported. Please install Rust version 1.30.0 or higher.",
                     mv.to_string()
                 )
@@ -25,7 +31,10 @@ pub fn check_rustc_version() -> Result<u32, Error> {
                 Ok(mv)
             }
         }
-        None => bail!("We can't figure out what your Rust version is- which means you might not have Rust installed. Please install Rust version 1.30.0 or higher."),
+        None => bail_code!(
+            ErrorCode::RustNotInstalled,
+            "We can't figure out what your Rust version is- which means you might not have Rust installed. Please install Rust version 1.30.0 or higher."
+        ),
     }
 }
 
@@ -48,18 +57,182 @@ fn rustc_minor_version() -> Option<u32> {
     otry!(pieces.next()).parse().ok()
 }
 
-/// Run `cargo build`.
-pub fn cargo_build_wasm(
-    path: &Path,
-    profile: BuildProfile,
-    target: &str,
-    extra_options: &[String],
-) -> Result<(), Error> {
+/// The cargo features to activate for a build.
+#[derive(Clone, Debug, Default)]
+pub struct CargoFeatures {
+    /// Features passed to `--features`.
+    pub features: Vec<String>,
+    /// Whether to pass `--all-features`.
+    pub all_features: bool,
+    /// Whether to pass `--no-default-features`.
+    pub no_default_features: bool,
+}
+
+impl CargoFeatures {
+    /// The cargo arguments selecting these features.
+    pub fn args(&self) -> Vec<String> {
+        let mut args = Vec::new();
+        if !self.features.is_empty() {
+            args.push("--features".to_string());
+            args.push(self.features.join(","));
+        }
+        if self.all_features {
+            args.push("--all-features".to_string());
+        }
+        if self.no_default_features {
+            args.push("--no-default-features".to_string());
+        }
+        args
+    }
+
+    fn add_args(&self, cmd: &mut Command) {
+        cmd.args(self.args());
+    }
+}
+
+/// The cargo targets to build.
+#[derive(Clone, Debug, Default)]
+pub struct TargetSelection {
+    /// Whether to build the library.
+    pub lib: bool,
+    /// Build every binary whose required features are enabled.
+    pub all_bins: bool,
+    /// Binaries to build by name.
+    pub bins: Vec<String>,
+    /// Examples to build by name.
+    pub examples: Vec<String>,
+}
+
+impl TargetSelection {
+    /// The `.wasm` files `cargo build` is expected to write for the selected
+    /// targets, used to plan a build without running it.
+    pub fn expected_artifacts(
+        &self,
+        crate_data: &CrateData,
+        profile: &BuildProfile,
+        wasm_target: &str,
+    ) -> Vec<WasmArtifact> {
+        let profile_dir = match profile {
+            BuildProfile::Dev => "debug",
+            BuildProfile::Release | BuildProfile::Profiling => "release",
+            BuildProfile::Custom(name) => name,
+        };
+        let dir = crate_data
+            .target_directory()
+            .join(wasm_target)
+            .join(profile_dir);
+        let mut artifacts = Vec::new();
+        for target in crate_data.crate_targets() {
+            let selected = match target.kind {
+                CrateTargetKind::Lib => self.lib,
+                _ => self.all_bins || self.bins.contains(&target.name),
+            };
+            if selected {
+                artifacts.push(WasmArtifact {
+                    path: dir.join(format!("{}.wasm", target.name)),
+                    name: target.name,
+                    kind: target.kind,
+                });
+            }
+        }
+        for example in &self.examples {
+            artifacts.push(WasmArtifact {
+                name: example.clone(),
+                kind: CrateTargetKind::Example,
+                path: dir.join("examples").join(format!("{}.wasm", example)),
+            });
+        }
+        artifacts
+    }
+
+    fn add_args(&self, cmd: &mut Command) {
+        if self.lib {
+            cmd.arg("--lib");
+        }
+        if self.all_bins {
+            cmd.arg("--bins");
+        }
+        for bin in &self.bins {
+            cmd.arg("--bin").arg(bin);
+        }
+        for example in &self.examples {
+            cmd.arg("--example").arg(example);
+        }
+    }
+}
+
+/// A `.wasm` file produced by `cargo build`.
+#[derive(Clone, Debug)]
+pub struct WasmArtifact {
+    /// The name of the cargo target that produced this file.
+    pub name: String,
+    /// The kind of the cargo target that produced this file.
+    pub kind: CrateTargetKind,
+    /// Where cargo wrote the file.
+    pub path: PathBuf,
+}
+
+/// Run `cmd`, a `cargo build` from `cargo_build_command`, returning the
+/// `.wasm` files it produced.
+///
+/// Artifacts are located from cargo's `compiler-artifact` JSON messages, so
+/// `CARGO_TARGET_DIR`, `--target-dir`, `build.target-dir` and custom profiles
+/// are all honored.
+pub fn cargo_build_wasm(crate_data: &CrateData, cmd: Command) -> Result<Vec<WasmArtifact>, Error> {
     let msg = format!("{}Compiling to Wasm...", emoji::CYCLONE);
     PBAR.info(&msg);
 
+    let package_id = crate_data.package_id();
+    let artifacts = child::run_reading_stdout(cmd, "cargo build", |stdout| {
+        read_wasm_artifacts(stdout, package_id)
+    })
+    .context("Compiling your crate to WebAssembly failed")?;
+    Ok(artifacts)
+}
+
+/// Collect the `.wasm` files of the package `package_id` from cargo's JSON
+/// messages. Dependencies and other workspace members may build cdylibs too,
+/// but only the crate being built is packaged.
+fn read_wasm_artifacts(stdout: &mut dyn Read, package_id: &PackageId) -> Result<Vec<WasmArtifact>, Error> {
+    let mut artifacts = Vec::new();
+    for message in cargo_metadata::parse_messages(stdout) {
+        if let Message::CompilerArtifact(artifact) = message? {
+            // Test harnesses are never packaged.
+            if artifact.profile.test || artifact.package_id != *package_id {
+                continue;
+            }
+            let kind = match CrateTargetKind::from_cargo_kinds(&artifact.target.kind) {
+                Some(kind) => kind,
+                None => continue,
+            };
+            for filename in &artifact.filenames {
+                let path = PathBuf::from(filename);
+                if path.extension().and_then(|s| s.to_str()) == Some("wasm") {
+                    artifacts.push(WasmArtifact {
+                        name: artifact.target.name.clone(),
+                        kind,
+                        path,
+                    });
+                }
+            }
+        }
+    }
+    Ok(artifacts)
+}
+
+/// The `cargo build` command run by `cargo_build_wasm`.
+pub fn cargo_build_command(
+    path: &Path,
+    profile: &BuildProfile,
+    target: &str,
+    targets: &TargetSelection,
+    features: &CargoFeatures,
+    extra_options: &[String],
+    reproducible: Option<&Reproducible>,
+) -> Command {
     let mut cmd = Command::new("cargo");
-    cmd.current_dir(path).arg("build").arg("--all-targets");
+    cmd.current_dir(path).arg("build");
+    targets.add_args(&mut cmd);
 
     if PBAR.quiet() {
         cmd.arg("--quiet");
@@ -81,12 +254,19 @@ pub fn cargo_build_wasm(
             // Plain cargo builds use the dev cargo profile, which includes
             // debug info by default.
         }
+        BuildProfile::Custom(name) => {
+            cmd.arg("--profile").arg(name);
+        }
     }
 
     cmd.arg("--target").arg(target);
+    features.add_args(&mut cmd);
+    cmd.arg("--message-format=json-render-diagnostics");
     cmd.args(extra_options);
-    child::run(cmd, "cargo build").context("Compiling your crate to WebAssembly failed")?;
-    Ok(())
+    if let Some(reproducible) = reproducible {
+        reproducible.configure_cargo(&mut cmd, path, target);
+    }
+    cmd
 }
 
 /// Run `cargo build --tests` targetting `wasm32-unknown-unknown`.
@@ -111,3 +291,170 @@ pub fn cargo_build_wasm_tests(path: &Path, debug: bool) -> Result<(), Error> {
     child::run(cmd, "cargo build").context("Compilation of your program failed")?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::fs;
+    use tempfile;
+
+    fn artifact_message(package_id: &str, name: &str, kind: &str, filename: &str) -> String {
+        json!({
+            "reason": "compiler-artifact",
+            "package_id": package_id,
+            "target": {
+                "name": name,
+                "kind": [kind],
+                "crate_types": [kind],
+                "src_path": "/src/lib.rs",
+                "edition": "2018",
+            },
+            "profile": {
+                "opt_level": "3",
+                "debuginfo": null,
+                "debug_assertions": false,
+                "overflow_checks": false,
+                "test": false,
+            },
+            "features": [],
+            "filenames": [filename],
+            "executable": null,
+            "fresh": false,
+        })
+        .to_string()
+    }
+
+    #[test]
+    fn only_reads_artifacts_of_the_package() {
+        let root = "demo 0.1.0 (path+file:///demo)";
+        let messages = [
+            artifact_message(
+                "helper 0.1.0 (path+file:///demo/helper)",
+                "helper",
+                "cdylib",
+                "/demo/target/wasm32-wasi/release/helper.wasm",
+            ),
+            artifact_message(root, "demo", "cdylib", "/demo/target/wasm32-wasi/release/demo.wasm"),
+            artifact_message(root, "demo", "cdylib", "/demo/target/wasm32-wasi/release/libdemo.rlib"),
+        ]
+        .join("\n");
+        let package_id = PackageId {
+            repr: root.to_string(),
+        };
+
+        let artifacts = read_wasm_artifacts(&mut messages.as_bytes(), &package_id).unwrap();
+        assert_eq!(artifacts.len(), 1);
+        assert_eq!(artifacts[0].name, "demo");
+        assert_eq!(artifacts[0].kind, CrateTargetKind::Lib);
+        assert_eq!(
+            artifacts[0].path,
+            PathBuf::from("/demo/target/wasm32-wasi/release/demo.wasm")
+        );
+    }
+
+    /// A crate with a cdylib, the binaries `tool` and `other`, and the example
+    /// `demo`.
+    fn mixed_crate(dir: &Path) -> CrateData {
+        fs::write(
+            dir.join("Cargo.toml"),
+            "[package]\nname = \"mixed\"\nversion = \"0.1.0\"\n\
+             [lib]\ncrate-type = [\"cdylib\"]\n",
+        )
+        .unwrap();
+        for source in &["src/lib.rs", "src/bin/tool.rs", "src/bin/other.rs", "examples/demo.rs"] {
+            let path = dir.join(source);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, "").unwrap();
+        }
+        CrateData::new(dir, None).unwrap()
+    }
+
+    fn names(artifacts: &[WasmArtifact]) -> Vec<(&str, CrateTargetKind)> {
+        let mut names: Vec<_> = artifacts.iter().map(|a| (a.name.as_str(), a.kind)).collect();
+        names.sort_by(|a, b| a.0.cmp(b.0));
+        names
+    }
+
+    fn args(targets: &TargetSelection) -> Vec<String> {
+        let mut cmd = Command::new("cargo");
+        targets.add_args(&mut cmd);
+        cmd.get_args()
+            .map(|arg| arg.to_string_lossy().into_owned())
+            .collect()
+    }
+
+    #[test]
+    fn expects_the_artifacts_of_the_selected_targets() {
+        let dir = tempfile::tempdir().unwrap();
+        let data = mixed_crate(dir.path());
+        let release = data.target_directory().join("wasm32-wasi/release");
+
+        let everything = TargetSelection {
+            lib: true,
+            all_bins: true,
+            bins: Vec::new(),
+            examples: Vec::new(),
+        };
+        let artifacts =
+            everything.expected_artifacts(&data, &BuildProfile::Release, "wasm32-wasi");
+        assert_eq!(
+            names(&artifacts),
+            vec![
+                ("mixed", CrateTargetKind::Lib),
+                ("other", CrateTargetKind::Bin),
+                ("tool", CrateTargetKind::Bin),
+            ]
+        );
+        let lib = artifacts.iter().find(|a| a.kind == CrateTargetKind::Lib).unwrap();
+        assert_eq!(lib.path, release.join("mixed.wasm"));
+
+        let selected = TargetSelection {
+            lib: false,
+            all_bins: false,
+            bins: vec!["tool".to_string()],
+            examples: vec!["demo".to_string()],
+        };
+        let artifacts = selected.expected_artifacts(&data, &BuildProfile::Dev, "wasm32-wasi");
+        assert_eq!(
+            names(&artifacts),
+            vec![("demo", CrateTargetKind::Example), ("tool", CrateTargetKind::Bin)]
+        );
+        let debug = data.target_directory().join("wasm32-wasi/debug");
+        for artifact in &artifacts {
+            let expected = match artifact.kind {
+                CrateTargetKind::Example => debug.join("examples/demo.wasm"),
+                _ => debug.join("tool.wasm"),
+            };
+            assert_eq!(artifact.path, expected);
+        }
+
+        let custom = BuildProfile::Custom("faas".to_string());
+        let artifacts = selected.expected_artifacts(&data, &custom, "wasm32-unknown-unknown");
+        let faas = data.target_directory().join("wasm32-unknown-unknown/faas");
+        assert!(artifacts.iter().all(|a| a.path.starts_with(&faas)));
+    }
+
+    #[test]
+    fn passes_the_selected_targets_to_cargo() {
+        assert!(args(&TargetSelection::default()).is_empty());
+        assert_eq!(
+            args(&TargetSelection {
+                lib: true,
+                all_bins: true,
+                bins: Vec::new(),
+                examples: Vec::new(),
+            }),
+            vec!["--lib", "--bins"]
+        );
+        assert_eq!(
+            args(&TargetSelection {
+                lib: false,
+                all_bins: false,
+                bins: vec!["tool".to_string(), "other".to_string()],
+                examples: vec!["demo".to_string()],
+            }),
+            vec!["--bin", "tool", "--bin", "other", "--example", "demo"]
+        );
+    }
+}
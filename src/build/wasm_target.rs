@@ -1,11 +1,15 @@
-//! Checking for the wasm32 target
+//! Checking for the wasm32 target, and which platform-specific dependencies
+//! apply to it
 
 use child;
 use emoji;
+use error::ErrorCode;
 use failure::{Error, ResultExt};
 use log::info;
 use std::fmt;
-use std::path::PathBuf;
+use std::iter::Peekable;
+use std::path::{Path, PathBuf};
+use std::vec::IntoIter;
 use std::process::Command;
 use PBAR;
 
@@ -60,7 +64,7 @@ pub fn check_for_wasm32_target(target: &str) -> Result<(), Error> {
     // Check if wasm32 target is present, otherwise bail.
     match check_wasm32_target(target) {
         Ok(ref wasm32_check) if wasm32_check.found => Ok(()),
-        Ok(wasm32_check) => bail!("{}", wasm32_check),
+        Ok(wasm32_check) => bail_code!(ErrorCode::Wasm32TargetMissing, "{}", wasm32_check),
         Err(err) => Err(err),
     }
 }
@@ -68,7 +72,8 @@ pub fn check_for_wasm32_target(target: &str) -> Result<(), Error> {
 /// Get rustc's sysroot as a PathBuf
 fn get_rustc_sysroot() -> Result<PathBuf, Error> {
     let command = Command::new("rustc")
-        .args(&["--print", "sysroot"])
+        .arg("--print")
+        .arg("sysroot")
         .output()?;
 
     if command.status.success() {
@@ -82,7 +87,7 @@ fn get_rustc_sysroot() -> Result<PathBuf, Error> {
 }
 
 /// Checks if the wasm32 target is present in rustc's sysroot.
-fn is_wasm32_target_in_sysroot(sysroot: &PathBuf, wasm32_target: &str) -> bool {
+fn is_wasm32_target_in_sysroot(sysroot: &Path, wasm32_target: &str) -> bool {
     let rustlib_path = sysroot.join("lib/rustlib");
 
     info!("Looking for {} in {:?}", wasm32_target, rustlib_path);
@@ -142,3 +147,164 @@ fn rustup_add_wasm_target(target: &str) -> Result<(), Error> {
 
     Ok(())
 }
+
+/// Whether the platform of a `[target.<platform>.dependencies]` table, a
+/// target triple or a `cfg(...)` expression, applies to `wasm_target`.
+///
+/// A `cfg` expression that can't be parsed is assumed to apply.
+pub fn platform_matches(platform: &str, wasm_target: &str) -> bool {
+    let platform = platform.trim();
+    if !platform.starts_with("cfg(") || !platform.ends_with(')') {
+        return platform == wasm_target;
+    }
+    let os = if wasm_target.contains("wasi") {
+        "wasi"
+    } else {
+        "unknown"
+    };
+    let cfg = [
+        ("target_arch", "wasm32"),
+        ("target_os", os),
+        ("target_family", "wasm"),
+        ("target_vendor", "unknown"),
+        ("target_env", ""),
+        ("target_endian", "little"),
+        ("target_pointer_width", "32"),
+    ];
+    let mut tokens = match cfg_tokens(&platform[3..]) {
+        Some(tokens) => tokens.into_iter().peekable(),
+        None => return true,
+    };
+    let result = match tokens.next() {
+        Some(CfgToken::Open) => eval_cfg(&mut tokens, &cfg),
+        _ => None,
+    };
+    match (result, tokens.next(), tokens.next()) {
+        (Some(result), Some(CfgToken::Close), None) => result,
+        _ => true,
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum CfgToken {
+    Ident(String),
+    Str(String),
+    Open,
+    Close,
+    Comma,
+    Eq,
+}
+
+fn cfg_tokens(s: &str) -> Option<Vec<CfgToken>> {
+    let mut tokens = Vec::new();
+    let mut chars = s.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '(' => tokens.push(CfgToken::Open),
+            ')' => tokens.push(CfgToken::Close),
+            ',' => tokens.push(CfgToken::Comma),
+            '=' => tokens.push(CfgToken::Eq),
+            '"' => {
+                let mut value = String::new();
+                loop {
+                    match chars.next()? {
+                        '"' => break,
+                        c => value.push(c),
+                    }
+                }
+                tokens.push(CfgToken::Str(value));
+            }
+            c if c.is_whitespace() => {}
+            c if c.is_alphanumeric() || c == '_' => {
+                let mut ident = c.to_string();
+                while let Some(&c) = chars.peek() {
+                    if !c.is_alphanumeric() && c != '_' {
+                        break;
+                    }
+                    ident.push(c);
+                    chars.next();
+                }
+                tokens.push(CfgToken::Ident(ident));
+            }
+            _ => return None,
+        }
+    }
+    Some(tokens)
+}
+
+/// Evaluate one cfg predicate, or return `None` if it is malformed.
+fn eval_cfg(tokens: &mut Peekable<IntoIter<CfgToken>>, cfg: &[(&str, &str)]) -> Option<bool> {
+    let name = match tokens.next()? {
+        CfgToken::Ident(name) => name,
+        _ => return None,
+    };
+    match tokens.peek() {
+        Some(CfgToken::Eq) => {
+            tokens.next();
+            match tokens.next()? {
+                CfgToken::Str(value) => Some(cfg.contains(&(name.as_str(), value.as_str()))),
+                _ => None,
+            }
+        }
+        Some(CfgToken::Open) => {
+            tokens.next();
+            let mut values = Vec::new();
+            loop {
+                if tokens.peek() == Some(&CfgToken::Close) {
+                    tokens.next();
+                    break;
+                }
+                values.push(eval_cfg(tokens, cfg)?);
+                match tokens.next()? {
+                    CfgToken::Comma => {}
+                    CfgToken::Close => break,
+                    _ => return None,
+                }
+            }
+            match name.as_str() {
+                "all" => Some(values.iter().all(|&v| v)),
+                "any" => Some(values.iter().any(|&v| v)),
+                "not" if values.len() == 1 => Some(!values[0]),
+                _ => None,
+            }
+        }
+        // Names like `unix`, `windows` or `test` are never set for wasm.
+        _ => Some(false),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::platform_matches;
+
+    #[test]
+    fn matches_target_triples() {
+        assert!(platform_matches("wasm32-wasi", "wasm32-wasi"));
+        assert!(!platform_matches("x86_64-pc-windows-msvc", "wasm32-wasi"));
+    }
+
+    #[test]
+    fn evaluates_cfg_expressions() {
+        assert!(platform_matches(r#"cfg(target_arch = "wasm32")"#, "wasm32-wasi"));
+        assert!(platform_matches(r#"cfg(target_os = "wasi")"#, "wasm32-wasi"));
+        assert!(!platform_matches(r#"cfg(target_os = "wasi")"#, "wasm32-unknown-unknown"));
+        assert!(!platform_matches("cfg(unix)", "wasm32-wasi"));
+        assert!(!platform_matches("cfg(windows)", "wasm32-wasi"));
+        assert!(platform_matches("cfg(not(windows))", "wasm32-wasi"));
+        assert!(platform_matches(
+            r#"cfg(all(target_arch = "wasm32", not(target_os = "emscripten")))"#,
+            "wasm32-wasi"
+        ));
+        assert!(!platform_matches(
+            r#"cfg(any(target_os = "linux", target_os = "macos"))"#,
+            "wasm32-wasi"
+        ));
+    }
+
+    #[test]
+    fn assumes_malformed_cfg_applies() {
+        assert!(platform_matches("cfg(all(unix)", "wasm32-wasi"));
+        assert!(platform_matches(r#"cfg(target_os = "wasi)"#, "wasm32-wasi"));
+        assert!(platform_matches("cfg(not(unix, windows))", "wasm32-wasi"));
+    }
+}
@@ -6,7 +6,12 @@
 use failure::Error;
 use install::Tool;
 use log::info;
-use std::process::{Command, Stdio};
+use logfile;
+use std::io::{self, Read, Write};
+use std::path::Path;
+use std::process::{Command, ExitStatus, Stdio};
+use std::thread::{self, JoinHandle};
+use std::time::Instant;
 
 /// Return a new Command object
 pub fn new_command(program: &str) -> Command {
@@ -24,11 +29,118 @@ pub fn new_command(program: &str) -> Command {
     }
 }
 
+/// Reads from a child's stdout, keeping a copy of what was read if
+/// `recorded` is set.
+struct Recorder<R> {
+    inner: R,
+    recorded: Option<Vec<u8>>,
+}
+
+impl<R: Read> Read for Recorder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        if let Some(recorded) = self.recorded.as_mut() {
+            recorded.extend_from_slice(&buf[..n]);
+        }
+        Ok(n)
+    }
+}
+
+/// Copy everything read from `source` to `sink` as it arrives, returning a
+/// copy of it once `source` is closed.
+fn tee<R, W>(mut source: R, mut sink: W) -> JoinHandle<Vec<u8>>
+where
+    R: Read + Send + 'static,
+    W: Write + Send + 'static,
+{
+    thread::spawn(move || {
+        let mut copy = Vec::new();
+        let mut buf = [0; 8192];
+        while let Ok(n) = source.read(&mut buf) {
+            if n == 0 {
+                break;
+            }
+            let _ = sink.write_all(&buf[..n]);
+            let _ = sink.flush();
+            copy.extend_from_slice(&buf[..n]);
+        }
+        copy
+    })
+}
+
+/// Spawn `command` with its stdout piped to `read_stdout`, and wait for it.
+///
+/// When a log file is open, the command's stderr is also piped, copied to
+/// ours, and written to the log along with its stdout, exit status and
+/// duration. Otherwise stderr is left as configured.
+fn execute<T, F>(
+    command: &mut Command,
+    command_name: &str,
+    read_stdout: F,
+) -> Result<(ExitStatus, Result<T, Error>), Error>
+where
+    F: FnOnce(&mut dyn Read) -> Result<T, Error>,
+{
+    execute_with_stderr(command, command_name, read_stdout, io::stderr())
+}
+
+/// `execute`, copying the command's stderr to `stderr` when it is piped.
+fn execute_with_stderr<T, F, W>(
+    command: &mut Command,
+    command_name: &str,
+    read_stdout: F,
+    stderr: W,
+) -> Result<(ExitStatus, Result<T, Error>), Error>
+where
+    F: FnOnce(&mut dyn Read) -> Result<T, Error>,
+    W: Write + Send + 'static,
+{
+    let logging = logfile::is_open();
+    let started = Instant::now();
+    if logging {
+        logfile::command_started(command_name, command);
+        command.stderr(Stdio::piped());
+    }
+
+    let mut child = command.stdout(Stdio::piped()).spawn()?;
+    let stderr = child.stderr.take().map(|piped| tee(piped, stderr));
+    let mut stdout = Recorder {
+        inner: child.stdout.take().expect("stdout should be piped"),
+        recorded: if logging { Some(Vec::new()) } else { None },
+    };
+    let result = read_stdout(&mut stdout);
+    io::copy(&mut stdout, &mut io::sink())?;
+    let status = child.wait()?;
+
+    if logging {
+        let stderr = stderr
+            .and_then(|stderr| stderr.join().ok())
+            .unwrap_or_default();
+        logfile::command_finished(
+            command_name,
+            &status,
+            started.elapsed(),
+            &stdout.recorded.unwrap_or_default(),
+            &stderr,
+        );
+    }
+    Ok((status, result))
+}
+
 /// Run the given command and return on success.
 pub fn run(mut command: Command, command_name: &str) -> Result<(), Error> {
     info!("Running {:?}", command);
 
-    let status = command.status()?;
+    let status = if logfile::is_open() {
+        let (status, result) = execute(&mut command, command_name, |stdout| {
+            io::copy(stdout, &mut io::stdout())?;
+            Ok(())
+        })?;
+        result?;
+        status
+    } else {
+        command.status()?
+    };
 
     if status.success() {
         Ok(())
@@ -42,23 +154,147 @@ pub fn run(mut command: Command, command_name: &str) -> Result<(), Error> {
     }
 }
 
+/// Run the given command, handing its stdout to `read_stdout` while it runs.
+///
+/// Whatever `read_stdout` leaves unread is drained before waiting for the
+/// command to exit. The command's stderr is inherited.
+pub fn run_reading_stdout<T, F>(
+    mut command: Command,
+    command_name: &str,
+    read_stdout: F,
+) -> Result<T, Error>
+where
+    F: FnOnce(&mut dyn Read) -> Result<T, Error>,
+{
+    info!("Running {:?}", command);
+
+    let (status, result) = execute(&mut command, command_name, read_stdout)?;
+
+    if status.success() {
+        result
+    } else {
+        bail!(
+            "failed to execute `{}`: exited with {}\n  full command: {:?}",
+            command_name,
+            status,
+            command,
+        )
+    }
+}
+
+/// Run `git` with `args` in `dir` and return its trimmed stdout, or `None`
+/// if git isn't installed, fails, e.g. outside a repository, or prints
+/// nothing.
+pub fn git_output(dir: &Path, args: &[&str]) -> Option<String> {
+    let mut command = Command::new("git");
+    command.current_dir(dir).args(args);
+    info!("Running {:?}", command);
+    let output = command.output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    Some(String::from_utf8_lossy(&output.stdout).trim().to_string()).filter(|s| !s.is_empty())
+}
+
 /// Run the given command and return its stdout.
 pub fn run_capture_stdout(mut command: Command, command_name: &Tool) -> Result<String, Error> {
     info!("Running {:?}", command);
 
-    let output = command
-        .stderr(Stdio::inherit())
-        .stdin(Stdio::inherit())
-        .output()?;
+    command.stderr(Stdio::inherit()).stdin(Stdio::inherit());
+    let (status, stdout) = execute(&mut command, &command_name.to_string(), |stdout| {
+        let mut bytes = Vec::new();
+        stdout.read_to_end(&mut bytes)?;
+        Ok(bytes)
+    })?;
 
-    if output.status.success() {
-        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    if status.success() {
+        Ok(String::from_utf8_lossy(&stdout?).into_owned())
     } else {
         bail!(
             "failed to execute `{}`: exited with {}\n  full command: {:?}",
             command_name,
-            output.status,
+            status,
             command,
         )
     }
 }
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::sync::{Arc, Mutex};
+    use tempfile;
+
+    /// Stands in for the terminal's stderr.
+    #[derive(Clone, Default)]
+    struct Terminal(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Terminal {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn logs_commands_and_still_forwards_stderr() {
+        let dir = tempfile::tempdir().unwrap();
+        let log = dir.path().join("logs/build.log");
+        logfile::open(&log).unwrap();
+
+        let mut command = Command::new("sh");
+        command
+            .arg("-c")
+            .arg("echo built; echo 'warning: one'; echo 'warning: two' >&2; exit 3");
+        let terminal = Terminal::default();
+        let result = execute_with_stderr(
+            &mut command,
+            "child-log-test",
+            |stdout| {
+                let mut first = [0; 6];
+                stdout.read_exact(&mut first)?;
+                Ok(String::from_utf8_lossy(&first).into_owned())
+            },
+            terminal.clone(),
+        );
+        logfile::close();
+        let (status, stdout) = result.unwrap();
+
+        assert_eq!(status.code(), Some(3));
+        assert_eq!(stdout.unwrap(), "built\n");
+        assert_eq!(&*terminal.0.lock().unwrap(), b"warning: two\n");
+
+        // Other tests may log at the same time, so look for this command's
+        // entries only.
+        let log = fs::read_to_string(&log).unwrap();
+        let lines: Vec<&str> = log.lines().collect();
+        assert!(lines[0].contains(" START rustwasmc "));
+        let run = lines
+            .iter()
+            .position(|line| line.contains(" RUN   child-log-test: "))
+            .unwrap();
+        assert!(lines[run].contains("\"sh\" \"-c\""));
+        let exit = lines
+            .iter()
+            .position(|line| line.contains(" EXIT  child-log-test: exit status: 3 after "))
+            .unwrap();
+        assert!(exit > run);
+        assert!(lines[exit].ends_with('s'));
+        // The rest of stdout is drained and logged, not only what was read.
+        assert_eq!(
+            &lines[exit + 1..exit + 6],
+            &[
+                "    --- stdout",
+                "    built",
+                "    warning: one",
+                "    --- stderr",
+                "    warning: two",
+            ][..]
+        );
+    }
+}
@@ -7,18 +7,36 @@ use bindgen;
 use build;
 use cache;
 use command::utils::{create_pkg_dir, get_crate_path};
+use config::{self, EffectiveConfig};
 use emoji;
+use hooks::{self, HookEnv};
+use error::{self, CodedError, ErrorCode};
 use failure::Error;
 use install::{self, InstallMode, Tool};
 use license;
 use lockfile::Lockfile;
 use log::info;
-use manifest;
+use logfile;
+use manifest::{self, CrateTargetKind};
+use oci::{self, OciImage};
+use pack;
+use plan::{BuildPlan, PlanFormat, PlannedCommand, PlannedStep, PlannedTool, ToolSource};
 use readme;
+use reporter::{self, Reporter, TerminalReporter};
+use reproducible::{self, Reproducible};
 use std::fs;
 use std::path::PathBuf;
-use std::time::Instant;
-use PBAR;
+use std::process::Command;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Build steps paired with an earlier step they can't run without.
+/// build-wasm isn't a prerequisite: without it, the wasm files of the previous
+/// build are used.
+const STEP_PREREQUISITES: &[(&str, &str)] = &[("run-wasm-bindgen", "install-wasm-bindgen")];
+
+/// The build steps that read the wasm files written by build-wasm.
+const ARTIFACT_STEPS: &[&str] = &["install-wasm-bindgen", "run-wasm-bindgen", "copy-wasm"];
 
 /// Everything required to configure and run the `rustwasmc build` command.
 #[allow(missing_docs)]
@@ -39,11 +57,24 @@ pub struct Build {
     pub bindgen: Option<Download>,
     pub cache: Cache,
     pub extra_options: Vec<String>,
+    pub reproducible: Option<Reproducible>,
+    pub mirrors: config::Mirrors,
+    pub artifacts: Vec<build::WasmArtifact>,
+    pub features: build::CargoFeatures,
+    pub targets: build::TargetSelection,
+    pub oci: Option<(String, String)>,
+    pub oci_archive: bool,
+    pub runtime_version: Option<String>,
+    pub no_runtime_dependency: bool,
+    pub third_party_licenses: bool,
+    pub reporter: Arc<dyn Reporter>,
+    pub skip_steps: Vec<String>,
+    pub only_steps: Vec<String>,
 }
 
 /// The build profile controls whether optimizations, debug info, and assertions
 /// are enabled or disabled.
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Debug)]
 pub enum BuildProfile {
     /// Enable assertions and debug info. Disable optimizations.
     Dev,
@@ -51,10 +82,63 @@ pub enum BuildProfile {
     Release,
     /// Enable optimizations and debug info. Disable assertions.
     Profiling,
+    /// A user-defined cargo profile, configured by
+    /// `[package.metadata.rustwasmc.profile.<name>]`.
+    Custom(String),
+}
+
+impl BuildProfile {
+    /// Get the profile selected by `--profile <name>`.
+    pub fn from_name(name: &str) -> BuildProfile {
+        match name {
+            "dev" => BuildProfile::Dev,
+            "release" => BuildProfile::Release,
+            "profiling" => BuildProfile::Profiling,
+            custom => BuildProfile::Custom(custom.to_string()),
+        }
+    }
+
+    /// The name of the profile, as passed to `--profile`.
+    pub fn name(&self) -> &str {
+        match self {
+            BuildProfile::Dev => "dev",
+            BuildProfile::Release => "release",
+            BuildProfile::Profiling => "profiling",
+            BuildProfile::Custom(name) => name,
+        }
+    }
+
+    /// Check that Rust `1.<rustc_minor_version>` can build with this profile.
+    /// Cargo supports custom profiles from Rust 1.57, but WASI libraries
+    /// (`wasi_lib`) need Rust 1.50 or below, so they can never use one.
+    pub fn check_toolchain(&self, rustc_minor_version: u32, wasi_lib: bool) -> Result<(), Error> {
+        let name = match self {
+            BuildProfile::Custom(name) => name,
+            _ => return Ok(()),
+        };
+        if wasi_lib {
+            bail_code!(
+                ErrorCode::ProfileNeedsNewerRust,
+                "WASI libraries can't be built with the custom profile `{}`, as cargo needs \
+                 Rust 1.57 for custom profiles and WASI libraries need Rust 1.50 or below; \
+                 use --dev, --release or --profiling, or build with --no-wasi",
+                name
+            );
+        }
+        if rustc_minor_version < 57 {
+            bail_code!(
+                ErrorCode::ProfileNeedsNewerRust,
+                "the custom profile `{}` needs Rust 1.57 or higher, but you have Rust 1.{}",
+                name,
+                rustc_minor_version
+            );
+        }
+        Ok(())
+    }
 }
 
 /// Everything required to configure and run the `rustwasmc build` command.
-#[derive(Debug, StructOpt)]
+#[derive(Clone, Debug, StructOpt)]
 pub struct BuildOptions {
     /// The path to the Rust crate. If not set, searches up the path from the current directory.
     #[structopt(parse(from_os_str))]
@@ -64,26 +148,47 @@ pub struct BuildOptions {
     #[structopt(long = "scope", short = "s")]
     pub scope: Option<String>,
 
-    #[structopt(long = "mode", short = "m", default_value = "normal")]
-    /// Sets steps to be run. [possible values: no-install, normal, force]
-    pub mode: InstallMode,
+    #[structopt(long = "mode", short = "m")]
+    /// Sets steps to be run. [possible values: no-install, normal(default), force]
+    pub mode: Option<InstallMode>,
 
-    #[structopt(long = "target", default_value = "wasmedge")]
+    #[structopt(long = "target")]
     /// Sets the runtime target. [possible values: wasmedge(default), nodejs, deno].
-    pub target: String,
+    pub target: Option<String>,
 
     #[structopt(long = "enable-aot")]
     /// Enable AOT in WasmEdge
     pub enable_aot: bool,
 
+    #[structopt(long = "no-enable-aot")]
+    /// Disable AOT, even if `enable-aot` is configured
+    pub no_enable_aot: bool,
+
     #[structopt(long = "enable-ext")]
     /// Requiring wasmedge-extensions instead of wasmedge
     pub enable_ext: bool,
 
+    #[structopt(long = "no-enable-ext")]
+    /// Require wasmedge, even if `enable-ext` is configured
+    pub no_enable_ext: bool,
+
     #[structopt(long = "no-wasi")]
     /// Force compiled to target wasm32-unknown-unknown
     pub no_wasi: bool,
 
+    #[structopt(long = "wasi")]
+    /// Compile to wasm32-wasi, even if `no-wasi` is configured
+    pub wasi: bool,
+
+    #[structopt(long = "runtime-version")]
+    /// The version range of wasmedge-core, or wasmedge-extensions with
+    /// --enable-ext, that package.json depends on for the nodejs target.
+    pub runtime_version: Option<String>,
+
+    #[structopt(long = "no-runtime-dependency")]
+    /// Don't add the WasmEdge runtime package to package.json's dependencies.
+    pub no_runtime_dependency: bool,
+
     #[structopt(skip = true)]
     /// By default a *.d.ts file is generated for the generated JS file, but
     /// this flag will disable generating this TypeScript file.
@@ -102,14 +207,92 @@ pub struct BuildOptions {
     /// Create a profiling build. Enable optimizations and debug info.
     pub profiling: bool,
 
-    #[structopt(long = "out-dir", short = "d", default_value = "pkg")]
-    /// Sets the output directory with a relative path.
-    pub out_dir: String,
+    #[structopt(long = "profile")]
+    /// Build with the named cargo profile, using the matching
+    /// `[package.metadata.rustwasmc.profile.<name>]` settings.
+    pub profile: Option<String>,
+
+    #[structopt(long = "out-dir", short = "d")]
+    /// Sets the output directory with a relative path. Defaults to `pkg`.
+    pub out_dir: Option<String>,
 
     #[structopt(long = "out-name")]
     /// Sets the output file names. Defaults to package name.
     pub out_name: Option<String>,
 
+    #[structopt(long = "npm-version")]
+    /// The version written to package.json. Defaults to the crate's version.
+    pub npm_version: Option<String>,
+
+    #[structopt(long = "prerelease")]
+    /// Append a prerelease tag and the git commit hash (or a timestamp) to
+    /// the package.json version, e.g. `1.0.0-nightly.3f2a1bc`.
+    pub prerelease: Option<String>,
+
+    #[structopt(long = "bin", number_of_values = 1)]
+    /// Build only the named binary. May be given more than once. Defaults to
+    /// every binary in the crate.
+    pub bin: Vec<String>,
+
+    #[structopt(long = "example", number_of_values = 1)]
+    /// Also build the named example and copy its wasm to the output
+    /// directory. May be given more than once.
+    pub example: Vec<String>,
+
+    #[structopt(long = "features", number_of_values = 1)]
+    /// Space or comma separated list of cargo features to activate.
+    pub features: Vec<String>,
+
+    #[structopt(long = "all-features")]
+    /// Activate all available cargo features.
+    pub all_features: bool,
+
+    #[structopt(long = "no-default-features")]
+    /// Do not activate the `default` cargo feature.
+    pub no_default_features: bool,
+
+    #[structopt(long = "third-party-licenses")]
+    /// Write the licenses of every dependency to THIRD_PARTY_LICENSES, with a
+    /// JSON summary, and include them in the package.
+    pub third_party_licenses: bool,
+
+    #[structopt(long = "reproducible")]
+    /// Make the output directory byte-for-byte reproducible and write a
+    /// build-manifest.json with the hashes of every artifact.
+    pub reproducible: bool,
+
+    #[structopt(long = "oci")]
+    /// Also package the binary as an OCI image with the given reference,
+    /// written to `oci` in the output directory.
+    pub oci: Option<String>,
+
+    #[structopt(long = "oci-archive")]
+    /// Write the OCI image as `<bin>.oci.tar` instead of a directory.
+    pub oci_archive: bool,
+
+    #[structopt(long = "skip-step", number_of_values = 1)]
+    /// Skip the named build step, e.g. `run-wasm-opt` or `hook-post-opt`.
+    /// May be given more than once.
+    pub skip_step: Vec<String>,
+
+    #[structopt(long = "only-step", number_of_values = 1)]
+    /// Run only the named build steps. May be given more than once.
+    pub only_step: Vec<String>,
+
+    #[structopt(long = "watch", short = "w")]
+    /// Rebuild whenever the crate's sources, Cargo.toml or Cargo.lock change.
+    pub watch: bool,
+
+    #[structopt(long = "dry-run")]
+    /// Print what the build would do instead of running it. Same as
+    /// `--plan text`.
+    pub dry_run: bool,
+
+    #[structopt(long = "plan")]
+    /// Print what the build would do, as `text` or `json`, instead of running
+    /// it.
+    pub plan: Option<PlanFormat>,
+
     #[structopt(last = true)]
     /// List of extra options to pass to `cargo build`
     pub extra_options: Vec<String>,
@@ -120,17 +303,39 @@ impl Default for BuildOptions {
         Self {
             path: None,
             scope: None,
-            mode: InstallMode::default(),
-            target: String::from("wasmedge"),
+            mode: None,
+            target: None,
             enable_aot: false,
+            no_enable_aot: false,
             enable_ext: false,
+            no_enable_ext: false,
             no_wasi: false,
+            wasi: false,
+            runtime_version: None,
+            no_runtime_dependency: false,
             disable_dts: true,
             dev: false,
             release: false,
             profiling: false,
-            out_dir: String::from("pkg"),
+            profile: None,
+            out_dir: None,
             out_name: None,
+            npm_version: None,
+            prerelease: None,
+            bin: Vec::new(),
+            example: Vec::new(),
+            features: Vec::new(),
+            all_features: false,
+            no_default_features: false,
+            third_party_licenses: false,
+            reproducible: false,
+            oci: None,
+            oci_archive: false,
+            skip_step: Vec::new(),
+            only_step: Vec::new(),
+            watch: false,
+            dry_run: false,
+            plan: None,
             extra_options: Vec::new(),
         }
     }
@@ -138,23 +343,401 @@ impl Default for BuildOptions {
 
 type BuildStep = fn(&mut Build) -> Result<(), Error>;
 
+impl BuildOptions {
+    /// The format to print the build plan in, if `--dry-run` or `--plan` was
+    /// given.
+    pub fn plan_format(&self) -> Option<PlanFormat> {
+        match self.plan {
+            Some(format) => Some(format),
+            None if self.dry_run => Some(PlanFormat::Text),
+            None => None,
+        }
+    }
+
+    /// The configuration layer set by command line flags. Flags which were
+    /// not passed are left unset so that configuration files can fill them in,
+    /// and their negations, e.g. `--no-enable-aot`, override those files.
+    pub fn cli_config(&self) -> config::Config {
+        fn flag(on: bool, off: bool) -> Option<bool> {
+            match (on, off) {
+                (true, _) => Some(true),
+                (false, true) => Some(false),
+                (false, false) => None,
+            }
+        }
+
+        config::Config {
+            target: self.target.clone(),
+            out_dir: self.out_dir.clone(),
+            scope: self.scope.clone(),
+            enable_aot: flag(self.enable_aot, self.no_enable_aot),
+            enable_ext: flag(self.enable_ext, self.no_enable_ext),
+            no_wasi: flag(self.no_wasi, self.wasi),
+            mode: self.mode.map(|mode| mode.to_string()),
+            cargo_args: if self.extra_options.is_empty() {
+                None
+            } else {
+                Some(self.extra_options.clone())
+            },
+            faas_api: None,
+            mirrors: config::MirrorsConfig::default(),
+        }
+    }
+}
+
+/// Configures a build to run in-process, without going through the command
+/// line.
+///
+/// ```no_run
+/// # extern crate failure;
+/// # extern crate rustwasmc;
+/// # fn main() -> Result<(), failure::Error> {
+/// use rustwasmc::command::build::BuildProfile;
+/// use rustwasmc::BuildConfig;
+///
+/// let outcome = BuildConfig::new("path/to/crate")
+///     .profile(BuildProfile::Release)
+///     .target("nodejs")
+///     .features(vec!["serde".to_string()])
+///     .run()?;
+/// println!("built {} at {}", outcome.package_name, outcome.out_dir.display());
+/// # Ok(())
+/// # }
+/// ```
+///
+/// Settings which are not set are taken from `rustwasmc.toml`, the
+/// `RUSTWASMC_*` environment variables and `Cargo.toml`, as on the command
+/// line.
+pub struct BuildConfig {
+    opts: BuildOptions,
+    reporter: Option<Arc<dyn Reporter>>,
+    cache: Option<Cache>,
+}
+
+impl BuildConfig {
+    /// Configure a build of the crate at `path`.
+    pub fn new<P: Into<PathBuf>>(path: P) -> BuildConfig {
+        BuildConfig {
+            opts: BuildOptions {
+                path: Some(path.into()),
+                ..BuildOptions::default()
+            },
+            reporter: None,
+            cache: None,
+        }
+    }
+
+    /// Configure a build from command line options.
+    pub fn from_options(opts: BuildOptions) -> BuildConfig {
+        BuildConfig {
+            opts,
+            reporter: None,
+            cache: None,
+        }
+    }
+
+    /// The npm scope to use in package.json.
+    pub fn scope<S: Into<String>>(mut self, scope: S) -> Self {
+        self.opts.scope = Some(scope.into());
+        self
+    }
+
+    /// The install mode.
+    pub fn mode(mut self, mode: InstallMode) -> Self {
+        self.opts.mode = Some(mode);
+        self
+    }
+
+    /// The runtime target: `wasmedge`, `nodejs` or `deno`.
+    pub fn target<S: Into<String>>(mut self, target: S) -> Self {
+        self.opts.target = Some(target.into());
+        self
+    }
+
+    /// The build profile. Defaults to `BuildProfile::Release`.
+    pub fn profile(mut self, profile: BuildProfile) -> Self {
+        self.opts.dev = false;
+        self.opts.release = false;
+        self.opts.profiling = false;
+        self.opts.profile = None;
+        match profile {
+            BuildProfile::Dev => self.opts.dev = true,
+            BuildProfile::Release => self.opts.release = true,
+            BuildProfile::Profiling => self.opts.profiling = true,
+            BuildProfile::Custom(name) => self.opts.profile = Some(name),
+        }
+        self
+    }
+
+    /// Compile the wasm ahead of time with `wasmedgec`.
+    pub fn enable_aot(mut self, enable: bool) -> Self {
+        self.opts.enable_aot = enable;
+        self.opts.no_enable_aot = !enable;
+        self
+    }
+
+    /// Require wasmedge-extensions instead of wasmedge.
+    pub fn enable_ext(mut self, enable: bool) -> Self {
+        self.opts.enable_ext = enable;
+        self.opts.no_enable_ext = !enable;
+        self
+    }
+
+    /// Compile to `wasm32-unknown-unknown` instead of `wasm32-wasi`.
+    pub fn no_wasi(mut self, no_wasi: bool) -> Self {
+        self.opts.no_wasi = no_wasi;
+        self.opts.wasi = !no_wasi;
+        self
+    }
+
+    /// The output directory, relative to the crate. Defaults to `pkg`.
+    pub fn out_dir<S: Into<String>>(mut self, out_dir: S) -> Self {
+        self.opts.out_dir = Some(out_dir.into());
+        self
+    }
+
+    /// The output file names. Defaults to the package name.
+    pub fn out_name<S: Into<String>>(mut self, out_name: S) -> Self {
+        self.opts.out_name = Some(out_name.into());
+        self
+    }
+
+    /// The version written to package.json.
+    pub fn npm_version<S: Into<String>>(mut self, version: S) -> Self {
+        self.opts.npm_version = Some(version.into());
+        self
+    }
+
+    /// Append a prerelease tag and the git commit to the package.json
+    /// version.
+    pub fn prerelease<S: Into<String>>(mut self, tag: S) -> Self {
+        self.opts.prerelease = Some(tag.into());
+        self
+    }
+
+    /// Build only these binaries. Defaults to every binary.
+    pub fn bins(mut self, bins: Vec<String>) -> Self {
+        self.opts.bin = bins;
+        self
+    }
+
+    /// Also build these examples.
+    pub fn examples(mut self, examples: Vec<String>) -> Self {
+        self.opts.example = examples;
+        self
+    }
+
+    /// The cargo features to activate.
+    pub fn features(mut self, features: Vec<String>) -> Self {
+        self.opts.features = features;
+        self
+    }
+
+    /// Activate every cargo feature.
+    pub fn all_features(mut self, all: bool) -> Self {
+        self.opts.all_features = all;
+        self
+    }
+
+    /// Don't activate the `default` cargo feature.
+    pub fn no_default_features(mut self, no_default: bool) -> Self {
+        self.opts.no_default_features = no_default;
+        self
+    }
+
+    /// The version range of the WasmEdge runtime package in package.json.
+    pub fn runtime_version<S: Into<String>>(mut self, version: S) -> Self {
+        self.opts.runtime_version = Some(version.into());
+        self
+    }
+
+    /// Don't add the WasmEdge runtime package to package.json.
+    pub fn no_runtime_dependency(mut self, disabled: bool) -> Self {
+        self.opts.no_runtime_dependency = disabled;
+        self
+    }
+
+    /// Write the licenses of every dependency into the package.
+    pub fn third_party_licenses(mut self, enable: bool) -> Self {
+        self.opts.third_party_licenses = enable;
+        self
+    }
+
+    /// Make the output byte-for-byte reproducible.
+    pub fn reproducible(mut self, reproducible: bool) -> Self {
+        self.opts.reproducible = reproducible;
+        self
+    }
+
+    /// Also package the binary as an OCI image with the given reference,
+    /// as an image layout directory, or a tarball if `archive` is set.
+    pub fn oci<S: Into<String>>(mut self, reference: S, archive: bool) -> Self {
+        self.opts.oci = Some(reference.into());
+        self.opts.oci_archive = archive;
+        self
+    }
+
+    /// Skip these build steps.
+    pub fn skip_steps(mut self, steps: Vec<String>) -> Self {
+        self.opts.skip_step = steps;
+        self
+    }
+
+    /// Run only these build steps.
+    pub fn only_steps(mut self, steps: Vec<String>) -> Self {
+        self.opts.only_step = steps;
+        self
+    }
+
+    /// Extra arguments passed to `cargo build`.
+    pub fn cargo_args(mut self, args: Vec<String>) -> Self {
+        self.opts.extra_options = args;
+        self
+    }
+
+    /// Where the build's messages go. Defaults to the terminal.
+    pub fn reporter(mut self, reporter: Arc<dyn Reporter>) -> Self {
+        self.reporter = Some(reporter);
+        self
+    }
+
+    /// The cache the tools are installed into. Defaults to the global
+    /// rustwasmc cache.
+    pub fn cache(mut self, cache: Cache) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    /// Check the configuration and create the `Build`.
+    pub fn build(self) -> Result<Build, Error> {
+        let reporter = self
+            .reporter
+            .unwrap_or_else(|| Arc::new(TerminalReporter));
+        let _scope = reporter::scoped(reporter.clone());
+        let mut build = Build::try_from_opts(self.opts)?;
+        build.reporter = reporter;
+        if let Some(cache) = self.cache {
+            build.set_cache(cache);
+        }
+        Ok(build)
+    }
+
+    /// Run the build.
+    pub fn run(self) -> Result<BuildOutcome, Error> {
+        self.build()?.run()
+    }
+
+    /// Describe what the build would do, without running it.
+    pub fn plan(self) -> Result<BuildPlan, Error> {
+        self.build()?.plan()
+    }
+}
+
+/// What a build produced.
+#[derive(Debug)]
+pub struct BuildOutcome {
+    /// The output directory.
+    pub out_dir: PathBuf,
+    /// The package name written to package.json.
+    pub package_name: String,
+    /// The version written to package.json.
+    pub package_version: String,
+    /// The files of the npm package in the output directory: package.json
+    /// and the files it lists. Empty if package.json wasn't written.
+    pub files: Vec<PathBuf>,
+    /// The `.wasm` files built by cargo, before they were processed.
+    pub artifacts: Vec<build::WasmArtifact>,
+    /// The OCI image, if one was written.
+    pub oci_image: Option<PathBuf>,
+    /// How long the build took.
+    pub duration: Duration,
+}
+
 impl Build {
     /// Construct a build command from the given options.
     pub fn try_from_opts(build_opts: BuildOptions) -> Result<Self, Error> {
-        let crate_path = get_crate_path(build_opts.path)?;
-        let crate_data = manifest::CrateData::new(&crate_path, build_opts.out_name.clone())?;
-        let out_dir = crate_path.join(PathBuf::from(build_opts.out_dir));
-
-        let profile = match (build_opts.dev, build_opts.release, build_opts.profiling) {
-            (false, false, false) | (false, true, false) => BuildProfile::Release,
-            (true, false, false) => BuildProfile::Dev,
-            (false, false, true) => BuildProfile::Profiling,
+        let crate_path = get_crate_path(build_opts.path.clone())?;
+        let mut crate_data = manifest::CrateData::new(&crate_path, build_opts.out_name.clone())?;
+        for &(on, off, flags) in &[
+            (build_opts.enable_aot, build_opts.no_enable_aot, "--enable-aot or --no-enable-aot"),
+            (build_opts.enable_ext, build_opts.no_enable_ext, "--enable-ext or --no-enable-ext"),
+            (build_opts.no_wasi, build_opts.wasi, "--no-wasi or --wasi"),
+        ] {
+            if on && off {
+                bail_code!(ErrorCode::ConflictingOptions, "Can only supply one of the {} flags", flags);
+            }
+        }
+        let config = EffectiveConfig::resolve(build_opts.cli_config(), &crate_path)?;
+        let out_dir = crate_path.join(PathBuf::from(config.out_dir.value));
+
+        let profile = match (build_opts.dev, build_opts.release, build_opts.profiling, &build_opts.profile) {
+            (false, false, false, Some(name)) => BuildProfile::from_name(name),
+            (false, false, false, None) | (false, true, false, None) => BuildProfile::Release,
+            (true, false, false, None) => BuildProfile::Dev,
+            (false, false, true, None) => BuildProfile::Profiling,
             // Unfortunately, `structopt` doesn't expose clap's `conflicts_with`
             // functionality yet, so we have to implement it ourselves.
-            _ => bail!("Can only supply one of the --dev, --release, or --profiling flags"),
+            _ => bail_code!(ErrorCode::ConflictingOptions, "Can only supply one of the --dev, --release, --profiling, or --profile flags"),
+        };
+
+        let reproducible = if build_opts.reproducible {
+            Some(Reproducible::new(&crate_path, crate_data.workspace_root())?)
+        } else {
+            None
         };
 
-        let target = match build_opts.no_wasi {
+        crate_data.set_npm_version(
+            build_opts.npm_version.as_deref(),
+            build_opts.prerelease.as_deref(),
+            &crate_path,
+            reproducible.as_ref().map(|r| r.source_date_epoch()),
+        )?;
+
+        let mut features: Vec<String> = build_opts
+            .features
+            .iter()
+            .flat_map(|f| f.split(|c: char| c == ',' || c.is_whitespace()))
+            .filter(|f| !f.is_empty())
+            .map(String::from)
+            .collect();
+        features.extend(crate_data.mapped_features(
+            &config.target.value,
+            config.enable_aot.value,
+            config.enable_ext.value,
+            config.no_wasi.value,
+        ));
+        features.sort();
+        features.dedup();
+
+        let bin_names = crate_data.bin_names();
+        for bin in &build_opts.bin {
+            if !bin_names.contains(bin) {
+                bail_code!(ErrorCode::UnknownTarget, "no bin target named `{}` in this crate", bin);
+            }
+        }
+        let example_names = crate_data.example_names();
+        for example in &build_opts.example {
+            if !example_names.contains(example) {
+                bail_code!(ErrorCode::UnknownTarget, "no example target named `{}` in this crate", example);
+            }
+        }
+        let targets = build::TargetSelection {
+            lib: crate_data.has_cdylib(),
+            all_bins: build_opts.bin.is_empty() && !bin_names.is_empty(),
+            bins: build_opts.bin.clone(),
+            examples: build_opts.example.clone(),
+        };
+
+        let oci = match build_opts.oci {
+            Some(reference) => Some((oci::select_bin(&crate_data, &build_opts.bin)?, reference)),
+            None => None,
+        };
+        if build_opts.oci_archive && oci.is_none() {
+            bail_code!(ErrorCode::ConflictingOptions, "--oci-archive requires --oci <ref>");
+        }
+        check_step_selection(&build_opts.skip_step, &build_opts.only_step)?;
+
+        let target = match config.no_wasi.value {
             true => String::from("wasm32-unknown-unknown"),
             false => String::from("wasm32-wasi")
         };
@@ -162,20 +745,37 @@ impl Build {
         Ok(Build {
             crate_path,
             crate_data,
-            scope: build_opts.scope,
+            scope: config.scope.value,
             disable_dts: build_opts.disable_dts,
             profile,
-            mode: build_opts.mode,
-            target: target,
-            enable_aot: build_opts.enable_aot,
-            enable_ext: build_opts.enable_ext,
-            no_wasi: build_opts.no_wasi,
-            run_target: build_opts.target,
+            mode: config.mode.value,
+            target,
+            enable_aot: config.enable_aot.value,
+            enable_ext: config.enable_ext.value,
+            no_wasi: config.no_wasi.value,
+            run_target: config.target.value,
             out_dir,
             out_name: build_opts.out_name,
             bindgen: None,
             cache: cache::get_rustwasmc_cache()?,
-            extra_options: build_opts.extra_options,
+            extra_options: config.cargo_args.value,
+            reproducible,
+            mirrors: config.mirrors,
+            artifacts: Vec::new(),
+            features: build::CargoFeatures {
+                features,
+                all_features: build_opts.all_features,
+                no_default_features: build_opts.no_default_features,
+            },
+            targets,
+            oci,
+            oci_archive: build_opts.oci_archive,
+            runtime_version: build_opts.runtime_version,
+            no_runtime_dependency: build_opts.no_runtime_dependency,
+            third_party_licenses: build_opts.third_party_licenses,
+            reporter: Arc::new(TerminalReporter),
+            skip_steps: build_opts.skip_step,
+            only_steps: build_opts.only_step,
         })
     }
 
@@ -185,13 +785,42 @@ impl Build {
     }
 
     /// Execute this `Build` command.
-    pub fn run(&mut self) -> Result<(), Error> {
+    pub fn run(&mut self) -> Result<BuildOutcome, Error> {
+        self.run_skipping(&[])
+    }
+
+    /// Execute this `Build` command, leaving out the steps named in
+    /// `skipped` as well as those deselected by `--skip-step` and
+    /// `--only-step`.
+    pub fn run_skipping(&mut self, skipped: &[&str]) -> Result<BuildOutcome, Error> {
+        let _scope = reporter::scoped(self.reporter.clone());
         let process_steps = Build::get_process_steps(self.mode);
 
         let started = Instant::now();
 
-        for (_, process_step) in process_steps {
-            process_step(self)?;
+        if !self.is_selected("build-wasm") || skipped.contains(&"build-wasm") {
+            self.artifacts = self.previous_artifacts(skipped)?;
+        }
+
+        for (name, process_step) in process_steps {
+            if !self.is_selected(&name) || skipped.contains(&name.as_str()) {
+                info!("Skipping the {} step.", name);
+                logfile::write("STEP", &format!("{} skipped", name));
+                continue;
+            }
+            self.reporter.step(&name);
+            logfile::write("STEP", &format!("{} started", name));
+            let step_started = Instant::now();
+            let result = process_step(self);
+            let seconds = step_started.elapsed().as_secs_f64();
+            match &result {
+                Ok(()) => logfile::write("STEP", &format!("{} finished in {:.3}s", name, seconds)),
+                Err(e) => logfile::write(
+                    "STEP",
+                    &format!("{} failed after {:.3}s: {}", name, seconds, e),
+                ),
+            }
+            result?;
         }
 
         let duration = crate::command::utils::elapsed(started.elapsed());
@@ -201,14 +830,291 @@ impl Build {
             self.out_dir.display()
         );
 
-        PBAR.info(&format!("{} Done in {}", emoji::SPARKLE, &duration));
+        self.reporter
+            .info(&format!("{} Done in {}", emoji::SPARKLE, &duration));
 
-        PBAR.info(&format!(
+        self.reporter.info(&format!(
             "{} Your wasm pkg is ready to publish at {}.",
             emoji::PACKAGE,
             self.out_dir.display()
         ));
-        Ok(())
+        self.outcome(started.elapsed())
+    }
+
+    /// The wasm files of the previous build, used by the later steps when
+    /// the build-wasm step doesn't run. They must exist if a step that reads
+    /// them runs.
+    fn previous_artifacts(&self, skipped: &[&str]) -> Result<Vec<build::WasmArtifact>, Error> {
+        let artifacts =
+            self.targets
+                .expected_artifacts(&self.crate_data, &self.profile, &self.target);
+        let reads_artifacts = ARTIFACT_STEPS
+            .iter()
+            .any(|step| self.is_selected(step) && !skipped.contains(step));
+        if reads_artifacts {
+            if let Some(missing) = artifacts.iter().find(|a| !a.path.is_file()) {
+                bail_code!(
+                    ErrorCode::MissingStepPrerequisite,
+                    "the build-wasm step doesn't run, and {} of the previous build doesn't exist",
+                    missing.path.display()
+                );
+            }
+        }
+        Ok(artifacts)
+    }
+
+    /// Whether `--skip-step` and `--only-step` let the step `name` run.
+    fn is_selected(&self, name: &str) -> bool {
+        step_selected(name, &self.skip_steps, &self.only_steps)
+    }
+
+    /// Describe what `run` would do, without running anything.
+    pub fn plan(&self) -> Result<BuildPlan, Error> {
+        let process_steps: Vec<String> = Build::get_process_steps(self.mode)
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect();
+        let mut steps = Vec::new();
+        for name in Build::step_names() {
+            let reason = if !process_steps.contains(&name) {
+                Some("--mode force skips the checks".to_string())
+            } else if !self.is_selected(&name) {
+                if self.only_steps.is_empty() {
+                    Some("skipped by --skip-step".to_string())
+                } else {
+                    Some("not selected by --only-step".to_string())
+                }
+            } else {
+                self.plan_skip_reason(&name)
+            };
+            steps.push(PlannedStep {
+                run: reason.is_none(),
+                name,
+                reason,
+            });
+        }
+        let runs = |name: &str| steps.iter().any(|step| step.run && step.name == name);
+
+        let artifacts = self
+            .targets
+            .expected_artifacts(&self.crate_data, &self.profile, &self.target);
+        let out_wasm: Vec<PathBuf> = artifacts
+            .iter()
+            .map(|artifact| match artifact.kind {
+                CrateTargetKind::Lib => {
+                    let name = self.out_name.as_ref().unwrap_or(&artifact.name);
+                    self.out_dir.join(format!("{}_bg.wasm", name))
+                }
+                _ => self.out_dir.join(&artifact.name).with_extension("wasm"),
+            })
+            .collect();
+        let install_permitted = self.mode.install_permitted();
+        let mut tools = Vec::new();
+        let mut commands = Vec::new();
+        let hook_commands = |commands: &mut Vec<PlannedCommand>, hook: &str| {
+            if runs(&format!("hook-{}", hook)) {
+                for command in self.crate_data.hook_commands(hook) {
+                    let cmd = hooks::shell_command(&command, &self.crate_path);
+                    commands.push(PlannedCommand::new(&format!("hook-{}", hook), &cmd));
+                }
+            }
+        };
+
+        hook_commands(&mut commands, "pre-build");
+        if runs("build-wasm") {
+            commands.push(PlannedCommand::new("build-wasm", &self.cargo_build_command()));
+        }
+        if runs("install-wasm-bindgen") || runs("run-wasm-bindgen") {
+            let version = Lockfile::new(&self.crate_data)
+                .ok()
+                .and_then(|lockfile| lockfile.wasm_bindgen_version().map(String::from));
+            let on_path = which::which("wasm-bindgen").ok().filter(|path| match &version {
+                Some(version) => {
+                    install::check_version(&Tool::WasmBindgen, path, version).unwrap_or(false)
+                }
+                None => false,
+            });
+            let bindgen = match (on_path, &version) {
+                (Some(path), _) => PlannedTool {
+                    name: Tool::WasmBindgen.to_string(),
+                    version: version.clone(),
+                    source: ToolSource::Path,
+                    path: Some(path),
+                    url: None,
+                },
+                (None, Some(version)) => {
+                    let mirror = self.mirrors.wasm_bindgen.value.as_deref();
+                    match install::prebuilt_download_url(&Tool::WasmBindgen, version, mirror) {
+                        Ok(url) => PlannedTool::locate_download(
+                            &self.cache,
+                            "wasm-bindgen",
+                            version,
+                            Some(url),
+                            install_permitted,
+                        ),
+                        Err(ref e) if error::code_of(e) == Some(ErrorCode::UnsupportedPlatform) => {
+                            PlannedTool::locate_download(
+                                &self.cache,
+                                "wasm-bindgen",
+                                version,
+                                None,
+                                install_permitted,
+                            )
+                        }
+                        // The list of WasmEdge builds couldn't be fetched, so
+                        // neither the URL nor the cache entry is known.
+                        Err(_) => unlocated_tool(Some(version.clone()), install_permitted),
+                    }
+                }
+                // Without a lockfile the version isn't known until cargo has
+                // built the crate.
+                (None, None) => unlocated_tool(None, install_permitted),
+            };
+            if runs("run-wasm-bindgen") {
+                for artifact in artifacts.iter().filter(|a| a.kind == CrateTargetKind::Lib) {
+                    let cmd = bindgen::wasm_bindgen_command(
+                        &self.crate_data,
+                        &bindgen.program(),
+                        &artifact.path,
+                        &self.bindgen_options(),
+                    );
+                    commands.push(PlannedCommand::new("run-wasm-bindgen", &cmd));
+                }
+            }
+            tools.push(bindgen);
+        }
+        hook_commands(&mut commands, "post-bindgen");
+        let configured_profile = self.crate_data.configured_profile(&self.profile);
+        if let (true, Some(args)) = (runs("run-wasm-opt"), configured_profile.wasm_opt_args()) {
+            let mirror = self.mirrors.wasm_opt.value.as_deref();
+            let wasm_opt = PlannedTool::locate(
+                &self.cache,
+                "wasm-opt",
+                wasm_opt::VERSION,
+                wasm_opt::download_url(mirror),
+                install_permitted,
+            );
+            for wasm in &out_wasm {
+                let cmd = wasm_opt::wasm_opt_command(
+                    &wasm_opt.program(),
+                    wasm,
+                    &args,
+                    self.reproducible.as_ref(),
+                );
+                commands.push(PlannedCommand::new("run-wasm-opt", &cmd));
+            }
+            tools.push(wasm_opt);
+        }
+        hook_commands(&mut commands, "post-opt");
+        if let (true, Some(args)) = (runs("run-wasmedgec"), configured_profile.wasmedgec_args()) {
+            let mirror = self.mirrors.wasmedgec.value.as_deref();
+            let wasmedgec = PlannedTool::locate(
+                &self.cache,
+                "wasmedgec",
+                wasmedgec::VERSION,
+                wasmedgec::download_url(mirror),
+                install_permitted,
+            );
+            for wasm in &out_wasm {
+                let cmd = wasmedgec::wasmedgec_command(
+                    &wasmedgec.program(),
+                    wasm,
+                    &args,
+                    self.reproducible.as_ref(),
+                );
+                commands.push(PlannedCommand::new("run-wasmedgec", &cmd));
+            }
+            tools.push(wasmedgec);
+        }
+        hook_commands(&mut commands, "post-aot");
+        hook_commands(&mut commands, "pre-package");
+
+        Ok(BuildPlan {
+            crate_path: self.crate_path.clone(),
+            out_dir: self.out_dir.clone(),
+            package_name: self.crate_data.npm_name(&self.scope)?,
+            package_version: self.crate_data.npm_version(),
+            mode: self.mode.to_string(),
+            profile: self.profile.name().to_string(),
+            run_target: self.run_target.clone(),
+            wasm_target: self.target.clone(),
+            features: self.features.features.clone(),
+            steps,
+            tools,
+            commands,
+        })
+    }
+
+    /// Why the selected step `name` would do nothing with this configuration.
+    fn plan_skip_reason(&self, name: &str) -> Option<String> {
+        let configured_profile = self.crate_data.configured_profile(&self.profile);
+        let reason = match name {
+            "install-wasm-bindgen" | "run-wasm-bindgen" if !self.targets.lib => {
+                "the crate has no cdylib library"
+            }
+            "copy-wasm" if !self.targets.all_bins
+                && self.targets.bins.is_empty()
+                && self.targets.examples.is_empty() =>
+            {
+                "no binaries or examples are built"
+            }
+            "write-third-party-licenses" if !self.third_party_licenses => {
+                "--third-party-licenses is not set"
+            }
+            "run-wasm-opt" if configured_profile.wasm_opt_args().is_none() => {
+                "wasm-opt is disabled for this profile"
+            }
+            "run-wasmedgec" if !self.enable_aot => "--enable-aot is not set",
+            "run-wasmedgec" if configured_profile.wasmedgec_args().is_none() => {
+                "wasmedgec is disabled for this profile"
+            }
+            "package-oci" if self.oci.is_none() => "--oci is not set",
+            "write-build-manifest" if self.reproducible.is_none() => "--reproducible is not set",
+            hook if hook.starts_with("hook-")
+                && self
+                    .crate_data
+                    .hook_commands(hook.trim_start_matches("hook-"))
+                    .is_empty() =>
+            {
+                "no commands are configured for the hook"
+            }
+            _ => return None,
+        };
+        Some(reason.to_string())
+    }
+
+    /// Describe what the build wrote to the out dir.
+    fn outcome(&self, duration: Duration) -> Result<BuildOutcome, Error> {
+        // package.json isn't written when the step is skipped.
+        let mut files = Vec::new();
+        let package_json = self.out_dir.join("package.json");
+        if package_json.is_file() {
+            let manifest = pack::read_package_json(&self.out_dir)?;
+            files.push(package_json);
+            if let Some(listed) = manifest["files"].as_array() {
+                files.extend(
+                    listed
+                        .iter()
+                        .filter_map(|file| file.as_str())
+                        .map(|file| self.out_dir.join(file))
+                        .filter(|path| path.exists()),
+                );
+            }
+        }
+        let oci_image = self
+            .oci
+            .as_ref()
+            .map(|(bin, _)| oci::default_destination(&self.out_dir, bin, self.oci_archive))
+            .filter(|path| path.exists());
+        Ok(BuildOutcome {
+            out_dir: self.out_dir.clone(),
+            package_name: self.crate_data.npm_name(&self.scope)?,
+            package_version: self.crate_data.npm_version(),
+            files,
+            artifacts: self.artifacts.clone(),
+            oci_image,
+            duration,
+        })
     }
 
     /// Execute the "clean" command
@@ -222,15 +1128,24 @@ impl Build {
         Ok(())
     }
 
-    fn get_process_steps(mode: InstallMode) -> Vec<(&'static str, BuildStep)> {
+    /// The names of every build step, in order, as accepted by
+    /// `--skip-step` and `--only-step`.
+    pub fn step_names() -> Vec<String> {
+        Build::get_process_steps(InstallMode::Normal)
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect()
+    }
+
+    fn get_process_steps(mode: InstallMode) -> Vec<(String, BuildStep)> {
+        // `step_run_wasm_opt` is named `run-wasm-opt`
         macro_rules! steps {
             ($($name:ident),+) => {
-                {
-                let mut steps: Vec<(&'static str, BuildStep)> = Vec::new();
-                    $(steps.push((stringify!($name), Build::$name));)*
-                        steps
-                    }
-                };
+                vec![$((
+                    stringify!($name).trim_start_matches("step_").replace('_', "-"),
+                    Build::$name as BuildStep,
+                )),*]
+            };
             ($($name:ident,)*) => (steps![$($name),*])
         }
         let mut steps = Vec::new();
@@ -245,16 +1160,24 @@ impl Build {
             }
         }
         steps.extend(steps![
+            step_hook_pre_build,
             step_build_wasm,
             step_create_dir,
             step_copy_readme,
             step_copy_license,
+            step_write_third_party_licenses,
             step_copy_wasm,
             step_install_wasm_bindgen,
             step_run_wasm_bindgen,
+            step_hook_post_bindgen,
             step_run_wasm_opt,
+            step_hook_post_opt,
             step_run_wasmedgec,
+            step_hook_post_aot,
+            step_hook_pre_package,
             step_create_json,
+            step_package_oci,
+            step_write_build_manifest,
         ]);
         steps
     }
@@ -270,26 +1193,37 @@ impl Build {
     fn step_check_crate_config(&mut self) -> Result<(), Error> {
         info!("Checking crate configuration...");
 
-        // If crate type only contains [bin], which means it will only run in wasi
-        // then we don't need bindgen as well
-        if !self.crate_data.check_crate_type()? {
+        self.crate_data.check_crate_type()?;
+        self.crate_data.npm_name(&self.scope)?;
+
+        // Binaries only run in wasi, so only a cdylib needs bindgen as well
+        if self.crate_data.has_cdylib() {
             // wasmedge only support wasm-bindgen 0.2.61
             let lockfile = Lockfile::new(&self.crate_data)?;
             let bindgen_version = lockfile.require_wasm_bindgen()?;
             if bindgen_version != "0.2.61" {
-                bail!("Sorry, rustwasmc only supports wasm-bindgen 0.2.61 at this time. Please fix your Cargo.toml to wasm-bindgen = \"=0.2.61\"")
+                bail_code!(
+                    ErrorCode::UnsupportedWasmBindgen,
+                    "Sorry, rustwasmc only supports wasm-bindgen 0.2.61 at this time, but the crate uses {}",
+                    bindgen_version
+                )
             }
 
             // wasi lib is not supported from rustc 1.51.0
             let rustc_minor_version = build::check_rustc_version().unwrap();
-            if rustc_minor_version > 50 && self.target == String::from("wasm32-wasi") {
-                bail!(
-                    r#"At this time, we can only support Rust compiler version 1.50 and below in order to support WASI features in WasmEdge. Please use this command to set Rust version:
-$ rustup override set 1.50.0
-If you do not need WASI features, you can use the —no-wasi flag to override this behavior. See more here: https://github.com/WasmEdge/WasmEdge/issues/264"#
+            if rustc_minor_version > 50 && self.target == "wasm32-wasi" {
+                bail_code!(
+                    ErrorCode::RustcTooNewForWasi,
+                    "Rust 1.{} is not supported for WASI libraries; use Rust 1.50 or below, or build with --no-wasi",
+                    rustc_minor_version
                 );
             }
         }
+        if let BuildProfile::Custom(_) = self.profile {
+            let wasi_lib = self.crate_data.has_cdylib() && self.target == "wasm32-wasi";
+            self.profile
+                .check_toolchain(build::check_rustc_version()?, wasi_lib)?;
+        }
         info!("Crate is correctly configured.");
         Ok(())
     }
@@ -301,18 +1235,50 @@ If you do not need WASI features, you can use the —no-wasi flag to override th
         Ok(())
     }
 
+    fn run_hook(&mut self, hook: &str) -> Result<(), Error> {
+        let commands = self.crate_data.hook_commands(hook);
+        if commands.is_empty() {
+            return Ok(());
+        }
+        info!("Running the {} hook...", hook);
+        let hook_env = HookEnv {
+            crate_path: &self.crate_path,
+            out_dir: &self.out_dir,
+            profile: self.profile.name(),
+            run_target: &self.run_target,
+            wasm_target: &self.target,
+            artifacts: self.artifacts.iter().map(|a| a.path.as_path()).collect(),
+        };
+        hooks::run(hook, &commands, &hook_env)
+    }
+
+    fn step_hook_pre_build(&mut self) -> Result<(), Error> {
+        self.run_hook("pre-build")
+    }
+
+    fn step_hook_post_bindgen(&mut self) -> Result<(), Error> {
+        self.run_hook("post-bindgen")
+    }
+
+    fn step_hook_post_opt(&mut self) -> Result<(), Error> {
+        self.run_hook("post-opt")
+    }
+
+    fn step_hook_post_aot(&mut self) -> Result<(), Error> {
+        self.run_hook("post-aot")
+    }
+
+    fn step_hook_pre_package(&mut self) -> Result<(), Error> {
+        self.run_hook("pre-package")
+    }
+
     fn step_build_wasm(&mut self) -> Result<(), Error> {
         info!("Building wasm...");
-        build::cargo_build_wasm(&self.crate_path, self.profile, &self.target, &self.extra_options)?;
+        self.artifacts = build::cargo_build_wasm(&self.crate_data, self.cargo_build_command())?;
 
-        info!(
-            "wasm built at {:#?}.",
-            &self
-                .crate_path
-                .join("target")
-                .join(&self.target)
-                .join("release")
-        );
+        for artifact in &self.artifacts {
+            info!("wasm built at {:#?}.", &artifact.path);
+        }
         Ok(())
     }
 
@@ -324,11 +1290,22 @@ If you do not need WASI features, you can use the —no-wasi flag to override th
     }
 
     fn step_create_json(&mut self) -> Result<(), Error> {
-        self.crate_data.write_package_json(
-            &self.out_dir,
-            &self.scope,
-            self.disable_dts,
-        )?;
+        let options = manifest::PackageJsonOptions {
+            scope: &self.scope,
+            disable_dts: self.disable_dts,
+            reproducible: self.reproducible.is_some(),
+            third_party_licenses: self.third_party_licenses,
+            artifacts: &self.artifacts,
+            features: &self.crate_data.active_features(&self.features),
+            runtime_dependency: self.crate_data.runtime_dependency(
+                &self.run_target,
+                self.enable_ext,
+                self.enable_aot,
+                self.runtime_version.as_deref(),
+                self.no_runtime_dependency,
+            ),
+        };
+        self.crate_data.write_package_json(&self.out_dir, &options)?;
         info!(
             "Wrote a package.json at {:#?}.",
             &self.out_dir.join("package.json")
@@ -338,7 +1315,7 @@ If you do not need WASI features, you can use the —no-wasi flag to override th
 
     fn step_copy_readme(&mut self) -> Result<(), Error> {
         info!("Copying readme from crate...");
-        readme::copy_from_crate(&self.crate_path, &self.out_dir)?;
+        readme::copy_from_crate(&self.crate_data, &self.crate_path, &self.out_dir)?;
         info!("Copied readme from crate to {:#?}.", &self.out_dir);
         Ok(())
     }
@@ -350,9 +1327,33 @@ If you do not need WASI features, you can use the —no-wasi flag to override th
         Ok(())
     }
 
+    fn builds_lib(&self) -> bool {
+        self.artifacts
+            .iter()
+            .any(|artifact| artifact.kind == CrateTargetKind::Lib)
+    }
+
+    fn step_write_third_party_licenses(&mut self) -> Result<(), Error> {
+        if !self.third_party_licenses {
+            return Ok(());
+        }
+        info!("Collecting the licenses of dependencies...");
+        license::write_third_party_licenses(
+            &self.crate_data,
+            &self.target,
+            &self.features,
+            &self.out_dir,
+        )?;
+        info!(
+            "Wrote the licenses of dependencies to {:#?}.",
+            &self.out_dir.join(license::THIRD_PARTY_LICENSES)
+        );
+        Ok(())
+    }
+
     fn step_install_wasm_bindgen(&mut self) -> Result<(), failure::Error> {
         // bindgen is only needed in cdylib target
-        if self.crate_data.check_crate_type()? {
+        if !self.builds_lib() {
             return Ok(());
         }
         info!("Identifying wasm-bindgen dependency...");
@@ -362,8 +1363,9 @@ If you do not need WASI features, you can use the —no-wasi flag to override th
         let bindgen = install::download_prebuilt_or_cargo_install(
             Tool::WasmBindgen,
             &self.cache,
-            &bindgen_version,
-            self.mode.install_permitted()
+            bindgen_version,
+            self.mode.install_permitted(),
+            self.mirrors.wasm_bindgen.value.as_deref(),
         )?;
         self.bindgen = Some(bindgen);
         info!("Installing wasm-bindgen-cli was successful.");
@@ -371,47 +1373,61 @@ If you do not need WASI features, you can use the —no-wasi flag to override th
     }
 
     fn step_copy_wasm(&mut self) -> Result<(), Error> {
-        // Only needed in bin target
-        if !self.crate_data.check_crate_type()? {
-            return Ok(());
+        // The library is written by wasm-bindgen, binaries and examples are
+        // copied as standalone WASI commands
+        for artifact in &self.artifacts {
+            if artifact.kind == CrateTargetKind::Lib {
+                continue;
+            }
+            let out_wasm_path = self.out_dir.join(&artifact.name).with_extension("wasm");
+            fs::copy(&artifact.path, &out_wasm_path)?;
         }
 
-        let release_or_debug = match self.profile {
-            BuildProfile::Release | BuildProfile::Profiling => "release",
-            BuildProfile::Dev => "debug",
-        };
+        Ok(())
+    }
 
-        for c in self.crate_data.crate_name().iter() {
-            let wasm_path = self.crate_data
-                .target_directory()
-                .join(&self.target)
-                .join(release_or_debug)
-                .join(c.as_str())
-                .with_extension("wasm");
-            let out_wasm_path = self.out_dir.join(c.as_str()).with_extension("wasm");
-            fs::copy(&wasm_path, &out_wasm_path)?;
-        }
+    fn cargo_build_command(&self) -> Command {
+        build::cargo_build_command(
+            &self.crate_path,
+            &self.profile,
+            &self.target,
+            &self.targets,
+            &self.features,
+            &self.extra_options,
+            self.reproducible.as_ref(),
+        )
+    }
 
-        Ok(())
+    fn bindgen_options(&self) -> bindgen::BindgenOptions<'_> {
+        bindgen::BindgenOptions {
+            out_dir: &self.out_dir,
+            out_name: &self.out_name,
+            disable_dts: self.disable_dts,
+            profile: &self.profile,
+            run_target: &self.run_target,
+            enable_aot: self.enable_aot,
+            enable_ext: self.enable_ext,
+        }
     }
 
     fn step_run_wasm_bindgen(&mut self) -> Result<(), Error> {
         // bindgen is only needed in cdylib target
-        if self.crate_data.check_crate_type()? {
+        if !self.builds_lib() {
             return Ok(());
         }
+        let bindgen = match &self.bindgen {
+            Some(bindgen) => bindgen,
+            None => bail_code!(
+                ErrorCode::MissingStepPrerequisite,
+                "the run-wasm-bindgen step needs the install-wasm-bindgen step, which didn't run"
+            ),
+        };
         info!("Building the wasm bindings...");
         bindgen::wasm_bindgen_build(
             &self.crate_data,
-            self.bindgen.as_ref().unwrap(),
-            &self.out_dir,
-            &self.out_name,
-            self.disable_dts,
-            self.profile,
-            &self.target,
-            &self.run_target,
-            self.enable_aot,
-            self.enable_ext,
+            bindgen,
+            &self.artifacts,
+            &self.bindgen_options(),
         )?;
         info!("wasm bindings were built at {:#?}.", &self.out_dir);
         Ok(())
@@ -420,7 +1436,7 @@ If you do not need WASI features, you can use the —no-wasi flag to override th
     fn step_run_wasm_opt(&mut self) -> Result<(), Error> {
         let args = match self
             .crate_data
-            .configured_profile(self.profile)
+            .configured_profile(&self.profile)
             .wasm_opt_args()
         {
             Some(args) => args,
@@ -432,10 +1448,11 @@ If you do not need WASI features, you can use the —no-wasi flag to override th
             &self.out_dir,
             &args,
             self.mode.install_permitted(),
+            self.reproducible.as_ref(),
+            self.mirrors.wasm_opt.value.as_deref(),
         ).map_err(|e| {
-            format_err!(
-                "{}\nTo disable `wasm-opt`, add `wasm-opt = false` to your package metadata in your `Cargo.toml`.", e
-            )
+            e.context(CodedError::new(ErrorCode::WasmOptFailed, "wasm-opt failed to optimize the wasm file"))
+                .into()
         })
     }
 
@@ -443,12 +1460,308 @@ If you do not need WASI features, you can use the —no-wasi flag to override th
         if !self.enable_aot {
             return Ok(())
         }
+        let args = match self
+            .crate_data
+            .configured_profile(&self.profile)
+            .wasmedgec_args()
+        {
+            Some(args) => args,
+            None => {
+                info!("wasmedgec is disabled for this profile");
+                return Ok(());
+            }
+        };
         wasmedgec::run(
             &self.cache,
             &self.out_dir,
+            &args,
             self.mode.install_permitted(),
+            self.reproducible.as_ref(),
+            self.mirrors.wasmedgec.value.as_deref(),
         )?;
 
         Ok(())
     }
+
+    fn step_package_oci(&mut self) -> Result<(), Error> {
+        let (bin, reference) = match &self.oci {
+            Some(oci) => oci,
+            None => return Ok(()),
+        };
+        info!("Packaging {} as an OCI image...", bin);
+        let mtime = self
+            .reproducible
+            .as_ref()
+            .map_or(0, |reproducible| reproducible.source_date_epoch());
+        let image = OciImage::for_bin(&self.crate_data, &self.out_dir, bin, reference, mtime)?;
+        let dest = oci::default_destination(&self.out_dir, bin, self.oci_archive);
+        if self.oci_archive {
+            image.write_archive(&dest)?;
+        } else {
+            image.write_layout(&dest)?;
+        }
+        info!("Wrote the OCI image {} to {:#?}.", reference, dest);
+        Ok(())
+    }
+
+    fn step_write_build_manifest(&mut self) -> Result<(), Error> {
+        let reproducible = match &self.reproducible {
+            Some(reproducible) => reproducible,
+            None => return Ok(()),
+        };
+        info!("Writing build manifest...");
+        reproducible::write_build_manifest(&self.out_dir, reproducible)?;
+        info!(
+            "Wrote a build manifest at {:#?}.",
+            &self.out_dir.join(reproducible::BUILD_MANIFEST)
+        );
+        Ok(())
+    }
+}
+
+/// Whether `--skip-step` (`skip_steps`) and `--only-step` (`only_steps`) let
+/// the step `name` run.
+fn step_selected(name: &str, skip_steps: &[String], only_steps: &[String]) -> bool {
+    let name = name.to_string();
+    if only_steps.is_empty() {
+        !skip_steps.contains(&name)
+    } else {
+        only_steps.contains(&name)
+    }
+}
+
+/// Check that `--skip-step` and `--only-step` aren't combined, name existing
+/// steps, and don't deselect a prerequisite of a selected step.
+fn check_step_selection(skip_steps: &[String], only_steps: &[String]) -> Result<(), Error> {
+    if !skip_steps.is_empty() && !only_steps.is_empty() {
+        bail_code!(ErrorCode::ConflictingOptions, "Can only supply one of the --skip-step or --only-step flags");
+    }
+    let step_names = Build::step_names();
+    for step in skip_steps.iter().chain(only_steps) {
+        if !step_names.contains(step) {
+            bail_code!(
+                ErrorCode::UnknownStep,
+                "no build step named `{}`; the steps are: {}",
+                step,
+                step_names.join(", ")
+            );
+        }
+    }
+    for (step, prerequisite) in STEP_PREREQUISITES {
+        if step_selected(step, skip_steps, only_steps)
+            && !step_selected(prerequisite, skip_steps, only_steps)
+        {
+            bail_code!(
+                ErrorCode::MissingStepPrerequisite,
+                "the {} step needs the {} step, which is deselected",
+                step,
+                prerequisite
+            );
+        }
+    }
+    Ok(())
+}
+
+/// A wasm-bindgen that would be downloaded from a URL that isn't known yet.
+fn unlocated_tool(version: Option<String>, install_permitted: bool) -> PlannedTool {
+    PlannedTool {
+        name: Tool::WasmBindgen.to_string(),
+        version,
+        source: if install_permitted {
+            ToolSource::Download
+        } else {
+            ToolSource::NotInstalled
+        },
+        path: None,
+        url: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use error::code_of;
+
+    #[test]
+    fn checks_the_toolchain_of_custom_profiles() {
+        let custom = BuildProfile::from_name("faas");
+        assert!(custom.check_toolchain(57, false).is_ok());
+        assert!(custom.check_toolchain(70, false).is_ok());
+
+        let e = custom.check_toolchain(56, false).unwrap_err();
+        assert_eq!(code_of(&e), Some(ErrorCode::ProfileNeedsNewerRust));
+        assert!(e.to_string().contains("Rust 1.57 or higher, but you have Rust 1.56"));
+
+        // No toolchain can build a WASI library with a custom profile.
+        for &version in &[50, 57, 70] {
+            let e = custom.check_toolchain(version, true).unwrap_err();
+            assert_eq!(code_of(&e), Some(ErrorCode::ProfileNeedsNewerRust));
+        }
+
+        for name in &["dev", "release", "profiling"] {
+            let profile = BuildProfile::from_name(name);
+            assert!(profile.check_toolchain(40, true).is_ok());
+        }
+    }
+
+    fn steps(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    fn selection_error(skip: &[&str], only: &[&str]) -> Option<ErrorCode> {
+        match check_step_selection(&steps(skip), &steps(only)) {
+            Ok(()) => None,
+            Err(e) => code_of(&e),
+        }
+    }
+
+    #[test]
+    fn selects_steps() {
+        assert!(step_selected("build-wasm", &[], &[]));
+        assert!(!step_selected("build-wasm", &steps(&["build-wasm"]), &[]));
+        assert!(step_selected("copy-wasm", &steps(&["build-wasm"]), &[]));
+        assert!(step_selected("build-wasm", &[], &steps(&["build-wasm"])));
+        assert!(!step_selected("copy-wasm", &[], &steps(&["build-wasm"])));
+    }
+
+    #[test]
+    fn checks_the_step_selection() {
+        assert_eq!(selection_error(&[], &[]), None);
+        assert_eq!(selection_error(&["run-wasm-opt", "hook-post-opt"], &[]), None);
+        assert_eq!(selection_error(&[], &["build-wasm", "copy-wasm"]), None);
+        assert_eq!(
+            selection_error(&["run-wasm-opt"], &["build-wasm"]),
+            Some(ErrorCode::ConflictingOptions)
+        );
+        assert_eq!(
+            selection_error(&["optimize"], &[]),
+            Some(ErrorCode::UnknownStep)
+        );
+        assert_eq!(
+            selection_error(&[], &["build-wasm", "optimize"]),
+            Some(ErrorCode::UnknownStep)
+        );
+    }
+
+    #[test]
+    fn keeps_the_prerequisites_of_selected_steps() {
+        for &(step, prerequisite) in STEP_PREREQUISITES {
+            assert_eq!(selection_error(&[prerequisite, step], &[]), None);
+            assert_eq!(selection_error(&[], &[prerequisite, step]), None);
+            assert_eq!(selection_error(&[], &[prerequisite]), None);
+            assert_eq!(
+                selection_error(&[prerequisite], &[]),
+                Some(ErrorCode::MissingStepPrerequisite)
+            );
+            assert_eq!(
+                selection_error(&[], &[step]),
+                Some(ErrorCode::MissingStepPrerequisite)
+            );
+        }
+    }
+
+    #[derive(Default)]
+    struct RecordingReporter {
+        messages: std::sync::Mutex<Vec<String>>,
+    }
+
+    impl RecordingReporter {
+        fn record(&self, message: String) {
+            self.messages.lock().unwrap().push(message);
+        }
+
+        fn messages(&self) -> Vec<String> {
+            self.messages.lock().unwrap().clone()
+        }
+    }
+
+    impl Reporter for RecordingReporter {
+        fn info(&self, message: &str) {
+            self.record(format!("info: {}", message));
+        }
+
+        fn warn(&self, message: &str) {
+            self.record(format!("warn: {}", message));
+        }
+
+        fn error(&self, message: &str) {
+            self.record(format!("error: {}", message));
+        }
+
+        fn step(&self, name: &str) {
+            self.record(format!("step: {}", name));
+        }
+    }
+
+    #[test]
+    fn reports_to_a_custom_reporter() {
+        let reporter = Arc::new(RecordingReporter::default());
+        {
+            let _scope = reporter::scoped(reporter.clone());
+            ::PBAR.info("an info");
+            ::PBAR.warn("a warning");
+            ::PBAR.error("an error");
+        }
+        // Outside the scope, messages go to the terminal again.
+        ::PBAR.info("not recorded");
+        assert_eq!(
+            reporter.messages(),
+            vec!["info: an info", "warn: a warning", "error: an error"]
+        );
+    }
+
+    #[test]
+    fn lists_the_outcome_of_a_build() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(
+            dir.path().join("Cargo.toml"),
+            "[package]\nname = \"demo\"\nversion = \"0.1.0\"\nlicense = \"MIT\"\n",
+        )
+        .unwrap();
+        fs::create_dir(dir.path().join("src")).unwrap();
+        fs::write(dir.path().join("src/main.rs"), "").unwrap();
+
+        let reporter = Arc::new(RecordingReporter::default());
+        let mut build = BuildConfig::new(dir.path())
+            .mode(InstallMode::Force)
+            .only_steps(steps(&["create-dir", "copy-readme", "copy-license", "copy-wasm", "create-json"]))
+            .reporter(reporter.clone())
+            .build()
+            .unwrap();
+
+        // Stand in for the wasm cargo would have built.
+        let artifacts = build
+            .targets
+            .expected_artifacts(&build.crate_data, &build.profile, &build.target);
+        assert_eq!(artifacts.len(), 1);
+        fs::create_dir_all(artifacts[0].path.parent().unwrap()).unwrap();
+        fs::write(&artifacts[0].path, b"\0asm").unwrap();
+
+        let outcome = build.run().unwrap();
+        assert_eq!(outcome.out_dir, dir.path().join("pkg"));
+        assert_eq!(outcome.package_name, "demo");
+        assert_eq!(outcome.package_version, "0.1.0");
+        assert_eq!(outcome.artifacts.len(), 1);
+        assert_eq!(outcome.artifacts[0].name, "demo");
+        assert_eq!(outcome.artifacts[0].kind, CrateTargetKind::Bin);
+        assert_eq!(outcome.artifacts[0].path, artifacts[0].path);
+        assert_eq!(
+            outcome.files,
+            vec![dir.path().join("pkg/package.json"), dir.path().join("pkg/demo.wasm")]
+        );
+        assert_eq!(fs::read(dir.path().join("pkg/demo.wasm")).unwrap(), b"\0asm");
+        assert_eq!(outcome.oci_image, None);
+
+        let messages = reporter.messages();
+        let steps: Vec<&str> = messages
+            .iter()
+            .filter_map(|m| m.strip_prefix("step: "))
+            .collect();
+        assert_eq!(steps, vec!["create-dir", "copy-readme", "copy-license", "copy-wasm", "create-json"]);
+        assert!(messages.contains(&"warn: origin crate has no README".to_string()));
+        assert!(messages.contains(
+            &"info: No LICENSE file was found for `MIT`; Please add a LICENSE-MIT file to your project directory"
+                .to_string()
+        ));
+    }
 }
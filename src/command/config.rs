@@ -0,0 +1,30 @@
+//! Implementation of the `rustwasmc config` command.
+
+use command::utils::get_crate_path;
+use config::{Config, EffectiveConfig};
+use failure::Error;
+use std::path::PathBuf;
+
+/// Subcommands for inspecting rustwasmc's configuration.
+#[derive(Debug, StructOpt)]
+pub enum ConfigCommand {
+    /// Print the effective configuration and where each value comes from
+    #[structopt(name = "show")]
+    Show {
+        /// The path to the Rust crate. If not set, searches up the path from the current directory.
+        #[structopt(parse(from_os_str))]
+        path: Option<PathBuf>,
+    },
+}
+
+/// Run a `rustwasmc config` subcommand.
+pub fn run(command: ConfigCommand) -> Result<(), Error> {
+    match command {
+        ConfigCommand::Show { path } => {
+            let crate_path = get_crate_path(path)?;
+            let config = EffectiveConfig::resolve(Config::default(), &crate_path)?;
+            print!("{}", config.display());
+            Ok(())
+        }
+    }
+}
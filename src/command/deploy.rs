@@ -0,0 +1,160 @@
+//! Implementation of the `rustwasmc deploy` command.
+
+use command::utils::get_crate_path;
+use config::{Config, EffectiveConfig};
+use emoji;
+use faas::{self, DeployState, FaasClient};
+use failure::Error;
+use log::info;
+use pack;
+use std::fs;
+use std::path::{Path, PathBuf};
+use PBAR;
+
+/// Everything required to configure the `rustwasmc deploy` command.
+#[derive(Debug, StructOpt)]
+pub struct DeployOptions {
+    /// The path to the Rust crate. If not set, searches up the path from the current directory.
+    #[structopt(parse(from_os_str))]
+    pub path: Option<PathBuf>,
+
+    #[structopt(long = "out-dir", short = "d")]
+    /// The directory containing the built package, relative to the crate. [default: pkg]
+    pub out_dir: Option<String>,
+
+    #[structopt(long = "api")]
+    /// The base URL of the FaaS API. Defaults to `faas-api` in rustwasmc.toml,
+    /// or the Second State FaaS.
+    pub api: Option<String>,
+
+    #[structopt(long = "wasm")]
+    /// The wasm file in the out dir to deploy. Defaults to the only
+    /// `*_bg.wasm` file.
+    pub wasm: Option<String>,
+
+    #[structopt(long = "description")]
+    /// The description of a new function. Defaults to the package's.
+    pub description: Option<String>,
+
+    #[structopt(long = "delete")]
+    /// Delete the deployed function instead of deploying.
+    pub delete: bool,
+}
+
+/// Deploy the wasm file of a built crate to the FaaS, or delete it.
+pub fn run(opts: DeployOptions) -> Result<(), Error> {
+    let crate_path = get_crate_path(opts.path)?;
+    let cli = Config {
+        out_dir: opts.out_dir,
+        faas_api: opts.api,
+        ..Config::default()
+    };
+    let config = EffectiveConfig::resolve(cli, &crate_path)?;
+    let out_dir = crate_path.join(config.out_dir.value);
+    // The API is part of the key of a deployment, so `http://host/` and
+    // `http://host` must be the same.
+    let api = config.faas_api.value.trim_end_matches('/').to_string();
+    let wasm_name = match opts.wasm {
+        Some(wasm) => wasm,
+        None => find_function_wasm(&out_dir)?,
+    };
+
+    let client = FaasClient::new(&api);
+    let mut state = DeployState::load(&crate_path)?;
+
+    if opts.delete {
+        info!("Deleting {} from {}...", wasm_name, api);
+        let deployment = faas::undeploy(&client, &mut state, &api, &wasm_name)?;
+        state.save(&crate_path)?;
+        PBAR.info(&format!(
+            "{}Deleted function {} ({}) from {}",
+            emoji::SPARKLE,
+            deployment.wasm_id,
+            wasm_name,
+            api
+        ));
+        return Ok(());
+    }
+
+    let wasm_path = out_dir.join(&wasm_name);
+    if !wasm_path.is_file() {
+        bail!(
+            "There is no {} in {}. Run `rustwasmc build` first.",
+            wasm_name,
+            out_dir.display()
+        );
+    }
+    // wasmedgec writes the AOT file next to the wasm file.
+    let aot_path = wasm_path.with_extension("so");
+    let aot_path = Some(aot_path.as_path()).filter(|path| path.is_file());
+    let description = match opts.description {
+        Some(description) => description,
+        None => pack::read_package_json(&out_dir)
+            .ok()
+            .and_then(|manifest| manifest["description"].as_str().map(String::from))
+            .unwrap_or_else(|| wasm_name.clone()),
+    };
+
+    let previous = state.find(&api, &wasm_name).cloned();
+    info!("Deploying {} to {}...", wasm_path.display(), api);
+    let result = faas::deploy(&client, &mut state, &api, &wasm_path, aot_path, &description);
+    state.save(&crate_path)?;
+    let deployment = result?;
+
+    let with_aot = if deployment.aot_sha256.is_some() {
+        " with its AOT file"
+    } else {
+        ""
+    };
+    let id = deployment.wasm_id;
+    let message = match &previous {
+        Some(previous)
+            if previous.wasm_sha256 == deployment.wasm_sha256
+                && previous.aot_sha256 == deployment.aot_sha256 =>
+        {
+            format!("Function {} is up to date with {}{}", id, wasm_name, with_aot)
+        }
+        Some(_) => format!("Updated function {} from {}{}", id, wasm_name, with_aot),
+        None => format!("Deployed function {} from {}{}", id, wasm_name, with_aot),
+    };
+    PBAR.info(&format!("{}{}", emoji::PACKAGE, message));
+    PBAR.info(&format!(
+        "Call it with: POST {}/api/run/{}/<function>",
+        api, deployment.wasm_id
+    ));
+    if previous.is_none() {
+        PBAR.warn(&format!(
+            "The admin key of the function is stored in {}, which was added to .gitignore. \
+             Keep it out of version control.",
+            faas::STATE_FILE
+        ));
+    }
+    Ok(())
+}
+
+/// The wasm-bindgen output in `out_dir`, which holds the functions.
+fn find_function_wasm(out_dir: &Path) -> Result<String, Error> {
+    let mut found = Vec::new();
+    if let Ok(entries) = fs::read_dir(out_dir) {
+        for entry in entries.filter_map(|e| e.ok()) {
+            if let Some(name) = entry.file_name().to_str() {
+                if name.ends_with("_bg.wasm") {
+                    found.push(name.to_string());
+                }
+            }
+        }
+    }
+    found.sort();
+    match found.len() {
+        0 => bail!(
+            "There is no function wasm file in {}. Run `rustwasmc build` first, or pass --wasm.",
+            out_dir.display()
+        ),
+        1 => Ok(found.remove(0)),
+        _ => bail!(
+            "There are several wasm files in {}: {}. Pick one with --wasm.",
+            out_dir.display(),
+            found.join(", ")
+        ),
+    }
+}
@@ -0,0 +1,24 @@
+//! Implementation of the `rustwasmc explain` command.
+
+use error::ErrorCode;
+use failure::Error;
+
+/// Everything required to configure the `rustwasmc explain` command.
+#[derive(Debug, StructOpt)]
+pub struct ExplainOptions {
+    /// The error code, e.g. `RWC0103`. If not set, lists every error code.
+    pub code: Option<ErrorCode>,
+}
+
+/// Print the explanation of an error code, or list the error codes.
+pub fn run(opts: ExplainOptions) -> Result<(), Error> {
+    match opts.code {
+        Some(code) => println!("{}: {}\n\n{}", code, code.summary(), code.explanation()),
+        None => {
+            for code in ErrorCode::ALL {
+                println!("{}  {}", code, code.summary());
+            }
+        }
+    }
+    Ok(())
+}
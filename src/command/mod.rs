@@ -2,14 +2,32 @@
 #![allow(clippy::redundant_closure)]
 
 pub mod build;
+pub mod config;
+pub mod deploy;
+pub mod explain;
+pub mod oci;
+pub mod pack;
+pub mod publish;
+pub mod serve;
 pub mod utils;
 
-use self::build::{Build, BuildOptions};
+use self::build::{Build, BuildConfig, BuildOptions};
+use self::config::ConfigCommand;
+use self::deploy::DeployOptions;
+use self::explain::ExplainOptions;
+use self::oci::OciOptions;
+use self::pack::PackOptions;
+use self::publish::PublishOptions;
+use self::serve::ServeOptions;
+use watch;
+use error::ErrorCode;
 use failure::Error;
 use log::info;
 use std::result;
 
 /// The various kinds of commands that `rustwasmc` can execute.
+// Only one `Command` is parsed per run, so its size doesn't matter.
+#[allow(clippy::large_enum_variant)]
 #[derive(Debug, StructOpt)]
 pub enum Command {
     /// 🏗️  build your npm package!
@@ -19,6 +37,34 @@ pub enum Command {
     /// clean the pkg and target dir
     #[structopt(name = "clean")]
     Clean{},
+
+    /// ⚙️  inspect the rustwasmc configuration
+    #[structopt(name = "config")]
+    Config(ConfigCommand),
+
+    /// 🍱  create a tarball of your npm package
+    #[structopt(name = "pack")]
+    Pack(PackOptions),
+
+    /// 🎆  pack up your npm package and publish!
+    #[structopt(name = "publish")]
+    Publish(PublishOptions),
+
+    /// 🐳  package a WASI binary as an OCI image
+    #[structopt(name = "oci")]
+    Oci(OciOptions),
+
+    /// 🚀  deploy the package's functions to the Second State FaaS
+    #[structopt(name = "deploy")]
+    Deploy(DeployOptions),
+
+    /// 🌐  serve the functions of a `--target nodejs` package over HTTP, like the Second State FaaS
+    #[structopt(name = "serve")]
+    Serve(ServeOptions),
+
+    /// ❓  explain an error code
+    #[structopt(name = "explain")]
+    Explain(ExplainOptions),
 }
 
 /// Run a command with the given logger!
@@ -30,14 +76,42 @@ pub fn run_rustwasmc(command: Command) -> result::Result<(), Error> {
             info!("Running build command...");
             // Check rust toolchain first
             let o = std::process::Command::new("rustc").arg("--version").output();
-            match o {
-                Err(_e) => bail!("Please follow instructions to install Rust language tools first. https://www.secondstate.io/articles/rustwasmc/ Thank you."),
-                _ => {}
+            if o.is_err() {
+                bail_code!(ErrorCode::RustNotInstalled, "Please follow instructions to install Rust language tools first. https://www.secondstate.io/articles/rustwasmc/ Thank you.");
+            }
+            match build_opts.plan_format() {
+                Some(format) => BuildConfig::from_options(build_opts).plan()?.print(format),
+                None if build_opts.watch => watch::run(build_opts),
+                None => BuildConfig::from_options(build_opts).run().map(|_| ()),
             }
-            Build::try_from_opts(build_opts).and_then(|mut b| b.run())
         }
         Command::Clean{} => {
             Build::clean()
         }
+        Command::Config(config_command) => {
+            info!("Running config command...");
+            self::config::run(config_command)
+        }
+        Command::Pack(pack_opts) => {
+            info!("Running pack command...");
+            self::pack::run(pack_opts)
+        }
+        Command::Publish(publish_opts) => {
+            info!("Running publish command...");
+            self::publish::run(publish_opts)
+        }
+        Command::Oci(oci_opts) => {
+            info!("Running oci command...");
+            self::oci::run(oci_opts)
+        }
+        Command::Deploy(deploy_opts) => {
+            info!("Running deploy command...");
+            self::deploy::run(deploy_opts)
+        }
+        Command::Serve(serve_opts) => {
+            info!("Running serve command...");
+            self::serve::run(serve_opts)
+        }
+        Command::Explain(explain_opts) => self::explain::run(explain_opts),
     }
 }
@@ -0,0 +1,72 @@
+//! Implementation of the `rustwasmc oci` command.
+
+use command::utils::get_crate_path;
+use config::{Config, EffectiveConfig};
+use emoji;
+use failure::Error;
+use log::info;
+use manifest::CrateData;
+use oci::{self, OciImage};
+use std::path::PathBuf;
+use PBAR;
+
+/// Everything required to configure the `rustwasmc oci` command.
+#[derive(Debug, StructOpt)]
+pub struct OciOptions {
+    /// The image reference, e.g. `registry.example.com/app:1.0`.
+    pub reference: String,
+
+    /// The path to the Rust crate. If not set, searches up the path from the current directory.
+    #[structopt(parse(from_os_str))]
+    pub path: Option<PathBuf>,
+
+    #[structopt(long = "out-dir", short = "d")]
+    /// The directory containing the built package, relative to the crate. [default: pkg]
+    pub out_dir: Option<String>,
+
+    #[structopt(long = "bin")]
+    /// The binary to package. Required when the crate has several.
+    pub bin: Option<String>,
+
+    #[structopt(long = "archive")]
+    /// Write a tarball of the image layout instead of a directory.
+    pub archive: bool,
+
+    #[structopt(long = "output", short = "o", parse(from_os_str))]
+    /// Where to write the image. Defaults to `oci` or `<bin>.oci.tar` in the
+    /// out dir.
+    pub output: Option<PathBuf>,
+}
+
+/// Package a binary built by `rustwasmc build` as an OCI image.
+pub fn run(opts: OciOptions) -> Result<(), Error> {
+    let crate_path = get_crate_path(opts.path)?;
+    let cli = Config {
+        out_dir: opts.out_dir,
+        ..Config::default()
+    };
+    let config = EffectiveConfig::resolve(cli, &crate_path)?;
+    let out_dir = crate_path.join(config.out_dir.value);
+    let crate_data = CrateData::new(&crate_path, None)?;
+
+    let bin = oci::select_bin(&crate_data, &opts.bin.into_iter().collect::<Vec<_>>())?;
+    let image = OciImage::for_bin(&crate_data, &out_dir, &bin, &opts.reference, 0)?;
+    let archive = opts.archive;
+    let dest = opts
+        .output
+        .unwrap_or_else(|| oci::default_destination(&out_dir, &bin, archive));
+
+    info!("Writing OCI image for {} to {}...", bin, dest.display());
+    if opts.archive {
+        image.write_archive(&dest)?;
+    } else {
+        image.write_layout(&dest)?;
+    }
+    PBAR.info(&format!(
+        "{}Wrote OCI image {} to {}",
+        emoji::PACKAGE,
+        opts.reference,
+        dest.display()
+    ));
+    Ok(())
+}
@@ -0,0 +1,58 @@
+//! Implementation of the `rustwasmc pack` command.
+
+use command::utils::get_crate_path;
+use config::{Config, EffectiveConfig};
+use emoji;
+use failure::Error;
+use log::info;
+use pack;
+use std::path::PathBuf;
+use PBAR;
+
+/// Everything required to configure the `rustwasmc pack` command.
+#[derive(Debug, StructOpt)]
+pub struct PackOptions {
+    /// The path to the Rust crate. If not set, searches up the path from the current directory.
+    #[structopt(parse(from_os_str))]
+    pub path: Option<PathBuf>,
+
+    #[structopt(long = "out-dir", short = "d")]
+    /// The directory containing the built package, relative to the crate. [default: pkg]
+    pub out_dir: Option<String>,
+
+    #[structopt(long = "pack-destination", parse(from_os_str))]
+    /// Where to write the tarball. Defaults to the out dir.
+    pub pack_destination: Option<PathBuf>,
+}
+
+/// Pack the out dir of a built crate into an npm tarball.
+pub fn run(opts: PackOptions) -> Result<(), Error> {
+    let crate_path = get_crate_path(opts.path)?;
+    let cli = Config {
+        out_dir: opts.out_dir,
+        ..Config::default()
+    };
+    let config = EffectiveConfig::resolve(cli, &crate_path)?;
+    let out_dir = crate_path.join(config.out_dir.value);
+    let destination = opts.pack_destination.unwrap_or_else(|| out_dir.clone());
+
+    info!("Packing {}...", out_dir.display());
+    let tarball = pack::pack(&out_dir, &destination)?;
+
+    for (file, size) in &tarball.files {
+        PBAR.info(&format!("{:>9}  {}", size, file));
+    }
+    PBAR.info(&format!("name:          {}", tarball.name));
+    PBAR.info(&format!("version:       {}", tarball.version));
+    PBAR.info(&format!("package size:  {}", tarball.size));
+    PBAR.info(&format!("unpacked size: {}", tarball.unpacked_size()));
+    PBAR.info(&format!("shasum:        {}", tarball.shasum));
+    PBAR.info(&format!("integrity:     {}", tarball.integrity));
+    PBAR.info(&format!("total files:   {}", tarball.files.len()));
+    PBAR.info(&format!(
+        "{}Packed {}",
+        emoji::PACKAGE,
+        tarball.path.display()
+    ));
+    Ok(())
+}
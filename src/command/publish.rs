@@ -0,0 +1,113 @@
+//! Implementation of the `rustwasmc publish` command.
+
+use command::utils::get_crate_path;
+use config::{Config, EffectiveConfig};
+use emoji;
+use failure::Error;
+use log::info;
+use npm::{self, Access, Npmrc};
+use pack;
+use semver::Version;
+use std::env;
+use std::path::PathBuf;
+use PBAR;
+
+/// Everything required to configure the `rustwasmc publish` command.
+#[derive(Debug, StructOpt)]
+pub struct PublishOptions {
+    /// The path to the Rust crate. If not set, searches up the path from the current directory.
+    #[structopt(parse(from_os_str))]
+    pub path: Option<PathBuf>,
+
+    #[structopt(long = "out-dir", short = "d")]
+    /// The directory containing the built package, relative to the crate. [default: pkg]
+    pub out_dir: Option<String>,
+
+    #[structopt(long = "registry")]
+    /// The registry to publish to. Defaults to the `registry` set in `.npmrc`,
+    /// or https://registry.npmjs.org/.
+    pub registry: Option<String>,
+
+    #[structopt(long = "tag", default_value = "latest")]
+    /// The dist-tag to publish the version under.
+    pub tag: String,
+
+    #[structopt(long = "access")]
+    /// Whether the package is `public` or `restricted`.
+    pub access: Option<Access>,
+
+    #[structopt(long = "scope", short = "s")]
+    /// The npm scope the package was built with.
+    pub scope: Option<String>,
+}
+
+/// Pack the out dir of a built crate and publish it to an npm registry.
+pub fn run(opts: PublishOptions) -> Result<(), Error> {
+    let crate_path = get_crate_path(opts.path)?;
+    let cli = Config {
+        out_dir: opts.out_dir,
+        scope: opts.scope,
+        ..Config::default()
+    };
+    let config = EffectiveConfig::resolve(cli, &crate_path)?;
+    let out_dir = crate_path.join(config.out_dir.value);
+
+    if Version::parse(&opts.tag).is_ok() {
+        bail!("Tag name must not be a valid version: {}", opts.tag);
+    }
+
+    let tarball = pack::pack(&out_dir, &out_dir)?;
+    let manifest = pack::read_package_json(&out_dir)?;
+    let package_scope = npm::package_scope(&tarball.name);
+
+    if let Some(scope) = &config.scope.value {
+        if package_scope != Some(scope.as_str()) {
+            bail!(
+                "The package `{}` is not in the scope `@{}`. Run `rustwasmc build --scope {}` first.",
+                tarball.name,
+                scope,
+                scope
+            );
+        }
+    }
+    if opts.access == Some(Access::Restricted) && package_scope.is_none() {
+        bail!("Can't restrict access to the unscoped package `{}`.", tarball.name);
+    }
+
+    let npmrc = Npmrc::load(&crate_path)?;
+    let registry = opts
+        .registry
+        .or_else(|| npmrc.registry(package_scope).map(String::from))
+        .or_else(|| env::var("NPM_CONFIG_REGISTRY").ok())
+        .unwrap_or_else(|| npm::DEFAULT_REGISTRY.to_string());
+    let token = env::var("NPM_TOKEN")
+        .or_else(|_| env::var("NODE_AUTH_TOKEN"))
+        .ok()
+        .or_else(|| npmrc.auth_token(&registry).map(String::from))
+        .filter(|token| !token.is_empty());
+    if token.is_none() {
+        PBAR.warn(&format!(
+            "No auth token found for {}. Set NPM_TOKEN or add one to .npmrc.",
+            registry
+        ));
+    }
+
+    info!("Publishing {} to {}...", tarball.path.display(), registry);
+    npm::publish(
+        &registry,
+        token.as_deref(),
+        &manifest,
+        &tarball,
+        &opts.tag,
+        opts.access,
+    )?;
+    PBAR.info(&format!(
+        "{}Published {}@{} to {} with tag `{}`",
+        emoji::PACKAGE,
+        tarball.name,
+        tarball.version,
+        registry,
+        opts.tag
+    ));
+    Ok(())
+}
@@ -0,0 +1,69 @@
+//! Implementation of the `rustwasmc serve` command.
+
+use command::utils::get_crate_path;
+use config::{Config, EffectiveConfig};
+use emoji;
+use failure::Error;
+use log::info;
+use serve::{self, Functions};
+use std::path::PathBuf;
+use std::time::Duration;
+use PBAR;
+
+/// Everything required to configure the `rustwasmc serve` command.
+///
+/// Only packages built with `--target nodejs` can be served; WASI binaries
+/// can't.
+#[derive(Debug, StructOpt)]
+pub struct ServeOptions {
+    /// The path to the Rust crate. If not set, searches up the path from the current directory.
+    #[structopt(parse(from_os_str))]
+    pub path: Option<PathBuf>,
+
+    #[structopt(long = "out-dir", short = "d")]
+    /// The directory containing the built package, relative to the crate. [default: pkg]
+    pub out_dir: Option<String>,
+
+    #[structopt(long = "host", default_value = "127.0.0.1")]
+    /// The address to listen on.
+    pub host: String,
+
+    #[structopt(long = "port", short = "p", default_value = "8081")]
+    /// The port to listen on.
+    pub port: u16,
+
+    #[structopt(long = "node", default_value = "node", parse(from_os_str))]
+    /// The Node.js binary that runs the functions.
+    pub node: PathBuf,
+
+    #[structopt(long = "timeout", default_value = "60")]
+    /// How many seconds a function may run before it is stopped.
+    pub timeout: u64,
+}
+
+/// Serve the functions of the package in the out dir over HTTP.
+pub fn run(opts: ServeOptions) -> Result<(), Error> {
+    let crate_path = get_crate_path(opts.path)?;
+    let cli = Config {
+        out_dir: opts.out_dir,
+        ..Config::default()
+    };
+    let config = EffectiveConfig::resolve(cli, &crate_path)?;
+    let out_dir = crate_path.join(config.out_dir.value);
+
+    info!("Loading the functions of {}...", out_dir.display());
+    let mut functions = Functions::load(&out_dir, &opts.node)?;
+    functions.set_timeout(Duration::from_secs(opts.timeout));
+    let address = format!("{}:{}", opts.host, opts.port);
+
+    PBAR.info(&format!(
+        "{}Serving {} function(s) on http://{}",
+        emoji::RUNNER,
+        functions.names().len(),
+        address
+    ));
+    for name in functions.names() {
+        PBAR.info(&format!("  POST http://{}/api/run/0/{}", address, name));
+    }
+    serve::serve(functions, &address)
+}
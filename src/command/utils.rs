@@ -37,7 +37,7 @@ fn find_manifest_from_cwd() -> Result<PathBuf, failure::Error> {
 
 /// Construct our `pkg` directory in the crate.
 pub fn create_pkg_dir(out_dir: &Path) -> Result<(), failure::Error> {
-    fs::create_dir_all(&out_dir)?;
+    fs::create_dir_all(out_dir)?;
     fs::write(out_dir.join(".gitignore"), "*")?;
     Ok(())
 }
@@ -52,7 +52,7 @@ pub fn find_pkg_directory(path: &Path) -> Option<PathBuf> {
     WalkDir::new(path)
         .into_iter()
         .filter_map(|x| x.ok().map(|e| e.into_path()))
-        .find(|x| is_pkg_directory(&x))
+        .find(|x| is_pkg_directory(x))
 }
 
 fn is_pkg_directory(path: &Path) -> bool {
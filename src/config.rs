@@ -0,0 +1,506 @@
+//! Reading `rustwasmc.toml` configuration files.
+//!
+//! Build settings are resolved from several layers. The first layer that sets
+//! a value wins:
+//!
+//! 1. command line flags
+//! 2. `RUSTWASMC_*` environment variables
+//! 3. `rustwasmc.toml` in the crate directory
+//! 4. `rustwasmc/config.toml` in the user's config directory
+//! 5. built-in defaults
+
+use faas;
+use failure::{Error, ResultExt};
+use install::InstallMode;
+use std::env;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use toml;
+
+/// The name of the project-level configuration file.
+pub const PROJECT_CONFIG_FILE: &str = "rustwasmc.toml";
+
+/// One layer of configuration. Every value is optional so that layers can be
+/// merged.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Config {
+    /// The runtime target, e.g. `wasmedge`, `nodejs` or `deno`.
+    pub target: Option<String>,
+    /// The output directory, relative to the crate.
+    pub out_dir: Option<String>,
+    /// The npm scope to use in package.json.
+    pub scope: Option<String>,
+    /// Whether to compile AOT binaries with `wasmedgec`.
+    pub enable_aot: Option<bool>,
+    /// Whether to require `wasmedge-extensions`.
+    pub enable_ext: Option<bool>,
+    /// Whether to compile to `wasm32-unknown-unknown`.
+    pub no_wasi: Option<bool>,
+    /// The install mode: `no-install`, `normal` or `force`.
+    pub mode: Option<String>,
+    /// Extra arguments passed to `cargo build`.
+    pub cargo_args: Option<Vec<String>>,
+    /// The base URL of the FaaS API `rustwasmc deploy` uploads to.
+    pub faas_api: Option<String>,
+    /// Download mirrors for the tools rustwasmc installs.
+    #[serde(default)]
+    pub mirrors: MirrorsConfig,
+}
+
+/// The `[mirrors]` table. Each value is a base URL which replaces
+/// `https://github.com` in the tool's download URL.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct MirrorsConfig {
+    /// Mirror for `wasm-bindgen` downloads.
+    pub wasm_bindgen: Option<String>,
+    /// Mirror for `wasm-opt` downloads.
+    pub wasm_opt: Option<String>,
+    /// Mirror for `wasmedgec` downloads.
+    pub wasmedgec: Option<String>,
+}
+
+/// Where a configuration value came from.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ConfigSource {
+    /// A command line flag.
+    Cli,
+    /// A `RUSTWASMC_*` environment variable.
+    Env,
+    /// The project's `rustwasmc.toml`.
+    Project(PathBuf),
+    /// The user's `config.toml`.
+    User(PathBuf),
+    /// rustwasmc's built-in default.
+    Default,
+}
+
+impl fmt::Display for ConfigSource {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigSource::Cli => write!(f, "command line"),
+            ConfigSource::Env => write!(f, "environment"),
+            ConfigSource::Project(path) => write!(f, "{}", path.display()),
+            ConfigSource::User(path) => write!(f, "{}", path.display()),
+            ConfigSource::Default => write!(f, "default"),
+        }
+    }
+}
+
+/// A resolved configuration value and the layer it came from.
+#[derive(Clone, Debug)]
+pub struct Setting<T> {
+    /// The resolved value.
+    pub value: T,
+    /// The layer which set the value.
+    pub source: ConfigSource,
+}
+
+/// Resolved download mirrors.
+#[derive(Clone, Debug)]
+pub struct Mirrors {
+    /// Mirror for `wasm-bindgen` downloads.
+    pub wasm_bindgen: Setting<Option<String>>,
+    /// Mirror for `wasm-opt` downloads.
+    pub wasm_opt: Setting<Option<String>>,
+    /// Mirror for `wasmedgec` downloads.
+    pub wasmedgec: Setting<Option<String>>,
+}
+
+/// The effective configuration after merging every layer.
+#[derive(Clone, Debug)]
+pub struct EffectiveConfig {
+    /// The runtime target.
+    pub target: Setting<String>,
+    /// The output directory, relative to the crate.
+    pub out_dir: Setting<String>,
+    /// The npm scope to use in package.json.
+    pub scope: Setting<Option<String>>,
+    /// Whether to compile AOT binaries with `wasmedgec`.
+    pub enable_aot: Setting<bool>,
+    /// Whether to require `wasmedge-extensions`.
+    pub enable_ext: Setting<bool>,
+    /// Whether to compile to `wasm32-unknown-unknown`.
+    pub no_wasi: Setting<bool>,
+    /// The install mode.
+    pub mode: Setting<InstallMode>,
+    /// Extra arguments passed to `cargo build`.
+    pub cargo_args: Setting<Vec<String>>,
+    /// The base URL of the FaaS API `rustwasmc deploy` uploads to.
+    pub faas_api: Setting<String>,
+    /// Download mirrors for the tools rustwasmc installs.
+    pub mirrors: Mirrors,
+}
+
+impl Config {
+    /// Read a configuration file.
+    pub fn from_file(path: &Path) -> Result<Config, Error> {
+        let contents = fs::read_to_string(path)
+            .with_context(|_| format!("failed to read: {}", path.display()))?;
+        let config = toml::from_str(&contents)
+            .with_context(|_| format!("failed to parse: {}", path.display()))?;
+        Ok(config)
+    }
+
+    /// Read the configuration from `RUSTWASMC_*` environment variables.
+    pub fn from_env() -> Result<Config, Error> {
+        Config::from_vars(|name| env::var(name).ok())
+    }
+
+    /// Read the configuration from the `RUSTWASMC_*` variables `var` looks
+    /// up. Empty variables are ignored.
+    pub fn from_vars<F>(var: F) -> Result<Config, Error>
+    where
+        F: Fn(&str) -> Option<String>,
+    {
+        let var = |name: &str| var(name).filter(|v| !v.is_empty());
+        let flag = |name: &str| -> Result<Option<bool>, Error> {
+            match var(name) {
+                None => Ok(None),
+                Some(v) => match v.as_str() {
+                    "1" | "true" | "yes" => Ok(Some(true)),
+                    "0" | "false" | "no" => Ok(Some(false)),
+                    _ => bail!("{} must be `true` or `false`, found `{}`", name, v),
+                },
+            }
+        };
+
+        Ok(Config {
+            target: var("RUSTWASMC_TARGET"),
+            out_dir: var("RUSTWASMC_OUT_DIR"),
+            scope: var("RUSTWASMC_SCOPE"),
+            enable_aot: flag("RUSTWASMC_ENABLE_AOT")?,
+            enable_ext: flag("RUSTWASMC_ENABLE_EXT")?,
+            no_wasi: flag("RUSTWASMC_NO_WASI")?,
+            mode: var("RUSTWASMC_MODE"),
+            cargo_args: var("RUSTWASMC_CARGO_ARGS")
+                .map(|args| args.split_whitespace().map(String::from).collect()),
+            faas_api: var("RUSTWASMC_FAAS_API"),
+            mirrors: MirrorsConfig {
+                wasm_bindgen: var("RUSTWASMC_WASM_BINDGEN_MIRROR"),
+                wasm_opt: var("RUSTWASMC_WASM_OPT_MIRROR"),
+                wasmedgec: var("RUSTWASMC_WASMEDGEC_MIRROR"),
+            },
+        })
+    }
+}
+
+/// The path of the project configuration file for the crate at `crate_path`.
+pub fn project_config_path(crate_path: &Path) -> PathBuf {
+    crate_path.join(PROJECT_CONFIG_FILE)
+}
+
+/// The path of the user configuration file, if the platform has a config
+/// directory.
+pub fn user_config_path() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("rustwasmc").join("config.toml"))
+}
+
+/// The configuration layers for the crate at `crate_path`, in order of
+/// precedence: `cli`, `env`, the project configuration file and the user
+/// configuration file at `user_path`. Files that don't exist are skipped.
+pub fn layers(
+    cli: Config,
+    env: Config,
+    crate_path: &Path,
+    user_path: Option<&Path>,
+) -> Result<Vec<(ConfigSource, Config)>, Error> {
+    let mut layers = vec![(ConfigSource::Cli, cli), (ConfigSource::Env, env)];
+
+    let project_path = project_config_path(crate_path);
+    if project_path.is_file() {
+        let project = Config::from_file(&project_path)?;
+        layers.push((ConfigSource::Project(project_path), project));
+    }
+    if let Some(user_path) = user_path {
+        if user_path.is_file() {
+            let user = Config::from_file(user_path)?;
+            layers.push((ConfigSource::User(user_path.to_path_buf()), user));
+        }
+    }
+    Ok(layers)
+}
+
+impl EffectiveConfig {
+    /// Merge the command line layer `cli` with the environment, the project
+    /// and user configuration files, and the defaults.
+    pub fn resolve(cli: Config, crate_path: &Path) -> Result<EffectiveConfig, Error> {
+        let layers = layers(
+            cli,
+            Config::from_env()?,
+            crate_path,
+            user_config_path().as_deref(),
+        )?;
+        EffectiveConfig::from_layers(&layers)
+    }
+
+    /// Merge `layers`, ordered from the highest precedence to the lowest,
+    /// with the defaults.
+    pub fn from_layers(layers: &[(ConfigSource, Config)]) -> Result<EffectiveConfig, Error> {
+        macro_rules! pick {
+            ($default:expr, $( $path:ident ).+) => {
+                layers
+                    .iter()
+                    .filter_map(|(source, layer)| {
+                        layer $( .$path )+ .clone().map(|value| Setting {
+                            value,
+                            source: source.clone(),
+                        })
+                    })
+                    .next()
+                    .unwrap_or(Setting {
+                        value: $default,
+                        source: ConfigSource::Default,
+                    })
+            };
+        }
+        macro_rules! pick_optional {
+            ($( $path:ident ).+) => {{
+                let setting = pick!(String::new(), $( $path ).+);
+                Setting {
+                    value: if setting.source == ConfigSource::Default {
+                        None
+                    } else {
+                        Some(setting.value)
+                    },
+                    source: setting.source,
+                }
+            }};
+        }
+
+        let mode = pick!("normal".to_string(), mode);
+        let mode = Setting {
+            value: mode
+                .value
+                .parse::<InstallMode>()
+                .with_context(|_| format!("invalid `mode` in {}", mode.source))?,
+            source: mode.source,
+        };
+
+        Ok(EffectiveConfig {
+            target: pick!("wasmedge".to_string(), target),
+            out_dir: pick!("pkg".to_string(), out_dir),
+            scope: pick_optional!(scope),
+            enable_aot: pick!(false, enable_aot),
+            enable_ext: pick!(false, enable_ext),
+            no_wasi: pick!(false, no_wasi),
+            mode,
+            cargo_args: pick!(Vec::new(), cargo_args),
+            faas_api: pick!(faas::DEFAULT_API.to_string(), faas_api),
+            mirrors: Mirrors {
+                wasm_bindgen: pick_optional!(mirrors.wasm_bindgen),
+                wasm_opt: pick_optional!(mirrors.wasm_opt),
+                wasmedgec: pick_optional!(mirrors.wasmedgec),
+            },
+        })
+    }
+
+    /// Render the effective configuration as TOML, annotating each value with
+    /// the layer it came from.
+    pub fn display(&self) -> String {
+        fn line<T: fmt::Debug>(key: &str, setting: &Setting<T>) -> String {
+            format!("{} = {:?}  # {}\n", key, setting.value, setting.source)
+        }
+        fn optional(key: &str, setting: &Setting<Option<String>>) -> String {
+            match &setting.value {
+                Some(value) => format!("{} = {:?}  # {}\n", key, value, setting.source),
+                None => format!("# {} is not set\n", key),
+            }
+        }
+
+        let mut out = String::new();
+        out.push_str(&line("target", &self.target));
+        out.push_str(&line("out-dir", &self.out_dir));
+        out.push_str(&optional("scope", &self.scope));
+        out.push_str(&line("enable-aot", &self.enable_aot));
+        out.push_str(&line("enable-ext", &self.enable_ext));
+        out.push_str(&line("no-wasi", &self.no_wasi));
+        out.push_str(&format!(
+            "mode = \"{}\"  # {}\n",
+            self.mode.value, self.mode.source
+        ));
+        out.push_str(&line("cargo-args", &self.cargo_args));
+        out.push_str(&line("faas-api", &self.faas_api));
+        out.push_str("\n[mirrors]\n");
+        out.push_str(&optional("wasm-bindgen", &self.mirrors.wasm_bindgen));
+        out.push_str(&optional("wasm-opt", &self.mirrors.wasm_opt));
+        out.push_str(&optional("wasmedgec", &self.mirrors.wasmedgec));
+        out
+    }
+}
+
+/// Replace the `https://github.com` prefix of `url` with `mirror`, if one is
+/// configured.
+pub fn apply_mirror(url: &str, mirror: Option<&str>) -> String {
+    match (mirror, url.strip_prefix("https://github.com")) {
+        (Some(mirror), Some(path)) => format!("{}{}", mirror.trim_end_matches('/'), path),
+        _ => url.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use tempfile;
+
+    fn config(toml: &str) -> Config {
+        toml::from_str(toml).unwrap()
+    }
+
+    fn from_vars(vars: &[(&str, &str)]) -> Result<Config, Error> {
+        let vars: HashMap<String, String> = vars
+            .iter()
+            .map(|&(name, value)| (name.to_string(), value.to_string()))
+            .collect();
+        Config::from_vars(|name| vars.get(name).cloned())
+    }
+
+    #[test]
+    fn the_first_layer_that_sets_a_value_wins() {
+        let project = PathBuf::from("/crate/rustwasmc.toml");
+        let user = PathBuf::from("/home/config.toml");
+        let layers = vec![
+            (ConfigSource::Cli, config("target = \"nodejs\"")),
+            (
+                ConfigSource::Env,
+                config("target = \"deno\"\nout-dir = \"env-pkg\""),
+            ),
+            (
+                ConfigSource::Project(project.clone()),
+                config("out-dir = \"project-pkg\"\nenable-aot = true\nmode = \"no-install\""),
+            ),
+            (
+                ConfigSource::User(user.clone()),
+                config("enable-aot = false\nscope = \"me\"\n[mirrors]\nwasm-opt = \"https://m\""),
+            ),
+        ];
+        let config = EffectiveConfig::from_layers(&layers).unwrap();
+
+        assert_eq!(config.target.value, "nodejs");
+        assert_eq!(config.target.source, ConfigSource::Cli);
+        assert_eq!(config.out_dir.value, "env-pkg");
+        assert_eq!(config.out_dir.source, ConfigSource::Env);
+        assert!(config.enable_aot.value);
+        assert_eq!(config.enable_aot.source, ConfigSource::Project(project.clone()));
+        assert_eq!(config.mode.value, InstallMode::Noinstall);
+        assert_eq!(config.mode.source, ConfigSource::Project(project));
+        assert_eq!(config.scope.value.as_deref(), Some("me"));
+        assert_eq!(config.scope.source, ConfigSource::User(user.clone()));
+        assert_eq!(config.mirrors.wasm_opt.value.as_deref(), Some("https://m"));
+        assert_eq!(config.mirrors.wasm_opt.source, ConfigSource::User(user));
+
+        assert!(!config.no_wasi.value);
+        assert_eq!(config.no_wasi.source, ConfigSource::Default);
+        assert_eq!(config.faas_api.value, faas::DEFAULT_API);
+        assert_eq!(config.mirrors.wasmedgec.value, None);
+        assert_eq!(config.mirrors.wasmedgec.source, ConfigSource::Default);
+    }
+
+    #[test]
+    fn defaults_apply_without_layers() {
+        let config = EffectiveConfig::from_layers(&[]).unwrap();
+        assert_eq!(config.target.value, "wasmedge");
+        assert_eq!(config.out_dir.value, "pkg");
+        assert_eq!(config.mode.value, InstallMode::Normal);
+        assert!(config.cargo_args.value.is_empty());
+        assert_eq!(config.scope.value, None);
+        assert_eq!(config.scope.source, ConfigSource::Default);
+    }
+
+    #[test]
+    fn rejects_an_invalid_mode() {
+        let layers = vec![(ConfigSource::Env, config("mode = \"sometimes\""))];
+        let e = EffectiveConfig::from_layers(&layers).unwrap_err();
+        assert!(e.to_string().contains("invalid `mode` in environment"));
+    }
+
+    #[test]
+    fn reads_the_project_and_user_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let user_path = dir.path().join("user.toml");
+        fs::write(dir.path().join(PROJECT_CONFIG_FILE), "target = \"nodejs\"").unwrap();
+        fs::write(&user_path, "target = \"deno\"\nno-wasi = true").unwrap();
+
+        let found = layers(Config::default(), Config::default(), dir.path(), Some(&user_path))
+            .unwrap();
+        let sources: Vec<&ConfigSource> = found.iter().map(|(source, _)| source).collect();
+        assert_eq!(
+            sources,
+            vec![
+                &ConfigSource::Cli,
+                &ConfigSource::Env,
+                &ConfigSource::Project(dir.path().join(PROJECT_CONFIG_FILE)),
+                &ConfigSource::User(user_path.clone()),
+            ]
+        );
+        let config = EffectiveConfig::from_layers(&found).unwrap();
+        assert_eq!(config.target.value, "nodejs");
+        assert!(config.no_wasi.value);
+
+        // Missing files are skipped.
+        let empty = tempfile::tempdir().unwrap();
+        let missing = empty.path().join("config.toml");
+        let found = layers(Config::default(), Config::default(), empty.path(), Some(&missing))
+            .unwrap();
+        assert_eq!(found.len(), 2);
+
+        fs::write(dir.path().join(PROJECT_CONFIG_FILE), "unknown = 1").unwrap();
+        assert!(layers(Config::default(), Config::default(), dir.path(), None).is_err());
+    }
+
+    #[test]
+    fn reads_environment_variables() {
+        let config = from_vars(&[
+            ("RUSTWASMC_TARGET", "nodejs"),
+            ("RUSTWASMC_OUT_DIR", ""),
+            ("RUSTWASMC_ENABLE_AOT", "yes"),
+            ("RUSTWASMC_ENABLE_EXT", "0"),
+            ("RUSTWASMC_NO_WASI", "true"),
+            ("RUSTWASMC_CARGO_ARGS", " --locked  --offline "),
+            ("RUSTWASMC_WASM_BINDGEN_MIRROR", "https://mirror"),
+        ])
+        .unwrap();
+        assert_eq!(config.target.as_deref(), Some("nodejs"));
+        assert_eq!(config.out_dir, None);
+        assert_eq!(config.enable_aot, Some(true));
+        assert_eq!(config.enable_ext, Some(false));
+        assert_eq!(config.no_wasi, Some(true));
+        assert_eq!(config.scope, None);
+        assert_eq!(
+            config.cargo_args,
+            Some(vec!["--locked".to_string(), "--offline".to_string()])
+        );
+        assert_eq!(config.mirrors.wasm_bindgen.as_deref(), Some("https://mirror"));
+
+        for &value in &["1", "true", "yes"] {
+            let config = from_vars(&[("RUSTWASMC_NO_WASI", value)]).unwrap();
+            assert_eq!(config.no_wasi, Some(true));
+        }
+        for &value in &["0", "false", "no"] {
+            let config = from_vars(&[("RUSTWASMC_NO_WASI", value)]).unwrap();
+            assert_eq!(config.no_wasi, Some(false));
+        }
+        let e = from_vars(&[("RUSTWASMC_ENABLE_AOT", "on")]).unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "RUSTWASMC_ENABLE_AOT must be `true` or `false`, found `on`"
+        );
+    }
+
+    #[test]
+    fn applies_mirrors_to_github_urls() {
+        let url = "https://github.com/WasmEdge/WasmEdge/releases/download/x.tar.gz";
+        assert_eq!(apply_mirror(url, None), url);
+        assert_eq!(
+            apply_mirror(url, Some("https://mirror.example/gh/")),
+            "https://mirror.example/gh/WasmEdge/WasmEdge/releases/download/x.tar.gz"
+        );
+        assert_eq!(
+            apply_mirror("https://example.com/x.tar.gz", Some("https://mirror.example")),
+            "https://example.com/x.tar.gz"
+        );
+    }
+}
@@ -0,0 +1,451 @@
+//! Errors with stable codes.
+//!
+//! Errors that users can act on carry an `ErrorCode` such as `RWC0103`, so
+//! tools wrapping rustwasmc can tell them apart without matching on messages.
+//! `rustwasmc explain <code>` prints the long-form explanation of a code.
+
+use failure::{self, Context, Fail};
+use serde_json::{json, Value};
+use std::fmt;
+use std::str::FromStr;
+
+/// Return early with a `CodedError`, like `bail!` with an `ErrorCode` in
+/// front of the message.
+#[macro_export]
+macro_rules! bail_code {
+    ($code:expr, $($arg:tt)*) => {
+        return Err($crate::error::CodedError::new($code, format!($($arg)*)).into())
+    };
+}
+
+macro_rules! error_codes {
+    ($($(#[$doc:meta])* $name:ident = $code:expr, $summary:expr, $explanation:expr;)*) => {
+        /// The stable code of an error.
+        #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+        pub enum ErrorCode {
+            $($(#[$doc])* $name,)*
+        }
+
+        impl ErrorCode {
+            /// Every error code, in order.
+            pub const ALL: &'static [ErrorCode] = &[$(ErrorCode::$name,)*];
+
+            /// The code, e.g. `RWC0103`.
+            pub fn code(self) -> &'static str {
+                match self {
+                    $(ErrorCode::$name => $code,)*
+                }
+            }
+
+            /// A one-line description of the error.
+            pub fn summary(self) -> &'static str {
+                match self {
+                    $(ErrorCode::$name => $summary,)*
+                }
+            }
+
+            /// What causes the error and how to fix it.
+            pub fn explanation(self) -> &'static str {
+                match self {
+                    $(ErrorCode::$name => $explanation,)*
+                }
+            }
+        }
+    };
+}
+
+error_codes! {
+    /// `rustc` could not be run.
+    RustNotInstalled = "RWC0101", "Rust is not installed",
+"rustwasmc runs `rustc` and `cargo` to build your crate, but `rustc` could not
+be run or its version could not be determined.
+
+Install the Rust language tools by following the instructions at
+https://www.secondstate.io/articles/rustwasmc/ and make sure `rustc` is on
+your PATH.";
+
+    /// `rustc` is older than 1.30.
+    RustcTooOld = "RWC0102", "the Rust compiler is too old",
+"rustwasmc needs Rust 1.30.0 or higher. Update your toolchain with:
+
+    $ rustup update";
+
+    /// `rustc` is too new to build a WASI library.
+    RustcTooNewForWasi = "RWC0103", "the Rust compiler is too new for WASI libraries",
+"Rust 1.51 and later produce WASI libraries that WasmEdge cannot run. At this
+time, we can only support Rust compiler version 1.50 and below in order to
+support WASI features in WasmEdge. Set the Rust version for your crate with:
+
+    $ rustup override set 1.50.0
+
+If you do not need WASI features, use the --no-wasi flag to build for
+wasm32-unknown-unknown instead. See more here:
+https://github.com/WasmEdge/WasmEdge/issues/264";
+
+    /// A custom profile was selected with a toolchain that can't build it.
+    ProfileNeedsNewerRust = "RWC0105", "custom profiles need Rust 1.57 or higher",
+"`--profile <name>` builds with the cargo profile of that name, which cargo
+supports from Rust 1.57. WASI libraries, however, can only be built with Rust
+1.50 or below, so they can't use custom profiles.
+
+Build with --dev, --release or --profiling instead, or, if you don't need WASI
+features, use --no-wasi and a newer toolchain:
+
+    $ rustup update";
+
+    /// The wasm32 target is not installed.
+    Wasm32TargetMissing = "RWC0104", "the wasm32 target is not installed",
+"The Rust standard library for the wasm target (`wasm32-wasi`, or
+`wasm32-unknown-unknown` with --no-wasi) was not found in rustc's sysroot.
+
+If you use rustup, add it with:
+
+    $ rustup target add wasm32-wasi
+
+Otherwise, install the target's standard library for your toolchain
+manually.";
+
+    /// The crate depends on a wasm-bindgen other than 0.2.61.
+    UnsupportedWasmBindgen = "RWC0201", "unsupported wasm-bindgen version",
+"WasmEdge only supports wasm-bindgen 0.2.61 at this time. Pin it in your
+Cargo.toml:
+
+    [dependencies]
+    wasm-bindgen = \"=0.2.61\"
+
+then run `cargo update -p wasm-bindgen` so that Cargo.lock uses it.";
+
+    /// The crate does not depend on wasm-bindgen.
+    MissingWasmBindgen = "RWC0202", "wasm-bindgen is not a dependency",
+"A cdylib crate is packaged with wasm-bindgen, so it must depend on it.
+Add it to your Cargo.toml:
+
+    [dependencies]
+    wasm-bindgen = \"=0.2.61\"";
+
+    /// There is no Cargo.lock.
+    MissingLockfile = "RWC0203", "Cargo.lock was not found",
+"rustwasmc reads the wasm-bindgen version from Cargo.lock, in the crate or
+workspace root. Run `cargo generate-lockfile` or `cargo build` to create it.";
+
+    /// No prebuilt tool is available for this platform.
+    UnsupportedPlatform = "RWC0301", "no prebuilt tools for this platform",
+"rustwasmc downloads prebuilt copies of wasm-bindgen and other tools, which are
+available for x86_64 Linux, macOS and Windows, and aarch64 Linux. On other
+platforms, install the tools yourself and make sure they are on your PATH.";
+
+    /// Downloading a tool failed.
+    DownloadFailed = "RWC0302", "a tool could not be downloaded",
+"rustwasmc could not download a tool it needs. Check your network connection
+and proxy settings. If GitHub is unreachable from your network, set a mirror
+for the tool in the `[mirrors]` table of rustwasmc.toml:
+
+    [mirrors]
+    wasm-bindgen = \"https://github-mirror.example.com\"";
+
+    /// A tool is not installed and may not be installed.
+    ToolNotInstalled = "RWC0303", "a required tool is not installed",
+"A tool rustwasmc needs is not installed, and the install mode does not allow
+installing it. Build without `--mode no-install`, or install the tool
+yourself and put it on your PATH.";
+
+    /// A tool's version could not be determined.
+    UnknownToolVersion = "RWC0304", "a tool's version could not be determined",
+"rustwasmc runs `<tool> --version` to check the version of the tools it
+installed, but could not read the output. The cached copy may be damaged:
+run `rustwasmc clean` and build again. If that does not help, please file an
+issue: https://github.com/second-state/rustwasmc/issues/new?template=bug_report.md";
+
+    /// There is no WasmEdge build of the wasm-bindgen version.
+    NoWasmEdgeBindgen = "RWC0305", "no WasmEdge build of this wasm-bindgen version",
+"rustwasmc uses a WasmEdge fork of wasm-bindgen, which is only available for
+the wasm-bindgen versions WasmEdge supports. Use wasm-bindgen 0.2.61.";
+
+    /// There is no Cargo.toml in the crate directory.
+    MissingCargoToml = "RWC0401", "Cargo.toml was not found",
+"The crate directory has no Cargo.toml. Run rustwasmc from a crate directory,
+or pass the path to the crate, e.g. `rustwasmc build path/to/crate`.";
+
+    /// The crate has neither a cdylib nor a bin target.
+    InvalidCrateType = "RWC0402", "the crate has no cdylib or bin target",
+"Only cdylib libraries and binaries can be compiled to wasm32. Add a cdylib
+library to your Cargo.toml:
+
+    [lib]
+    crate-type = [\"cdylib\", \"rlib\"]
+
+or a binary target:
+
+    [[bin]]
+    name = \"my-command\"
+    path = \"src/main.rs\"";
+
+    /// A `[package.metadata.rustwasmc.profile]` table is invalid.
+    InvalidProfile = "RWC0403", "invalid rustwasmc profile",
+"A [package.metadata.rustwasmc.profile.<name>] table inherits from a profile
+that does not exist, or the `inherits` keys form a cycle. Each profile may
+inherit from `dev`, `release`, `profiling`, or another profile table.";
+
+    /// The npm package name is invalid.
+    InvalidNpmName = "RWC0404", "invalid npm package name",
+"npm package names must be lowercase, URL-safe, at most 214 characters, and
+must not start with `.` or `_`. Unscoped names must not be a Node.js core
+module name, such as `http` or `fs`. The name is the crate name, with the --scope
+prepended. To publish under a different name, set it in Cargo.toml:
+
+    [package.metadata.rustwasmc.npm]
+    name = \"my-package\"";
+
+    /// The package.json version is not valid semver.
+    InvalidNpmVersion = "RWC0405", "invalid npm version",
+"The version written to package.json must be a valid semver version, such as
+`1.2.3` or `1.2.3-beta.1`. Check --npm-version and --prerelease.";
+
+    /// Command line options conflict.
+    ConflictingOptions = "RWC0501", "conflicting command line options",
+"Some options can't be used together, such as two build profiles, or
+require another option, such as --oci-archive, which requires --oci. See
+`rustwasmc help build`.";
+
+    /// A --bin or --example target does not exist.
+    UnknownTarget = "RWC0502", "no such target in the crate",
+"A target selected with --bin or --example is not in the crate. Check the
+[[bin]] and [[example]] targets in your Cargo.toml, or the files in
+src/bin and examples.";
+
+    /// A --skip-step or --only-step names no build step.
+    UnknownStep = "RWC0503", "no such build step",
+"--skip-step and --only-step take the names of build steps, such as
+`build-wasm`, `run-wasm-opt` or `hook-post-opt`. The error message lists
+every step.";
+
+    /// A selected build step needs one that was deselected.
+    MissingStepPrerequisite = "RWC0504", "a build step needs a deselected step",
+"Some build steps use what an earlier step produced: run-wasm-bindgen runs
+the wasm-bindgen installed by install-wasm-bindgen, and without build-wasm
+the later steps use the wasm files of the previous build, which must exist.
+Select the earlier step as well, e.g. with another --only-step, or build
+once without --skip-step and --only-step.";
+
+    /// wasm-opt failed.
+    WasmOptFailed = "RWC0601", "wasm-opt failed",
+"wasm-opt could not optimize the wasm file. To disable it, add to your
+Cargo.toml:
+
+    [package.metadata.rustwasmc.profile.release]
+    wasm-opt = false";
+
+    /// A hook command failed.
+    HookFailed = "RWC0701", "a build hook failed",
+"A command in [package.metadata.rustwasmc.hooks] exited with an error, so the
+build was stopped. Fix the command, or skip the hook with
+`--skip-step hook-<name>`, e.g. `--skip-step hook-post-opt`.";
+
+    /// The package in the output directory can't be served.
+    NotServable = "RWC0801", "the package can't be served locally",
+"`rustwasmc serve` calls the functions of a package built for Node.js, so
+build it with:
+
+    $ rustwasmc build --target nodejs
+
+Serving needs Node.js on your PATH (or passed with --node), and the
+wasmedge-core package installed where Node.js can find it:
+
+    $ npm install wasmedge-core";
+
+    /// The FaaS API rejected a deployment.
+    DeployFailed = "RWC0802", "the FaaS API rejected the request",
+"`rustwasmc deploy` uploads the wasm file to the FaaS API at `faas-api`,
+which answered with an error. Check that the wasm file is a function built
+by rustwasmc, and that the API URL is right:
+
+    $ rustwasmc config show
+
+A function can only be updated or deleted with the admin key returned when
+it was created, which is kept in .rustwasmc-deploy.json. If the function was
+deleted on the service, remove its entry from that file and deploy again.";
+
+    /// There is no deployment to update or delete.
+    NotDeployed = "RWC0803", "the function has not been deployed",
+"`rustwasmc deploy --delete` deletes a function deployed from this crate,
+which is recorded in .rustwasmc-deploy.json along with the FaaS API it was
+deployed to. Nothing was recorded for this wasm file and API. Deploy it
+first, or pass the same --api and --wasm as when it was deployed.";
+}
+
+impl fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.code())
+    }
+}
+
+impl FromStr for ErrorCode {
+    type Err = failure::Error;
+    fn from_str(s: &str) -> Result<Self, failure::Error> {
+        let s = s.trim();
+        ErrorCode::ALL
+            .iter()
+            .find(|code| code.code().eq_ignore_ascii_case(s))
+            .cloned()
+            .ok_or_else(|| format_err!("Unknown error code: {}", s))
+    }
+}
+
+/// An error with an `ErrorCode`.
+#[derive(Debug)]
+pub struct CodedError {
+    /// The error's code.
+    pub code: ErrorCode,
+    /// The error message.
+    pub message: String,
+}
+
+impl CodedError {
+    /// Create an error with `code` and `message`.
+    pub fn new<S: Into<String>>(code: ErrorCode, message: S) -> CodedError {
+        CodedError {
+            code,
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for CodedError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl Fail for CodedError {}
+
+/// Find the code of `error`, or of one of its causes.
+pub fn code_of(error: &failure::Error) -> Option<ErrorCode> {
+    error.iter_chain().find_map(|cause| {
+        cause
+            .downcast_ref::<CodedError>()
+            .map(|e| e.code)
+            .or_else(|| {
+                cause
+                    .downcast_ref::<Context<CodedError>>()
+                    .map(|c| c.get_context().code)
+            })
+    })
+}
+
+/// How errors are reported on the command line.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MessageFormat {
+    /// Human-readable text.
+    Human,
+    /// One JSON object per error.
+    Json,
+}
+
+impl FromStr for MessageFormat {
+    type Err = failure::Error;
+    fn from_str(s: &str) -> Result<Self, failure::Error> {
+        match s {
+            "human" => Ok(MessageFormat::Human),
+            "json" => Ok(MessageFormat::Json),
+            _ => bail!("Unknown message-format: {}", s),
+        }
+    }
+}
+
+/// The text printed for `error` with the `human` message format.
+pub fn render_human(error: &failure::Error) -> String {
+    let mut out = match code_of(error) {
+        Some(code) => format!("Error[{}]: {}\n", code, error),
+        None => format!("Error: {}\n", error),
+    };
+    for cause in error.iter_causes() {
+        out.push_str(&format!("Caused by: {}\n", cause));
+    }
+    if let Some(code) = code_of(error) {
+        out.push_str(&format!(
+            "For more information about this error, try `rustwasmc explain {}`.\n",
+            code
+        ));
+    }
+    out
+}
+
+/// The JSON object printed for `error` with the `json` message format.
+pub fn render_json(error: &failure::Error) -> Value {
+    let code = code_of(error);
+    json!({
+        "error": {
+            "code": code.map(|c| c.code()),
+            "summary": code.map(|c| c.summary()),
+            "message": error.to_string(),
+            "causes": error.iter_causes().map(|c| c.to_string()).collect::<Vec<_>>(),
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use failure::ResultExt;
+    use std::collections::BTreeSet;
+
+    #[test]
+    fn codes_are_unique_and_parse_back() {
+        let mut seen = BTreeSet::new();
+        for &code in ErrorCode::ALL {
+            let s = code.code();
+            assert!(seen.insert(s), "{} is used twice", s);
+            assert!(s.starts_with("RWC"), "{}", s);
+            assert_eq!(s.len(), 7, "{}", s);
+            assert!(s[3..].chars().all(|c| c.is_ascii_digit()), "{}", s);
+            assert!(!code.summary().is_empty());
+            assert!(!code.explanation().is_empty());
+
+            assert_eq!(code.to_string(), s);
+            assert_eq!(s.parse::<ErrorCode>().unwrap(), code);
+            assert_eq!(format!(" {} ", s.to_lowercase()).parse::<ErrorCode>().unwrap(), code);
+        }
+        assert!("RWC9999".parse::<ErrorCode>().is_err());
+        assert!("".parse::<ErrorCode>().is_err());
+    }
+
+    #[test]
+    fn finds_the_code_in_the_cause_chain() {
+        fn failing() -> Result<(), failure::Error> {
+            bail_code!(ErrorCode::MissingLockfile, "no lockfile in {}", "/demo");
+        }
+        let e = failing().unwrap_err();
+        assert_eq!(code_of(&e), Some(ErrorCode::MissingLockfile));
+        assert_eq!(e.to_string(), "no lockfile in /demo");
+
+        let e: failure::Error = failing().context("building the crate").unwrap_err().into();
+        assert_eq!(code_of(&e), Some(ErrorCode::MissingLockfile));
+        assert_eq!(
+            render_human(&e),
+            "Error[RWC0203]: building the crate\n\
+             Caused by: no lockfile in /demo\n\
+             For more information about this error, try `rustwasmc explain RWC0203`.\n"
+        );
+
+        let e: failure::Error = Err::<(), _>(format_err!("plain"))
+            .context(CodedError::new(ErrorCode::DownloadFailed, "download failed"))
+            .unwrap_err()
+            .into();
+        assert_eq!(code_of(&e), Some(ErrorCode::DownloadFailed));
+        assert_eq!(
+            render_json(&e),
+            json!({
+                "error": {
+                    "code": "RWC0302",
+                    "summary": ErrorCode::DownloadFailed.summary(),
+                    "message": "download failed",
+                    "causes": ["plain"],
+                }
+            })
+        );
+
+        let e = format_err!("plain");
+        assert_eq!(code_of(&e), None);
+        assert_eq!(render_human(&e), "Error: plain\n");
+    }
+}
@@ -0,0 +1,703 @@
+//! Deploying functions to the Second State FaaS.
+//!
+//! The API takes the wasm file as the request body:
+//!
+//! * `POST /api/executables` creates a function and returns its `wasm_id`
+//!   and the `admin_key` needed to change it.
+//! * `PUT /api/update_wasm_binary/<wasm_id>` replaces the wasm file.
+//! * `PUT /api/update_aot_binary/<wasm_id>` uploads the AOT-compiled file.
+//! * `DELETE /api/executables/<wasm_id>` deletes the function.
+//!
+//! Requests that change a function send the admin key in the
+//! `SSVM_Admin_Key` header. What was deployed from a crate is recorded in
+//! `.rustwasmc-deploy.json`, so later deployments update the same function.
+//! As that file holds the admin keys, it is added to the crate's `.gitignore`.
+
+use error::{CodedError, ErrorCode};
+use failure::{Error, ResultExt};
+use reqwest::header::CONTENT_TYPE;
+use reqwest::{Client, RequestBuilder};
+use serde::de::DeserializeOwned;
+use serde_json;
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The FaaS API used when none is configured.
+pub const DEFAULT_API: &str = "https://rpc.ssvm.secondstate.io:8081";
+
+/// The file in the crate directory recording deployed functions.
+pub const STATE_FILE: &str = ".rustwasmc-deploy.json";
+
+/// The response to creating a function.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct CreateResponse {
+    /// The id of the function, used in its URL.
+    pub wasm_id: u64,
+    /// The SHA-256 of the wasm file, as `0x` and hex digits.
+    pub wasm_sha256: String,
+    /// The key for calling the function, if the service requires one.
+    pub usage_key: String,
+    /// The key for updating and deleting the function.
+    pub admin_key: String,
+}
+
+/// The response to replacing the wasm or AOT file of a function.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct UpdateResponse {
+    /// The id of the function.
+    pub wasm_id: u64,
+    /// The SHA-256 of the uploaded file, as `0x` and hex digits.
+    #[serde(alias = "aot_sha256")]
+    pub wasm_sha256: String,
+}
+
+/// The operations of the FaaS API. `FaasClient` implements them over HTTP;
+/// tests and tools can provide their own.
+pub trait FaasApi {
+    /// Create a function running `wasm`.
+    fn create(&self, wasm: &[u8], description: &str) -> Result<CreateResponse, Error>;
+
+    /// Replace the wasm file of the function `wasm_id`.
+    fn update(&self, wasm_id: u64, admin_key: &str, wasm: &[u8]) -> Result<UpdateResponse, Error>;
+
+    /// Upload the AOT-compiled file of the function `wasm_id`.
+    fn update_aot(&self, wasm_id: u64, admin_key: &str, aot: &[u8]) -> Result<UpdateResponse, Error>;
+
+    /// Delete the function `wasm_id`.
+    fn delete(&self, wasm_id: u64, admin_key: &str) -> Result<(), Error>;
+}
+
+/// A client for the FaaS API at a base URL.
+#[derive(Debug)]
+pub struct FaasClient {
+    base: String,
+    client: Client,
+}
+
+impl FaasClient {
+    /// A client for the API at `base`, e.g. `http://localhost:8081` for a
+    /// local stub.
+    pub fn new(base: &str) -> FaasClient {
+        FaasClient {
+            base: base.trim_end_matches('/').to_string(),
+            client: Client::new(),
+        }
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("{}{}", self.base, path)
+    }
+
+    fn send(&self, request: RequestBuilder) -> Result<String, Error> {
+        let mut response = request
+            .send()
+            .with_context(|_| format!("failed to connect to the FaaS API at {}", self.base))?;
+        let text = response.text().unwrap_or_default();
+        if !response.status().is_success() {
+            return Err(CodedError::new(
+                ErrorCode::DeployFailed,
+                format!(
+                    "{} answered {}: {}",
+                    response.url(),
+                    response.status(),
+                    text.trim()
+                ),
+            )
+            .into());
+        }
+        Ok(text)
+    }
+
+    fn send_json<T: DeserializeOwned>(&self, request: RequestBuilder) -> Result<T, Error> {
+        let text = self.send(request)?;
+        let value = serde_json::from_str(&text)
+            .with_context(|_| format!("unexpected response from the FaaS API: {}", text.trim()))?;
+        Ok(value)
+    }
+
+    fn upload(&self, path: &str, admin_key: &str, body: &[u8]) -> Result<UpdateResponse, Error> {
+        let request = self
+            .client
+            .put(&self.url(path))
+            .header(CONTENT_TYPE, "application/octet-stream")
+            .header("SSVM_Admin_Key", admin_key)
+            .body(body.to_vec());
+        self.send_json(request)
+    }
+}
+
+impl FaasApi for FaasClient {
+    fn create(&self, wasm: &[u8], description: &str) -> Result<CreateResponse, Error> {
+        let request = self
+            .client
+            .post(&self.url("/api/executables"))
+            .header(CONTENT_TYPE, "application/octet-stream")
+            .header("SSVM-Description", description)
+            .body(wasm.to_vec());
+        self.send_json(request)
+    }
+
+    fn update(&self, wasm_id: u64, admin_key: &str, wasm: &[u8]) -> Result<UpdateResponse, Error> {
+        self.upload(&format!("/api/update_wasm_binary/{}", wasm_id), admin_key, wasm)
+    }
+
+    fn update_aot(&self, wasm_id: u64, admin_key: &str, aot: &[u8]) -> Result<UpdateResponse, Error> {
+        self.upload(&format!("/api/update_aot_binary/{}", wasm_id), admin_key, aot)
+    }
+
+    fn delete(&self, wasm_id: u64, admin_key: &str) -> Result<(), Error> {
+        let request = self
+            .client
+            .delete(&self.url(&format!("/api/executables/{}", wasm_id)))
+            .header("SSVM_Admin_Key", admin_key);
+        self.send(request)?;
+        Ok(())
+    }
+}
+
+/// A function deployed from the crate.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Deployment {
+    /// The base URL of the API the function was deployed to.
+    pub api: String,
+    /// The name of the deployed wasm file in the output directory.
+    pub wasm: String,
+    /// The id of the function.
+    pub wasm_id: u64,
+    /// The SHA-256 of the deployed wasm file, as `0x` and hex digits.
+    pub wasm_sha256: String,
+    /// The SHA-256 of the deployed AOT file, if one was uploaded.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub aot_sha256: Option<String>,
+    /// The key for calling the function.
+    pub usage_key: String,
+    /// The key for updating and deleting the function.
+    pub admin_key: String,
+}
+
+/// The functions deployed from a crate, as stored in `STATE_FILE`.
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct DeployState {
+    /// Every deployed function.
+    pub deployments: Vec<Deployment>,
+}
+
+impl DeployState {
+    /// The path of the state file of the crate at `crate_path`.
+    pub fn path(crate_path: &Path) -> PathBuf {
+        crate_path.join(STATE_FILE)
+    }
+
+    /// Read the state of the crate at `crate_path`, which is empty if nothing
+    /// was deployed yet.
+    pub fn load(crate_path: &Path) -> Result<DeployState, Error> {
+        let path = DeployState::path(crate_path);
+        if !path.is_file() {
+            return Ok(DeployState::default());
+        }
+        let contents = fs::read_to_string(&path)
+            .with_context(|_| format!("failed to read: {}", path.display()))?;
+        let state = serde_json::from_str(&contents)
+            .with_context(|_| format!("failed to parse: {}", path.display()))?;
+        Ok(state)
+    }
+
+    /// Write the state of the crate at `crate_path`, readable only by the
+    /// owner, and make sure the crate's `.gitignore` lists it.
+    pub fn save(&self, crate_path: &Path) -> Result<(), Error> {
+        let path = DeployState::path(crate_path);
+        let contents = serde_json::to_string_pretty(self)?;
+        fs::write(&path, contents + "\n")
+            .with_context(|_| format!("failed to write: {}", path.display()))?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(&path, fs::Permissions::from_mode(0o600))
+                .with_context(|_| format!("failed to set the permissions of: {}", path.display()))?;
+        }
+        ignore_state_file(crate_path)
+    }
+
+    /// The function deployed from `wasm` to `api`.
+    pub fn find(&self, api: &str, wasm: &str) -> Option<&Deployment> {
+        self.deployments
+            .iter()
+            .find(|d| d.api == api && d.wasm == wasm)
+    }
+
+    /// Record `deployment`, replacing the one of the same file and API.
+    pub fn record(&mut self, deployment: Deployment) {
+        self.remove(&deployment.api, &deployment.wasm);
+        self.deployments.push(deployment);
+    }
+
+    /// Remove the function deployed from `wasm` to `api`, returning it.
+    pub fn remove(&mut self, api: &str, wasm: &str) -> Option<Deployment> {
+        let index = self
+            .deployments
+            .iter()
+            .position(|d| d.api == api && d.wasm == wasm)?;
+        Some(self.deployments.remove(index))
+    }
+}
+
+/// Add `STATE_FILE` to the `.gitignore` in `crate_path` unless it is listed.
+fn ignore_state_file(crate_path: &Path) -> Result<(), Error> {
+    let path = crate_path.join(".gitignore");
+    let mut contents = if path.is_file() {
+        fs::read_to_string(&path).with_context(|_| format!("failed to read: {}", path.display()))?
+    } else {
+        String::new()
+    };
+    let listed = contents
+        .lines()
+        .any(|line| line.trim().trim_start_matches('/') == STATE_FILE);
+    if listed {
+        return Ok(());
+    }
+    if !contents.is_empty() && !contents.ends_with('\n') {
+        contents.push('\n');
+    }
+    contents.push_str(&format!("/{}\n", STATE_FILE));
+    fs::write(&path, contents).with_context(|_| format!("failed to write: {}", path.display()))?;
+    Ok(())
+}
+
+fn sha256(bytes: &[u8]) -> String {
+    format!("0x{:x}", Sha256::digest(bytes))
+}
+
+/// Deploy `wasm_path` and, if given, its AOT-compiled file `aot_path` to
+/// `api`. Updates the function recorded in `state` for the same file and
+/// API, or creates one. Files that haven't changed since the last deployment
+/// are not uploaded again. The hashes of the uploaded files are computed
+/// locally, as the API may format them differently.
+///
+/// `state` is updated even if uploading the AOT file fails, and should be
+/// saved either way.
+pub fn deploy(
+    client: &dyn FaasApi,
+    state: &mut DeployState,
+    api: &str,
+    wasm_path: &Path,
+    aot_path: Option<&Path>,
+    description: &str,
+) -> Result<Deployment, Error> {
+    let wasm_name = match wasm_path.file_name().and_then(|n| n.to_str()) {
+        Some(name) => name.to_string(),
+        None => bail!("invalid wasm file name: {}", wasm_path.display()),
+    };
+    let wasm = fs::read(wasm_path)
+        .with_context(|_| format!("failed to read: {}", wasm_path.display()))?;
+    let aot = match aot_path {
+        Some(path) => {
+            Some(fs::read(path).with_context(|_| format!("failed to read: {}", path.display()))?)
+        }
+        None => None,
+    };
+
+    let wasm_sha256 = sha256(&wasm);
+    let mut deployment = match state.find(api, &wasm_name).cloned() {
+        Some(mut deployment) => {
+            if deployment.wasm_sha256 != wasm_sha256 {
+                client.update(deployment.wasm_id, &deployment.admin_key, &wasm)?;
+                deployment.wasm_sha256 = wasm_sha256;
+                // The AOT file was compiled from the old wasm file.
+                deployment.aot_sha256 = None;
+            }
+            deployment
+        }
+        None => {
+            let response = client.create(&wasm, description)?;
+            Deployment {
+                api: api.to_string(),
+                wasm: wasm_name,
+                wasm_id: response.wasm_id,
+                wasm_sha256,
+                aot_sha256: None,
+                usage_key: response.usage_key,
+                admin_key: response.admin_key,
+            }
+        }
+    };
+    // Record the function before uploading the AOT file, so that it can be
+    // updated or deleted if that fails.
+    state.record(deployment.clone());
+
+    if let Some(aot) = aot {
+        let aot_sha256 = sha256(&aot);
+        if deployment.aot_sha256.as_ref() != Some(&aot_sha256) {
+            client.update_aot(deployment.wasm_id, &deployment.admin_key, &aot)?;
+            deployment.aot_sha256 = Some(aot_sha256);
+            state.record(deployment.clone());
+        }
+    }
+    Ok(deployment)
+}
+
+/// Delete the function deployed from the wasm file `wasm_name` to `api`,
+/// and remove it from `state`.
+pub fn undeploy(
+    client: &dyn FaasApi,
+    state: &mut DeployState,
+    api: &str,
+    wasm_name: &str,
+) -> Result<Deployment, Error> {
+    let deployment = match state.find(api, wasm_name) {
+        Some(deployment) => deployment.clone(),
+        None => bail_code!(
+            ErrorCode::NotDeployed,
+            "{} has not been deployed to {}",
+            wasm_name,
+            api
+        ),
+    };
+    client.delete(deployment.wasm_id, &deployment.admin_key)?;
+    state.remove(api, wasm_name);
+    Ok(deployment)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use error;
+    use std::cell::RefCell;
+    use std::collections::HashMap;
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::sync::mpsc;
+    use std::thread;
+    use tempfile;
+
+    /// A `FaasApi` that records the calls made to it. Its hashes are
+    /// upper-case, to check that they aren't compared with local ones.
+    #[derive(Default)]
+    struct FakeApi {
+        calls: RefCell<Vec<String>>,
+    }
+
+    impl FakeApi {
+        fn take_calls(&self) -> Vec<String> {
+            self.calls.borrow_mut().drain(..).collect()
+        }
+    }
+
+    impl FaasApi for FakeApi {
+        fn create(&self, wasm: &[u8], description: &str) -> Result<CreateResponse, Error> {
+            self.calls
+                .borrow_mut()
+                .push(format!("create {} {}", wasm.len(), description));
+            Ok(CreateResponse {
+                wasm_id: 7,
+                wasm_sha256: sha256(wasm).to_uppercase(),
+                usage_key: "usage".to_string(),
+                admin_key: "admin".to_string(),
+            })
+        }
+
+        fn update(&self, wasm_id: u64, admin_key: &str, wasm: &[u8]) -> Result<UpdateResponse, Error> {
+            self.calls
+                .borrow_mut()
+                .push(format!("update {} {} {}", wasm_id, admin_key, wasm.len()));
+            Ok(UpdateResponse {
+                wasm_id,
+                wasm_sha256: sha256(wasm).to_uppercase(),
+            })
+        }
+
+        fn update_aot(&self, wasm_id: u64, admin_key: &str, aot: &[u8]) -> Result<UpdateResponse, Error> {
+            self.calls
+                .borrow_mut()
+                .push(format!("update_aot {} {} {}", wasm_id, admin_key, aot.len()));
+            Ok(UpdateResponse {
+                wasm_id,
+                wasm_sha256: sha256(aot).to_uppercase(),
+            })
+        }
+
+        fn delete(&self, wasm_id: u64, admin_key: &str) -> Result<(), Error> {
+            self.calls
+                .borrow_mut()
+                .push(format!("delete {} {}", wasm_id, admin_key));
+            Ok(())
+        }
+    }
+
+    const API: &str = "http://127.0.0.1:8081";
+
+    #[test]
+    fn deploys_updates_and_deletes() {
+        let dir = tempfile::tempdir().unwrap();
+        let wasm = dir.path().join("demo_bg.wasm");
+        let aot = dir.path().join("demo_bg.so");
+        fs::write(&wasm, b"wasm v1").unwrap();
+        fs::write(&aot, b"aot v1").unwrap();
+        let api = FakeApi::default();
+        let mut state = DeployState::default();
+
+        let deployment = deploy(&api, &mut state, API, &wasm, Some(&aot), "demo").unwrap();
+        assert_eq!(api.take_calls(), vec!["create 7 demo", "update_aot 7 admin 6"]);
+        assert_eq!(deployment.wasm_id, 7);
+        assert_eq!(deployment.wasm_sha256, sha256(b"wasm v1"));
+        assert_eq!(deployment.aot_sha256, Some(sha256(b"aot v1")));
+
+        // Nothing changed, so nothing is uploaded.
+        deploy(&api, &mut state, API, &wasm, Some(&aot), "demo").unwrap();
+        assert!(api.take_calls().is_empty());
+
+        // A new wasm file invalidates the AOT file compiled from the old one.
+        fs::write(&wasm, b"wasm v2").unwrap();
+        let deployment = deploy(&api, &mut state, API, &wasm, None, "demo").unwrap();
+        assert_eq!(api.take_calls(), vec!["update 7 admin 7"]);
+        assert_eq!(deployment.wasm_sha256, sha256(b"wasm v2"));
+        assert_eq!(deployment.aot_sha256, None);
+        deploy(&api, &mut state, API, &wasm, Some(&aot), "demo").unwrap();
+        assert_eq!(api.take_calls(), vec!["update_aot 7 admin 6"]);
+        assert_eq!(state.deployments.len(), 1);
+
+        let deleted = undeploy(&api, &mut state, API, "demo_bg.wasm").unwrap();
+        assert_eq!(api.take_calls(), vec!["delete 7 admin"]);
+        assert_eq!(deleted.wasm_id, 7);
+        assert!(state.deployments.is_empty());
+        assert!(undeploy(&api, &mut state, API, "demo_bg.wasm").is_err());
+    }
+
+    #[test]
+    fn keeps_deployments_to_other_apis_apart() {
+        let dir = tempfile::tempdir().unwrap();
+        let wasm = dir.path().join("demo_bg.wasm");
+        fs::write(&wasm, b"wasm").unwrap();
+        let api = FakeApi::default();
+        let mut state = DeployState::default();
+
+        deploy(&api, &mut state, API, &wasm, None, "demo").unwrap();
+        deploy(&api, &mut state, "http://other", &wasm, None, "demo").unwrap();
+        assert_eq!(api.take_calls(), vec!["create 4 demo", "create 4 demo"]);
+        assert_eq!(state.deployments.len(), 2);
+        assert!(state.find("http://other", "demo_bg.wasm").is_some());
+    }
+
+    #[test]
+    fn saves_and_loads_state() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(DeployState::load(dir.path()).unwrap().deployments.is_empty());
+
+        let mut state = DeployState::default();
+        state.record(Deployment {
+            api: API.to_string(),
+            wasm: "demo_bg.wasm".to_string(),
+            wasm_id: 7,
+            wasm_sha256: sha256(b"wasm"),
+            aot_sha256: Some(sha256(b"aot")),
+            usage_key: "usage".to_string(),
+            admin_key: "admin".to_string(),
+        });
+        state.save(dir.path()).unwrap();
+
+        let loaded = DeployState::load(dir.path()).unwrap();
+        assert_eq!(loaded.deployments.len(), 1);
+        let deployment = loaded.find(API, "demo_bg.wasm").unwrap();
+        assert_eq!(deployment.wasm_id, 7);
+        assert_eq!(deployment.admin_key, "admin");
+        assert_eq!(deployment.aot_sha256, Some(sha256(b"aot")));
+    }
+
+    #[test]
+    fn ignores_state_file_in_git() {
+        let dir = tempfile::tempdir().unwrap();
+        let gitignore = dir.path().join(".gitignore");
+        fs::write(&gitignore, "/target").unwrap();
+
+        DeployState::default().save(dir.path()).unwrap();
+        DeployState::default().save(dir.path()).unwrap();
+        assert_eq!(
+            fs::read_to_string(&gitignore).unwrap(),
+            "/target\n/.rustwasmc-deploy.json\n"
+        );
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(DeployState::path(dir.path()))
+                .unwrap()
+                .permissions()
+                .mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+    }
+
+    struct Received {
+        method: String,
+        path: String,
+        headers: HashMap<String, String>,
+        body: Vec<u8>,
+    }
+
+    /// A stand-in for the FaaS API that answers one request after another
+    /// with the given status and body, and records what it received.
+    struct MockFaas {
+        url: String,
+        received: mpsc::Receiver<Received>,
+    }
+
+    impl MockFaas {
+        fn start(responses: Vec<(u16, &'static str)>) -> MockFaas {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            // A trailing `/` must not end up in the request paths.
+            let url = format!("http://{}/", listener.local_addr().unwrap());
+            let (sender, received) = mpsc::channel();
+            thread::spawn(move || {
+                for (status, response) in responses {
+                    let (mut stream, _) = listener.accept().unwrap();
+                    let mut reader = BufReader::new(stream.try_clone().unwrap());
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    let mut parts = line.split_whitespace();
+                    let method = parts.next().unwrap().to_string();
+                    let path = parts.next().unwrap().to_string();
+                    let mut headers = HashMap::new();
+                    loop {
+                        line.clear();
+                        reader.read_line(&mut line).unwrap();
+                        let header = line.trim_end();
+                        if header.is_empty() {
+                            break;
+                        }
+                        let colon = header.find(':').unwrap();
+                        headers.insert(
+                            header[..colon].to_ascii_lowercase(),
+                            header[colon + 1..].trim().to_string(),
+                        );
+                    }
+                    let length = headers
+                        .get("content-length")
+                        .map(|length| length.parse().unwrap())
+                        .unwrap_or(0);
+                    let mut body = vec![0; length];
+                    reader.read_exact(&mut body).unwrap();
+                    write!(
+                        stream,
+                        "HTTP/1.1 {} X\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                        status,
+                        response.len(),
+                        response
+                    )
+                    .unwrap();
+                    sender
+                        .send(Received {
+                            method,
+                            path,
+                            headers,
+                            body,
+                        })
+                        .unwrap();
+                }
+            });
+            MockFaas { url, received }
+        }
+
+        fn next(&self) -> Received {
+            self.received.recv().unwrap()
+        }
+    }
+
+    #[test]
+    fn creates_functions_over_http() {
+        let mock = MockFaas::start(vec![(
+            200,
+            r#"{"wasm_id":7,"wasm_sha256":"0xAB","usage_key":"00000000","admin_key":"secret"}"#,
+        )]);
+        let client = FaasClient::new(&mock.url);
+
+        let response = client.create(b"\0asm", "demo 0.1.0").unwrap();
+        assert_eq!(response.wasm_id, 7);
+        assert_eq!(response.wasm_sha256, "0xAB");
+        assert_eq!(response.usage_key, "00000000");
+        assert_eq!(response.admin_key, "secret");
+
+        let request = mock.next();
+        assert_eq!(request.method, "POST");
+        assert_eq!(request.path, "/api/executables");
+        assert_eq!(request.headers["content-type"], "application/octet-stream");
+        assert_eq!(request.headers["ssvm-description"], "demo 0.1.0");
+        assert!(!request.headers.contains_key("ssvm_admin_key"));
+        assert_eq!(request.body, b"\0asm");
+    }
+
+    #[test]
+    fn updates_and_deletes_functions_over_http() {
+        let mock = MockFaas::start(vec![
+            (200, r#"{"wasm_id":7,"wasm_sha256":"0x01"}"#),
+            (200, r#"{"wasm_id":7,"aot_sha256":"0x02"}"#),
+            (200, ""),
+        ]);
+        let client = FaasClient::new(&mock.url);
+
+        let response = client.update(7, "secret", b"wasm").unwrap();
+        assert_eq!((response.wasm_id, response.wasm_sha256.as_str()), (7, "0x01"));
+        let request = mock.next();
+        assert_eq!(request.method, "PUT");
+        assert_eq!(request.path, "/api/update_wasm_binary/7");
+        assert_eq!(request.headers["ssvm_admin_key"], "secret");
+        assert_eq!(request.headers["content-type"], "application/octet-stream");
+        assert_eq!(request.body, b"wasm");
+
+        let response = client.update_aot(7, "secret", b"aot").unwrap();
+        assert_eq!(response.wasm_sha256, "0x02");
+        let request = mock.next();
+        assert_eq!(request.method, "PUT");
+        assert_eq!(request.path, "/api/update_aot_binary/7");
+        assert_eq!(request.headers["ssvm_admin_key"], "secret");
+        assert_eq!(request.body, b"aot");
+
+        client.delete(7, "secret").unwrap();
+        let request = mock.next();
+        assert_eq!(request.method, "DELETE");
+        assert_eq!(request.path, "/api/executables/7");
+        assert_eq!(request.headers["ssvm_admin_key"], "secret");
+        assert!(request.body.is_empty());
+    }
+
+    #[test]
+    fn reports_api_errors() {
+        let mock = MockFaas::start(vec![(403, "wrong admin key\n"), (200, "not json")]);
+        let client = FaasClient::new(&mock.url);
+
+        let e = client.delete(7, "wrong").unwrap_err();
+        assert_eq!(error::code_of(&e), Some(ErrorCode::DeployFailed));
+        let message = e.to_string();
+        assert!(message.contains("/api/executables/7"), "{}", message);
+        assert!(message.contains("403"), "{}", message);
+        assert!(message.ends_with(": wrong admin key"), "{}", message);
+
+        let e = client.update(7, "secret", b"wasm").unwrap_err();
+        assert!(e.to_string().contains("unexpected response from the FaaS API: not json"));
+    }
+
+    #[test]
+    fn deploys_through_the_client() {
+        let mock = MockFaas::start(vec![
+            (
+                200,
+                r#"{"wasm_id":3,"wasm_sha256":"0xANY","usage_key":"","admin_key":"secret"}"#,
+            ),
+            (200, r#"{"wasm_id":3,"wasm_sha256":"0xANY"}"#),
+        ]);
+        let dir = tempfile::tempdir().unwrap();
+        let wasm = dir.path().join("demo_bg.wasm");
+        let aot = dir.path().join("demo_bg.so");
+        fs::write(&wasm, b"wasm").unwrap();
+        fs::write(&aot, b"aot").unwrap();
+        let mut state = DeployState::default();
+
+        let client = FaasClient::new(&mock.url);
+        let deployment = deploy(&client, &mut state, &mock.url, &wasm, Some(&aot), "demo").unwrap();
+        assert_eq!(mock.next().path, "/api/executables");
+        assert_eq!(mock.next().path, "/api/update_aot_binary/3");
+        assert_eq!(deployment.wasm_id, 3);
+        assert_eq!(deployment.admin_key, "secret");
+        assert_eq!(deployment.wasm_sha256, sha256(b"wasm"));
+        assert_eq!(deployment.aot_sha256, Some(sha256(b"aot")));
+    }
+}
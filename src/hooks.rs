@@ -0,0 +1,210 @@
+//! Running the user-defined commands of `[package.metadata.rustwasmc.hooks]`.
+//!
+//! Each hook is a shell command, or a list of them, run from the crate
+//! directory at a fixed point of the build. The commands get the state of the
+//! build in `RUSTWASMC_*` environment variables:
+//!
+//! * `RUSTWASMC_HOOK`: the name of the hook, e.g. `post-opt`.
+//! * `RUSTWASMC_CRATE_DIR`: the crate directory.
+//! * `RUSTWASMC_PKG_DIR`: the output directory.
+//! * `RUSTWASMC_PROFILE`: `dev`, `release`, `profiling` or a custom profile.
+//! * `RUSTWASMC_RUN_TARGET`: `wasmedge`, `nodejs` or `deno`.
+//! * `RUSTWASMC_WASM_TARGET`: `wasm32-wasi` or `wasm32-unknown-unknown`.
+//! * `RUSTWASMC_ARTIFACTS`: the `.wasm` files built by cargo, separated like
+//!   `PATH`. Empty before the build.
+//! * `RUSTWASMC_PKG_WASM`: the `.wasm` files in the output directory,
+//!   separated like `PATH`.
+
+use child;
+use error::{CodedError, ErrorCode};
+use failure::Error;
+use std::env;
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// The state of the build passed to hook commands.
+#[derive(Debug)]
+pub struct HookEnv<'a> {
+    /// The crate directory.
+    pub crate_path: &'a Path,
+    /// The output directory.
+    pub out_dir: &'a Path,
+    /// The name of the build profile.
+    pub profile: &'a str,
+    /// The run target.
+    pub run_target: &'a str,
+    /// The wasm target cargo builds for.
+    pub wasm_target: &'a str,
+    /// The `.wasm` files built by cargo so far.
+    pub artifacts: Vec<&'a Path>,
+}
+
+impl<'a> HookEnv<'a> {
+    fn vars(&self, hook: &str) -> Result<Vec<(&'static str, OsString)>, Error> {
+        let mut pkg_wasm = Vec::new();
+        if let Ok(entries) = fs::read_dir(self.out_dir) {
+            for entry in entries.filter_map(|e| e.ok()) {
+                let path = entry.path();
+                if path.extension().and_then(|ext| ext.to_str()) == Some("wasm") {
+                    pkg_wasm.push(absolute(&path)?);
+                }
+            }
+        }
+        pkg_wasm.sort();
+        let artifacts = self
+            .artifacts
+            .iter()
+            .map(|path| absolute(path))
+            .collect::<Result<Vec<_>, Error>>()?;
+
+        Ok(vec![
+            ("RUSTWASMC_HOOK", hook.into()),
+            ("RUSTWASMC_CRATE_DIR", absolute(self.crate_path)?.into()),
+            ("RUSTWASMC_PKG_DIR", absolute(self.out_dir)?.into()),
+            ("RUSTWASMC_PROFILE", self.profile.into()),
+            ("RUSTWASMC_RUN_TARGET", self.run_target.into()),
+            ("RUSTWASMC_WASM_TARGET", self.wasm_target.into()),
+            ("RUSTWASMC_ARTIFACTS", env::join_paths(artifacts)?),
+            ("RUSTWASMC_PKG_WASM", env::join_paths(pkg_wasm)?),
+        ])
+    }
+}
+
+fn absolute(path: &Path) -> Result<PathBuf, Error> {
+    if path.is_absolute() {
+        Ok(path.to_path_buf())
+    } else {
+        Ok(env::current_dir()?.join(path))
+    }
+}
+
+/// Run the `commands` of `hook` one after another, stopping at the first
+/// one that fails.
+pub fn run(hook: &str, commands: &[String], hook_env: &HookEnv) -> Result<(), Error> {
+    let vars = hook_env.vars(hook)?;
+    for command in commands {
+        let mut cmd = shell_command(command, hook_env.crate_path);
+        cmd.envs(vars.iter().map(|(key, value)| (key, value)));
+        child::run(cmd, command).map_err(|e| {
+            e.context(CodedError::new(
+                ErrorCode::HookFailed,
+                format!("the {} hook `{}` failed", hook, command),
+            ))
+        })?;
+    }
+    Ok(())
+}
+
+/// The command that runs the hook command `command` with the shell, from
+/// `crate_path`.
+pub fn shell_command(command: &str, crate_path: &Path) -> Command {
+    let mut cmd = if cfg!(windows) {
+        let mut cmd = Command::new("cmd");
+        cmd.arg("/C").arg(command);
+        cmd
+    } else {
+        let mut cmd = Command::new("sh");
+        cmd.arg("-c").arg(command);
+        cmd
+    };
+    cmd.current_dir(crate_path);
+    cmd
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use error::code_of;
+    use tempfile;
+
+    fn hook_env<'a>(dir: &'a Path, out_dir: &'a Path) -> HookEnv<'a> {
+        HookEnv {
+            crate_path: dir,
+            out_dir,
+            profile: "release",
+            run_target: "nodejs",
+            wasm_target: "wasm32-wasi",
+            artifacts: vec![Path::new("target/wasm32-wasi/release/demo.wasm")],
+        }
+    }
+
+    #[test]
+    fn exports_the_build_state() {
+        let dir = tempfile::tempdir().unwrap();
+        let out_dir = dir.path().join("pkg");
+        fs::create_dir(&out_dir).unwrap();
+        for file in &["b.wasm", "a.wasm", "a.js", "a.so"] {
+            fs::write(out_dir.join(file), "").unwrap();
+        }
+
+        let vars = hook_env(dir.path(), &out_dir).vars("post-opt").unwrap();
+        let var = |name: &str| {
+            let (_, value) = vars.iter().find(|(key, _)| *key == name).unwrap();
+            value.clone().into_string().unwrap()
+        };
+        assert_eq!(vars.len(), 8);
+        assert_eq!(var("RUSTWASMC_HOOK"), "post-opt");
+        assert_eq!(var("RUSTWASMC_CRATE_DIR"), dir.path().display().to_string());
+        assert_eq!(var("RUSTWASMC_PKG_DIR"), out_dir.display().to_string());
+        assert_eq!(var("RUSTWASMC_PROFILE"), "release");
+        assert_eq!(var("RUSTWASMC_RUN_TARGET"), "nodejs");
+        assert_eq!(var("RUSTWASMC_WASM_TARGET"), "wasm32-wasi");
+        // Relative paths are made absolute.
+        assert_eq!(
+            var("RUSTWASMC_ARTIFACTS"),
+            env::current_dir()
+                .unwrap()
+                .join("target/wasm32-wasi/release/demo.wasm")
+                .display()
+                .to_string()
+        );
+        assert_eq!(
+            var("RUSTWASMC_PKG_WASM"),
+            format!("{}:{}", out_dir.join("a.wasm").display(), out_dir.join("b.wasm").display())
+        );
+
+        // Before the build, nothing is listed.
+        let missing = dir.path().join("missing");
+        let mut hook_env = hook_env(dir.path(), &missing);
+        hook_env.artifacts.clear();
+        let vars = hook_env.vars("pre-build").unwrap();
+        for (key, value) in &vars {
+            if *key == "RUSTWASMC_ARTIFACTS" || *key == "RUSTWASMC_PKG_WASM" {
+                assert!(value.is_empty());
+            }
+        }
+    }
+
+    #[test]
+    fn runs_commands_in_the_crate_dir_with_the_build_state() {
+        let dir = tempfile::tempdir().unwrap();
+        let out_dir = dir.path().join("pkg");
+        let commands = vec![
+            "echo \"$RUSTWASMC_HOOK $RUSTWASMC_PROFILE\" > first.txt".to_string(),
+            "cp first.txt second.txt".to_string(),
+        ];
+        run("post-bindgen", &commands, &hook_env(dir.path(), &out_dir)).unwrap();
+        assert_eq!(
+            fs::read_to_string(dir.path().join("second.txt")).unwrap(),
+            "post-bindgen release\n"
+        );
+    }
+
+    #[test]
+    fn stops_at_the_first_failing_command() {
+        let dir = tempfile::tempdir().unwrap();
+        let out_dir = dir.path().join("pkg");
+        let commands = vec![
+            "touch first".to_string(),
+            "exit 3".to_string(),
+            "touch third".to_string(),
+        ];
+        let e = run("pre-package", &commands, &hook_env(dir.path(), &out_dir)).unwrap_err();
+        assert_eq!(code_of(&e), Some(ErrorCode::HookFailed));
+        assert_eq!(e.to_string(), "the pre-package hook `exit 3` failed");
+        assert!(dir.path().join("first").exists());
+        assert!(!dir.path().join("third").exists());
+    }
+}
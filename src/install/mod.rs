@@ -3,7 +3,9 @@
 use self::krate::Krate;
 use binary_install::{Cache, Download};
 use child;
+use config;
 use emoji;
+use error::{CodedError, ErrorCode};
 use failure::{self, ResultExt};
 use log::debug;
 use log::{info};
@@ -30,11 +32,14 @@ pub use self::tool::Tool;
 /// global install on `$PATH` that fits the bill. Then attempts to download a
 /// tarball from the GitHub releases page, if this target has prebuilt
 /// binaries. Finally, falls back to `cargo install`.
+///
+/// If a `mirror` is given it replaces the GitHub host in the download URL.
 pub fn download_prebuilt_or_cargo_install(
     tool: Tool,
     cache: &Cache,
     version: &str,
     install_permitted: bool,
+    mirror: Option<&str>,
 ) -> Result<Download, failure::Error> {
     // If the tool is installed globally and it has the right version, use
     // that. Assume that other tools are installed next to it.
@@ -51,15 +56,12 @@ pub fn download_prebuilt_or_cargo_install(
     let msg = format!("{}Installing {}...", emoji::DOWN_ARROW, tool);
     PBAR.info(&msg);
 
-    let dl = download_prebuilt(&tool, &cache, version, install_permitted);
+    let dl = download_prebuilt(&tool, cache, version, install_permitted, mirror);
     match dl {
-        Ok(dl) => return Ok(dl),
-        Err(e) => {
-            panic!(
-                "could not download pre-built `{}`: {}.",
-                tool, e
-            );
-        }
+        Ok(dl) => Ok(dl),
+        Err(e) => Err(e
+            .context(format!("could not download pre-built `{}`", tool))
+            .into()),
     }
 
     // cargo_install(tool, &cache, version, install_permitted)
@@ -91,10 +93,14 @@ pub fn get_cli_version(tool: &Tool, path: &PathBuf) -> Result<String, failure::E
     let mut cmd = Command::new(path);
     cmd.arg("--version");
     let stdout = child::run_capture_stdout(cmd, tool)?;
-    let version = stdout.trim().split_whitespace().nth(1);
+    let version = stdout.split_whitespace().nth(1);
     match version {
         Some(v) => Ok(v.to_string()),
-        None => bail!("Something went wrong! We couldn't determine your version of the wasm-bindgen CLI. We were supposed to set that up for you, so it's likely not your fault! You should file an issue: https://github.com/second-state/rustwasmc/issues/new?template=bug_report.md.")
+        None => bail_code!(
+            ErrorCode::UnknownToolVersion,
+            "Something went wrong! We couldn't determine your version of the {} CLI.",
+            tool
+        ),
     }
 }
 
@@ -104,34 +110,56 @@ pub fn download_prebuilt(
     cache: &Cache,
     version: &str,
     install_permitted: bool,
+    mirror: Option<&str>,
 ) -> Result<Download, failure::Error> {
-    let url = match prebuilt_url(tool, version) {
-        Ok(url) => url,
-        Err(e) => bail!(
-            "no prebuilt {} binaries are available for this platform: {}",
-            tool,
-            e,
-        ),
+    let url = prebuilt_download_url(tool, version, mirror)?;
+    let download = |name: &str, binaries: &[&str]| {
+        cache
+            .download(install_permitted, name, binaries, &url)
+            .map_err(|e| {
+                e.context(CodedError::new(
+                    ErrorCode::DownloadFailed,
+                    format!("failed to download {} from {}", name, url),
+                ))
+            })
     };
     match tool {
         Tool::WasmBindgen => {
             // let binaries = &["wasm-bindgen", "wasm-bindgen-test-runner"];
             let binaries = &["wasm-bindgen"];
-            match cache.download(install_permitted, "wasm-bindgen", binaries, &url)? {
+            match download("wasm-bindgen", binaries)? {
                 Some(download) => Ok(download),
-                None => bail!("wasm-bindgen v{} is not installed!", version),
+                None => bail_code!(
+                    ErrorCode::ToolNotInstalled,
+                    "wasm-bindgen v{} is not installed!",
+                    version
+                ),
             }
         }
         Tool::CargoGenerate => {
             let binaries = &["cargo-generate"];
-            match cache.download(install_permitted, "cargo-generate", binaries, &url)? {
+            match download("cargo-generate", binaries)? {
                 Some(download) => Ok(download),
-                None => bail!("cargo-generate v{} is not installed!", version),
+                None => bail_code!(
+                    ErrorCode::ToolNotInstalled,
+                    "cargo-generate v{} is not installed!",
+                    version
+                ),
             }
         }
     }
 }
 
+/// The URL `download_prebuilt` downloads the tool from, with `mirror`
+/// applied.
+pub fn prebuilt_download_url(
+    tool: &Tool,
+    version: &str,
+    mirror: Option<&str>,
+) -> Result<String, failure::Error> {
+    Ok(config::apply_mirror(&prebuilt_url(tool, version)?, mirror))
+}
+
 /// Returns the URL of a precompiled version of wasm-bindgen, if we have one
 /// available for our host platform.
 fn prebuilt_url(tool: &Tool, version: &str) -> Result<String, failure::Error> {
@@ -144,11 +172,15 @@ fn prebuilt_url(tool: &Tool, version: &str) -> Result<String, failure::Error> {
     } else if target::LINUX && target::aarch64 {
         "aarch64-unknown-linux-gnu"
     } else {
-        bail!("Unrecognized target!")
+        bail_code!(
+            ErrorCode::UnsupportedPlatform,
+            "no prebuilt {} binaries are available for this platform",
+            tool
+        )
     };
 
     let semv = Version::parse(version).unwrap();
-    let ssvm_ver = get_ssvm_ver(&format!("{}.{}.{}", semv.major, semv.minor, semv.patch)).unwrap();
+    let ssvm_ver = get_ssvm_ver(&format!("{}.{}.{}", semv.major, semv.minor, semv.patch))?;
 
     match tool {
         Tool::WasmBindgen => {
@@ -193,14 +225,24 @@ fn get_ssvm_ver(bindgen_semver: &str) -> Result<String, failure::Error> {
                 vers = String::from_utf8(data.to_vec()).unwrap();
                 Ok(data.len())
             }).unwrap();
-            transfer.perform().unwrap();
+            if let Err(e) = transfer.perform() {
+                bail_code!(
+                    ErrorCode::DownloadFailed,
+                    "failed to download the list of WasmEdge wasm-bindgen versions: {}",
+                    e
+                );
+            }
         }
     }
 
     let vers: Value = serde_json::from_str(&vers).unwrap();
     match &vers[bindgen_semver] {
         Value::String(sv) => Ok(sv.to_string()),
-        _ => bail!("no wasmedge mapping for bindgen {}", bindgen_semver)
+        _ => bail_code!(
+            ErrorCode::NoWasmEdgeBindgen,
+            "no wasmedge mapping for bindgen {}",
+            bindgen_semver
+        ),
     }
 }
 
@@ -230,7 +272,7 @@ pub fn cargo_install(
     }
 
     if !install_permitted {
-        bail!("{} v{} is not installed!", tool, version)
+        bail_code!(ErrorCode::ToolNotInstalled, "{} v{} is not installed!", tool, version)
     }
 
     // Run `cargo install` to a temporary location to handle ctrl-c gracefully
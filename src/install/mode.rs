@@ -1,8 +1,9 @@
+use std::fmt;
 use std::str::FromStr;
 
 /// The `InstallMode` determines which mode of initialization we are running, and
 /// what install steps we perform.
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum InstallMode {
     /// Perform all the install steps.
     Normal,
@@ -13,6 +14,8 @@ pub enum InstallMode {
     Force,
 }
 
+// `#[default]` on an enum variant needs Rust 1.62.
+#[allow(clippy::derivable_impls)]
 impl Default for InstallMode {
     fn default() -> InstallMode {
         InstallMode::Normal
@@ -31,6 +34,16 @@ impl FromStr for InstallMode {
     }
 }
 
+impl fmt::Display for InstallMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            InstallMode::Noinstall => write!(f, "no-install"),
+            InstallMode::Normal => write!(f, "normal"),
+            InstallMode::Force => write!(f, "force"),
+        }
+    }
+}
+
 impl InstallMode {
     /// Determines if installation is permitted during a function call based on --mode flag
     pub fn install_permitted(self) -> bool {
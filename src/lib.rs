@@ -2,13 +2,19 @@
 
 #![deny(missing_docs)]
 
+extern crate base64;
 extern crate cargo_metadata;
 extern crate console;
 extern crate strsim;
 #[macro_use]
 extern crate failure;
+extern crate flate2;
 extern crate glob;
+extern crate hex;
+#[macro_use]
+extern crate lazy_static;
 extern crate parking_lot;
+extern crate reqwest;
 extern crate semver;
 extern crate serde;
 extern crate which;
@@ -16,6 +22,8 @@ extern crate which;
 extern crate serde_derive;
 extern crate serde_ignored;
 extern crate serde_json;
+extern crate sha1;
+extern crate sha2;
 #[macro_use]
 extern crate structopt;
 extern crate binary_install;
@@ -23,26 +31,51 @@ extern crate chrono;
 extern crate curl;
 extern crate dialoguer;
 extern crate log;
+extern crate notify;
+extern crate tar;
 extern crate toml;
 extern crate walkdir;
 
+#[cfg(test)]
+extern crate tempfile;
+
+#[macro_use]
+pub mod error;
 pub mod bindgen;
 pub mod build;
 pub mod cache;
 pub mod child;
 pub mod command;
+pub mod config;
 pub mod emoji;
+pub mod faas;
+pub mod hooks;
 pub mod install;
 pub mod license;
 pub mod lockfile;
+pub mod logfile;
 pub mod manifest;
+pub mod npm;
+pub mod oci;
+pub mod pack;
+pub mod plan;
 pub mod progressbar;
 pub mod readme;
+pub mod reporter;
+pub mod reproducible;
+pub mod serve;
+pub mod spdx;
 pub mod target;
 pub mod wasm_opt;
 pub mod wasmedgec;
+pub mod watch;
 
+use error::MessageFormat;
 use progressbar::{LogLevel, ProgressOutput};
+use std::path::PathBuf;
+
+pub use command::build::{BuildConfig, BuildOutcome};
+pub use reporter::Reporter;
 
 /// The global progress bar and user-facing message output.
 pub static PBAR: ProgressOutput = ProgressOutput::new();
@@ -65,4 +98,12 @@ pub struct Cli {
     #[structopt(long = "log-level", default_value = "info")]
     /// The maximum level of messages that should be logged by rustwasmc. [possible values: info, warn, error]
     pub log_level: LogLevel,
+
+    #[structopt(long = "message-format", default_value = "human")]
+    /// How errors are reported. `json` prints each error, with its code, as a JSON object to stdout. [possible values: human, json]
+    pub message_format: MessageFormat,
+
+    #[structopt(long = "log-file", parse(from_os_str))]
+    /// Also write a timestamped log of every message, build step and child command, with its output, to this file, whatever the verbosity
+    pub log_file: Option<PathBuf>,
 }
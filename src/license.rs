@@ -1,11 +1,16 @@
-//! Copy `LICENSE` file(s) for the packaged wasm.
+//! Copy `LICENSE` file(s) for the packaged wasm, and collect the licenses of
+//! its dependencies.
 
-use failure;
+use failure::{self, ResultExt};
+use serde_json;
+use std::collections::BTreeSet;
 use std::fs;
 use std::path::Path;
 
+use build::CargoFeatures;
 use glob::glob;
 use manifest::CrateData;
+use spdx;
 use PBAR;
 
 fn glob_license_files(path: &Path) -> Result<Vec<String>, failure::Error> {
@@ -32,7 +37,7 @@ fn glob_license_files(path: &Path) -> Result<Vec<String>, failure::Error> {
                 };
                 license_files.push(file_name_string);
             }
-            Err(e) => println!("{:?}", e),
+            Err(e) => PBAR.warn(&e.to_string()),
         }
     }
     Ok(license_files)
@@ -45,39 +50,26 @@ pub fn copy_from_crate(
     out_dir: &Path,
 ) -> Result<(), failure::Error> {
     assert!(
-        fs::metadata(path).ok().map_or(false, |m| m.is_dir()),
+        path.is_dir(),
         "crate directory should exist"
     );
 
     assert!(
-        fs::metadata(&out_dir).ok().map_or(false, |m| m.is_dir()),
+        out_dir.is_dir(),
         "crate's pkg directory should exist"
     );
 
     match (crate_data.crate_license(), crate_data.crate_license_file()) {
-        (Some(_), _) => {
-            let license_files = glob_license_files(path);
-
-            match license_files {
-                Ok(files) => {
-                    if files.is_empty() {
-                        PBAR.info("License key is set in Cargo.toml but no LICENSE file(s) were found; Please add the LICENSE file(s) to your project directory");
-                        return Ok(());
-                    }
-                    for license_file in files {
-                        let crate_license_path = path.join(&license_file);
-                        let new_license_path = out_dir.join(&license_file);
-                        if fs::copy(&crate_license_path, &new_license_path).is_err() {
-                            PBAR.info("origin crate has no LICENSE");
-                        }
-                    }
-                }
-                Err(_) => PBAR.info("origin crate has no LICENSE"),
+        (Some(license), _) => match spdx::Expression::parse(license) {
+            Ok(expression) => copy_license_texts(&expression, path, out_dir)?,
+            Err(e) => {
+                PBAR.warn(&format!("{}; copying every LICENSE file", e));
+                copy_all_license_files(path, out_dir);
             }
-        }
+        },
         (None, Some(license_file)) => {
-            let crate_license_path = path.join(&license_file);
-            let new_license_path = out_dir.join(&license_file);
+            let crate_license_path = path.join(license_file);
+            let new_license_path = out_dir.join(license_file);
             if fs::copy(&crate_license_path, &new_license_path).is_err() {
                 PBAR.info("origin crate has no LICENSE");
             }
@@ -87,3 +79,253 @@ pub fn copy_from_crate(
 
     Ok(())
 }
+
+/// Copy the license text of each identifier in `expression`, e.g.
+/// `LICENSE-MIT` and `LICENSE-APACHE` for `MIT OR Apache-2.0`. A crate with a
+/// single license may keep its text in a plain `LICENSE` file.
+fn copy_license_texts(
+    expression: &spdx::Expression,
+    path: &Path,
+    out_dir: &Path,
+) -> Result<(), failure::Error> {
+    for id in expression.unknown_ids() {
+        PBAR.warn(&format!(
+            "`{}` in the crate's license is not a known SPDX identifier",
+            id
+        ));
+    }
+
+    let files = glob_license_files(path)?;
+    let licenses = expression.licenses();
+    let mut copied = BTreeSet::new();
+    for id in &licenses {
+        let mut matching: Vec<&String> = files
+            .iter()
+            .filter(|file| spdx::is_license_file_for(file, id))
+            .collect();
+        if matching.is_empty() && licenses.len() == 1 {
+            matching = files
+                .iter()
+                .filter(|file| ["LICENSE", "LICENSE.md", "LICENSE.txt"].contains(&file.as_str()))
+                .collect();
+        }
+        if matching.is_empty() {
+            PBAR.info(&format!(
+                "No LICENSE file was found for `{}`; Please add a LICENSE-{} file to your project directory",
+                id,
+                id.to_uppercase()
+            ));
+        }
+        copied.extend(matching);
+    }
+
+    for license_file in copied {
+        fs::copy(path.join(license_file), out_dir.join(license_file))
+            .with_context(|_| format!("failed to copy {}", license_file))?;
+    }
+    Ok(())
+}
+
+fn copy_all_license_files(path: &Path, out_dir: &Path) {
+    match glob_license_files(path) {
+        Ok(files) => {
+            if files.is_empty() {
+                PBAR.info("License key is set in Cargo.toml but no LICENSE file(s) were found; Please add the LICENSE file(s) to your project directory");
+                return;
+            }
+            for license_file in files {
+                let crate_license_path = path.join(&license_file);
+                let new_license_path = out_dir.join(license_file);
+                if fs::copy(&crate_license_path, &new_license_path).is_err() {
+                    PBAR.info("origin crate has no LICENSE");
+                }
+            }
+        }
+        Err(_) => PBAR.info("origin crate has no LICENSE"),
+    }
+}
+
+/// The name of the file listing the licenses of every dependency.
+pub const THIRD_PARTY_LICENSES: &str = "THIRD_PARTY_LICENSES";
+
+/// The name of the JSON summary of `THIRD_PARTY_LICENSES`.
+pub const THIRD_PARTY_LICENSES_JSON: &str = "THIRD_PARTY_LICENSES.json";
+
+#[derive(Serialize)]
+struct ThirdPartyLicense {
+    name: String,
+    version: String,
+    license: Option<String>,
+    repository: Option<String>,
+    license_files: Vec<String>,
+}
+
+fn is_license_file(name: &str) -> bool {
+    let name = name.to_uppercase();
+    ["LICENSE", "LICENCE", "COPYING", "NOTICE", "UNLICENSE"]
+        .iter()
+        .any(|prefix| name.starts_with(prefix))
+}
+
+/// Write `THIRD_PARTY_LICENSES`, with the license expression and license
+/// texts of every dependency compiled into the wasm file for `wasm_target`
+/// with `features`, and a JSON summary of it into `out_dir`.
+pub fn write_third_party_licenses(
+    crate_data: &CrateData,
+    wasm_target: &str,
+    features: &CargoFeatures,
+    out_dir: &Path,
+) -> Result<(), failure::Error> {
+    let mut text = String::from(
+        "This package contains code from the following third-party crates.\n",
+    );
+    let mut summary = Vec::new();
+
+    for pkg in crate_data.third_party_packages(wasm_target, features)? {
+        let pkg_dir = pkg.manifest_path.parent().unwrap_or(Path::new("."));
+        let mut license_files = BTreeSet::new();
+        if let Some(license_file) = &pkg.license_file {
+            license_files.insert(pkg_dir.join(license_file));
+        }
+        if let Ok(entries) = fs::read_dir(pkg_dir) {
+            for entry in entries.filter_map(|e| e.ok()) {
+                let is_file = entry.file_type().map(|t| t.is_file()).unwrap_or(false);
+                if is_file && is_license_file(&entry.file_name().to_string_lossy()) {
+                    license_files.insert(entry.path());
+                }
+            }
+        }
+
+        text.push_str(&format!("\n{}\n{} {}\n", "=".repeat(80), pkg.name, pkg.version));
+        if let Some(license) = &pkg.license {
+            text.push_str(&format!("License: {}\n", license));
+        }
+        if let Some(repository) = &pkg.repository {
+            text.push_str(&format!("Repository: {}\n", repository));
+        }
+        let mut names = Vec::new();
+        for path in &license_files {
+            let name = path
+                .file_name()
+                .map(|n| n.to_string_lossy().to_string())
+                .unwrap_or_default();
+            match fs::read_to_string(path) {
+                Ok(contents) => {
+                    text.push_str(&format!("\n--- {} ---\n\n{}\n", name, contents.trim_end()));
+                    names.push(name);
+                }
+                Err(_) => PBAR.warn(&format!(
+                    "Could not read the license file {} of {}",
+                    path.display(),
+                    pkg.name
+                )),
+            }
+        }
+        if pkg.license.is_none() && names.is_empty() {
+            PBAR.warn(&format!(
+                "{} {} declares no license and has no license file",
+                pkg.name, pkg.version
+            ));
+        }
+
+        summary.push(ThirdPartyLicense {
+            name: pkg.name.clone(),
+            version: pkg.version.to_string(),
+            license: pkg.license.clone(),
+            repository: pkg.repository.clone(),
+            license_files: names,
+        });
+    }
+
+    let text_path = out_dir.join(THIRD_PARTY_LICENSES);
+    fs::write(&text_path, text)
+        .with_context(|_| format!("failed to write: {}", text_path.display()))?;
+    let json_path = out_dir.join(THIRD_PARTY_LICENSES_JSON);
+    fs::write(&json_path, serde_json::to_string_pretty(&summary)?)
+        .with_context(|_| format!("failed to write: {}", json_path.display()))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::Value;
+    use tempfile;
+
+    fn write_crate(dir: &Path, name: &str, extra: &str) {
+        fs::create_dir_all(dir.join("src")).unwrap();
+        fs::write(
+            dir.join("Cargo.toml"),
+            format!(
+                "[package]\nname = \"{}\"\nversion = \"0.1.0\"\nauthors = []\n{}",
+                name, extra
+            ),
+        )
+        .unwrap();
+        fs::write(dir.join("src/lib.rs"), "").unwrap();
+    }
+
+    fn licensed_packages(crate_data: &CrateData, features: &CargoFeatures, out_dir: &Path) -> Vec<String> {
+        write_third_party_licenses(crate_data, "wasm32-wasi", features, out_dir).unwrap();
+        let json = fs::read_to_string(out_dir.join(THIRD_PARTY_LICENSES_JSON)).unwrap();
+        let summary: Value = serde_json::from_str(&json).unwrap();
+        summary
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|pkg| pkg["name"].as_str().unwrap().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn lists_the_dependencies_of_the_selected_features() {
+        let dir = tempfile::tempdir().unwrap();
+        // Path dependencies inside the crate's directory would be workspace
+        // members, so they go next to it.
+        let root = &dir.path().join("demo");
+        write_crate(
+            root,
+            "demo",
+            "\n[lib]\ncrate-type = [\"cdylib\"]\n\n\
+             [workspace]\n\n\
+             [features]\ndefault = [\"dflt\"]\nextra = [\"opt\"]\n\n\
+             [dependencies]\n\
+             always = { path = \"../always\" }\n\
+             dflt = { path = \"../dflt\", optional = true }\n\
+             opt = { path = \"../opt\", optional = true }\n\n\
+             [target.'cfg(windows)'.dependencies]\nwin = { path = \"../win\" }\n\n\
+             [dev-dependencies]\ndevonly = { path = \"../devonly\" }\n",
+        );
+        write_crate(&dir.path().join("always"), "always", "license = \"MIT\"\n");
+        fs::write(dir.path().join("always/LICENSE"), "The MIT license text\n").unwrap();
+        for name in &["dflt", "opt", "win", "devonly"] {
+            write_crate(&dir.path().join(name), name, "license = \"Apache-2.0\"\n");
+        }
+        let out_dir = root.join("pkg");
+        fs::create_dir_all(&out_dir).unwrap();
+        let crate_data = CrateData::new(root, None).unwrap();
+
+        assert_eq!(
+            licensed_packages(&crate_data, &CargoFeatures::default(), &out_dir),
+            vec!["always", "dflt"]
+        );
+        let text = fs::read_to_string(out_dir.join(THIRD_PARTY_LICENSES)).unwrap();
+        assert!(text.contains("always 0.1.0\nLicense: MIT\n"));
+        assert!(text.contains("--- LICENSE ---\n\nThe MIT license text\n"));
+
+        let no_default = CargoFeatures {
+            no_default_features: true,
+            ..CargoFeatures::default()
+        };
+        assert_eq!(licensed_packages(&crate_data, &no_default, &out_dir), vec!["always"]);
+
+        let extra = CargoFeatures {
+            features: vec!["extra".to_string()],
+            ..CargoFeatures::default()
+        };
+        assert_eq!(
+            licensed_packages(&crate_data, &extra, &out_dir),
+            vec!["always", "dflt", "opt"]
+        );
+    }
+}
@@ -6,6 +6,7 @@ use std::fs;
 use std::path::PathBuf;
 
 use console::style;
+use error::{CodedError, ErrorCode};
 use failure::{Error, ResultExt};
 use manifest::CrateData;
 use toml;
@@ -43,12 +44,14 @@ impl Lockfile {
     /// `None`.
     pub fn require_wasm_bindgen(&self) -> Result<&str, Error> {
         self.wasm_bindgen_version().ok_or_else(|| {
-            format_err!(
-                "Ensure that you have \"{}\" as a dependency in your Cargo.toml file:\n\
-                 [dependencies]\n\
-                 wasm-bindgen = \"=0.2.61\"",
-                style("wasm-bindgen 0.2.61").bold().dim(),
+            CodedError::new(
+                ErrorCode::MissingWasmBindgen,
+                format!(
+                    "Ensure that you have \"{}\" as a dependency in your Cargo.toml file",
+                    style("wasm-bindgen 0.2.61").bold().dim(),
+                ),
             )
+            .into()
         })
     }
 
@@ -72,7 +75,11 @@ fn get_lockfile_path(crate_data: &CrateData) -> Result<PathBuf, Error> {
     // if it cannot, otherwise return the path buffer.
     let lockfile_path = crate_data.workspace_root().join("Cargo.lock");
     if !lockfile_path.is_file() {
-        bail!("Could not find lockfile at {:?}", lockfile_path)
+        bail_code!(
+            ErrorCode::MissingLockfile,
+            "Could not find lockfile at {:?}",
+            lockfile_path
+        )
     } else {
         Ok(lockfile_path)
     }
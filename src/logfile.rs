@@ -0,0 +1,178 @@
+//! The log written by `--log-file`.
+//!
+//! The log records everything a command does, whatever the terminal
+//! verbosity: every message, every build step with its duration, every child
+//! process with its exit status, duration and output, and rustwasmc's own
+//! `log` records. Each entry starts with a UTC timestamp; the lines of a
+//! multi-line entry after the first are indented.
+
+use chrono::Utc;
+use failure::{Error, ResultExt};
+use log::{self, Level, LevelFilter, Log, Metadata, Record};
+use std::env;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, ExitStatus};
+use std::sync::Mutex;
+use std::time::Duration;
+
+lazy_static! {
+    static ref LOG_FILE: Mutex<Option<File>> = Mutex::new(None);
+}
+
+/// Start logging to `path`, replacing the file if it exists.
+pub fn open(path: &Path) -> Result<(), Error> {
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        fs::create_dir_all(parent)
+            .with_context(|_| format!("failed to create directory: {}", parent.display()))?;
+    }
+    let file = File::create(path)
+        .with_context(|_| format!("failed to create log file: {}", path.display()))?;
+    *LOG_FILE.lock().unwrap_or_else(|e| e.into_inner()) = Some(file);
+    // Let through the records `Logger` writes to the file.
+    if log::max_level() < LevelFilter::Debug {
+        log::set_max_level(LevelFilter::Debug);
+    }
+
+    let args: Vec<String> = env::args().collect();
+    write(
+        "START",
+        &format!("rustwasmc {}: {}", env!("CARGO_PKG_VERSION"), args.join(" ")),
+    );
+    Ok(())
+}
+
+/// Stop logging, closing the log file.
+pub fn close() {
+    *LOG_FILE.lock().unwrap_or_else(|e| e.into_inner()) = None;
+}
+
+/// Whether a log file is open.
+pub fn is_open() -> bool {
+    LOG_FILE
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .is_some()
+}
+
+/// Add an entry of the given kind, e.g. `INFO` or `STEP`, to the log file,
+/// if one is open.
+pub fn write(kind: &str, message: &str) {
+    let mut file = LOG_FILE.lock().unwrap_or_else(|e| e.into_inner());
+    let file = match file.as_mut() {
+        Some(file) => file,
+        None => return,
+    };
+    // The log is only an aid; failing to write it must not fail the command.
+    let _ = file.write_all(entry(kind, message).as_bytes());
+    let _ = file.flush();
+}
+
+/// The log entry of the given kind for `message`, ending with a newline.
+fn entry(kind: &str, message: &str) -> String {
+    let mut entry = format!(
+        "{} {:<5} ",
+        Utc::now().format("%Y-%m-%dT%H:%M:%S%.3fZ"),
+        kind
+    );
+    let mut lines = message.trim_end().lines();
+    entry.push_str(lines.next().unwrap_or(""));
+    entry.push('\n');
+    for line in lines {
+        entry.push_str("    ");
+        entry.push_str(line);
+        entry.push('\n');
+    }
+    entry
+}
+
+/// Record that `command` is about to run.
+pub fn command_started(command_name: &str, command: &Command) {
+    write("RUN", &format!("{}: {:?}", command_name, command));
+}
+
+/// Record how a command ended and what it printed.
+pub fn command_finished(
+    command_name: &str,
+    status: &ExitStatus,
+    duration: Duration,
+    stdout: &[u8],
+    stderr: &[u8],
+) {
+    let mut message = format!(
+        "{}: {} after {:.3}s",
+        command_name,
+        status,
+        duration.as_secs_f64()
+    );
+    for (stream, output) in &[("stdout", stdout), ("stderr", stderr)] {
+        if output.is_empty() {
+            continue;
+        }
+        message.push_str(&format!("\n--- {}\n", stream));
+        message.push_str(String::from_utf8_lossy(output).trim_end());
+    }
+    write("EXIT", &message);
+}
+
+/// A `log` logger that writes records to the log file as well as to the
+/// logger it wraps.
+///
+/// The log file gets rustwasmc's records down to `debug`, and other crates'
+/// warnings and errors.
+#[derive(Debug)]
+pub struct Logger<L> {
+    inner: L,
+}
+
+impl<L: Log> Logger<L> {
+    /// Wrap `inner`, which handles the records meant for the terminal.
+    pub fn new(inner: L) -> Logger<L> {
+        Logger { inner }
+    }
+}
+
+fn logs_to_file(metadata: &Metadata) -> bool {
+    let level = if metadata.target().starts_with("rustwasmc") {
+        Level::Debug
+    } else {
+        Level::Warn
+    };
+    metadata.level() <= level && is_open()
+}
+
+impl<L: Log> Log for Logger<L> {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.inner.enabled(metadata) || logs_to_file(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        if logs_to_file(record.metadata()) {
+            write(
+                "LOG",
+                &format!("{} {}: {}", record.level(), record.target(), record.args()),
+            );
+        }
+        self.inner.log(record);
+    }
+
+    fn flush(&self) {
+        self.inner.flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn indents_the_lines_of_an_entry() {
+        let exit = entry("EXIT", "cargo: exit status: 0\n--- stdout\nbuilt\n\n");
+        let (timestamp, rest) = exit.split_at(24);
+        assert!(timestamp.starts_with("20"));
+        assert!(timestamp.ends_with('Z'));
+        assert_eq!(rest, " EXIT  cargo: exit status: 0\n    --- stdout\n    built\n");
+        assert!(entry("INFO", "").ends_with(" INFO  \n"));
+    }
+}
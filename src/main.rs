@@ -15,26 +15,32 @@ use std::panic;
 use structopt::StructOpt;
 use rustwasmc::{
     command::run_rustwasmc,
-    Cli, PBAR,
+    error::{self, MessageFormat},
+    logfile, Cli, PBAR,
 };
 
 mod installer;
 
 fn main() {
-    env_logger::init();
+    let logger = env_logger::Builder::from_default_env().build();
+    log::set_max_level(logger.filter());
+    log::set_boxed_logger(Box::new(logfile::Logger::new(logger)))
+        .expect("the logger should only be set once");
 
     setup_panic_hooks();
 
-    if let Err(e) = run() {
-        eprintln!("Error: {}", e);
-        for cause in e.iter_causes() {
-            eprintln!("Caused by: {}", cause);
+    let mut message_format = MessageFormat::Human;
+    if let Err(e) = run(&mut message_format) {
+        logfile::write("ERROR", &error::render_human(&e));
+        match message_format {
+            MessageFormat::Human => eprint!("{}", error::render_human(&e)),
+            MessageFormat::Json => println!("{}", error::render_json(&e)),
         }
         ::std::process::exit(1);
     }
 }
 
-fn run() -> Result<(), failure::Error> {
+fn run(message_format: &mut MessageFormat) -> Result<(), failure::Error> {
     if let Ok(me) = env::current_exe() {
         // If we're actually running as the installer then execute our
         // self-installation, otherwise just continue as usual.
@@ -49,6 +55,7 @@ fn run() -> Result<(), failure::Error> {
     }
 
     let args = Cli::from_args();
+    *message_format = args.message_format;
 
     PBAR.set_log_level(args.log_level);
 
@@ -56,11 +63,19 @@ fn run() -> Result<(), failure::Error> {
         PBAR.set_quiet(true);
     }
 
+    if let Some(path) = &args.log_file {
+        logfile::open(path)?;
+    }
+
     run_rustwasmc(args.cmd)?;
+    logfile::write("END", "finished successfully");
 
     Ok(())
 }
 
+// `PanicInfo` is deprecated in favor of `PanicHookInfo`, which older Rust
+// versions don't have.
+#[allow(deprecated)]
 fn setup_panic_hooks() {
     let meta = human_panic::Metadata {
         version: env!("CARGO_PKG_VERSION").into(),
@@ -9,36 +9,87 @@
 mod npm;
 
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 use self::npm::{
-    repository::Repository, CommonJSPackage, NpmPackage,
+    person::Person, repository::Repository, BuildMetadata, CommonJSPackage, NpmPackage,
 };
-use cargo_metadata::Metadata;
+use cargo_metadata::{DependencyKind, Metadata, Package, PackageId};
 use chrono::offset;
 use chrono::DateTime;
+use chrono::TimeZone;
+use build::wasm_target;
+use build::{CargoFeatures, WasmArtifact};
 use command::build::{BuildProfile};
+use child;
 use curl::easy;
+use error::{CodedError, ErrorCode};
 use failure::{Error, ResultExt};
+use license;
+use spdx;
 use serde::{self, Deserialize};
+use semver::Version;
 use serde_json;
-use std::collections::BTreeSet;
+use std::collections::{BTreeMap, BTreeSet};
 use std::env;
 use std::io::Write;
 use strsim::levenshtein;
 use toml;
+use wasmedgec;
 use PBAR;
 
 const RUSTWASMC_METADATA_KEY: &str = "package.metadata.rustwasmc";
 const RUSTWASMC_VERSION: Option<&'static str> = option_env!("CARGO_PKG_VERSION");
 const RUSTWASMC_REPO_URL: &str = "https://github.com/second-state/rustwasmc";
 
+/// The version range of the WasmEdge npm packages added to package.json when
+/// none is configured.
+pub const DEFAULT_RUNTIME_VERSION: &str = "^0.8.0";
+
+/// The kinds of cargo target that rustwasmc packages.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CrateTargetKind {
+    /// A `cdylib` library. It gets JS bindings from `wasm-bindgen`.
+    Lib,
+    /// A `[[bin]]`. It is packaged as a standalone WASI command.
+    Bin,
+    /// An example. It is packaged like a binary, but only when requested.
+    Example,
+}
+
+impl CrateTargetKind {
+    /// Get the kind for a cargo target's `kind` list, if it is one rustwasmc
+    /// packages.
+    pub fn from_cargo_kinds(kinds: &[String]) -> Option<CrateTargetKind> {
+        if kinds.iter().any(|k| k == "example") {
+            Some(CrateTargetKind::Example)
+        } else if kinds.iter().any(|k| k == "bin") {
+            Some(CrateTargetKind::Bin)
+        } else if kinds.iter().any(|k| k == "cdylib") {
+            Some(CrateTargetKind::Lib)
+        } else {
+            None
+        }
+    }
+}
+
+/// A cargo target of the crate that rustwasmc packages.
+#[derive(Clone, Debug)]
+pub struct CrateTarget {
+    /// What kind of target this is.
+    pub kind: CrateTargetKind,
+    /// The target's name. For the library this is the name with `-` replaced
+    /// by `_`, as used for the `.wasm` file.
+    pub name: String,
+}
+
 /// Store for metadata learned about a crate
 pub struct CrateData {
     data: Metadata,
     current_idx: usize,
     manifest: CargoManifest,
     out_name: Option<String>,
+    npm_version: Option<String>,
 }
 
 #[doc(hidden)]
@@ -56,6 +107,12 @@ struct CargoPackage {
     license_file: Option<String>,
     repository: Option<String>,
     homepage: Option<String>,
+    // Only a plain version string is used; `rust-version.workspace = true`
+    // is ignored.
+    #[serde(rename = "rust-version")]
+    rust_version: Option<toml::Value>,
+    // A path, `false`, or `{ workspace = true }`.
+    readme: Option<toml::Value>,
 
     #[serde(default)]
     metadata: CargoMetadata,
@@ -71,6 +128,54 @@ struct CargoMetadata {
 struct CargoRustWasmc {
     #[serde(default)]
     profile: CargoRustWasmcProfiles,
+
+    /// Cargo features activated automatically for a run target (`wasmedge`,
+    /// `nodejs`, `deno`) or build flag (`enable-aot`, `enable-ext`, `no-wasi`).
+    #[serde(default)]
+    features: BTreeMap<String, Vec<String>>,
+
+    /// Extra package.json fields, merged over the generated ones.
+    #[serde(default)]
+    npm: serde_json::Map<String, serde_json::Value>,
+
+    #[serde(default)]
+    runtime: CargoRustWasmcRuntime,
+
+    #[serde(default)]
+    hooks: CargoRustWasmcHooks,
+}
+
+/// The `[package.metadata.rustwasmc.hooks]` table: shell commands run at
+/// fixed points of the build.
+#[derive(Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct CargoRustWasmcHooks {
+    pre_build: Option<CargoRustWasmcHookCommands>,
+    post_bindgen: Option<CargoRustWasmcHookCommands>,
+    post_opt: Option<CargoRustWasmcHookCommands>,
+    post_aot: Option<CargoRustWasmcHookCommands>,
+    pre_package: Option<CargoRustWasmcHookCommands>,
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(untagged)]
+enum CargoRustWasmcHookCommands {
+    One(String),
+    Many(Vec<String>),
+}
+
+/// The `[package.metadata.rustwasmc.runtime]` table, which configures the
+/// WasmEdge npm package the nodejs bindings are declared to depend on.
+#[derive(Default, Deserialize)]
+struct CargoRustWasmcRuntime {
+    #[serde(default, rename = "add-dependency")]
+    add_dependency: Option<bool>,
+
+    #[serde(default, rename = "wasmedge-core")]
+    wasmedge_core: Option<String>,
+
+    #[serde(default, rename = "wasmedge-extensions")]
+    wasmedge_extensions: Option<String>,
 }
 
 #[derive(Deserialize)]
@@ -92,6 +197,10 @@ struct CargoRustWasmcProfiles {
         deserialize_with = "CargoRustWasmcProfile::deserialize_profiling"
     )]
     profiling: CargoRustWasmcProfile,
+
+    /// User-defined profiles, e.g. `[package.metadata.rustwasmc.profile.faas]`.
+    #[serde(flatten)]
+    custom: BTreeMap<String, CargoRustWasmcProfile>,
 }
 
 impl Default for CargoRustWasmcProfiles {
@@ -100,21 +209,86 @@ impl Default for CargoRustWasmcProfiles {
             dev: CargoRustWasmcProfile::default_dev(),
             release: CargoRustWasmcProfile::default_release(),
             profiling: CargoRustWasmcProfile::default_profiling(),
+            custom: BTreeMap::new(),
+        }
+    }
+}
+
+impl CargoRustWasmcProfiles {
+    /// Fill in every unset value of the user-defined profiles from the
+    /// profile they inherit from.
+    fn resolve_custom_profiles(&mut self) -> Result<(), Error> {
+        let names: Vec<String> = self.custom.keys().cloned().collect();
+        for name in names {
+            let defaults = self.inherited_defaults(&name, &mut vec![name.clone()])?;
+            if let Some(profile) = self.custom.get_mut(&name) {
+                profile.update_with_defaults(&defaults);
+            }
+        }
+        Ok(())
+    }
+
+    /// Get the fully resolved profile that the custom profile `name` inherits
+    /// from. Profiles without `inherits` inherit from `release`.
+    fn inherited_defaults(
+        &self,
+        name: &str,
+        seen: &mut Vec<String>,
+    ) -> Result<CargoRustWasmcProfile, Error> {
+        let parent = self.custom[name]
+            .inherits
+            .clone()
+            .unwrap_or_else(|| "release".to_string());
+        match parent.as_str() {
+            "dev" => Ok(self.dev.clone()),
+            "release" => Ok(self.release.clone()),
+            "profiling" => Ok(self.profiling.clone()),
+            _ => {
+                if seen.contains(&parent) {
+                    bail_code!(
+                        ErrorCode::InvalidProfile,
+                        "profile `{}` inherits from itself: {} -> {}",
+                        name,
+                        seen.join(" -> "),
+                        parent
+                    );
+                }
+                let mut resolved = match self.custom.get(&parent) {
+                    Some(profile) => profile.clone(),
+                    None => bail_code!(
+                        ErrorCode::InvalidProfile,
+                        "profile `{}` inherits from `{}`, but there is no \
+                         [package.metadata.rustwasmc.profile.{}] in your Cargo.toml",
+                        name,
+                        parent,
+                        parent
+                    ),
+                };
+                seen.push(parent.clone());
+                resolved.update_with_defaults(&self.inherited_defaults(&parent, seen)?);
+                Ok(resolved)
+            }
         }
     }
 }
 
 /// This is where configuration goes for wasm-bindgen, wasm-opt, wasm-snip, or
 /// anything else that rustwasmc runs.
-#[derive(Default, Deserialize)]
+#[derive(Clone, Default, Deserialize)]
 pub struct CargoRustWasmcProfile {
+    /// The profile that unset values are taken from. Only used by
+    /// user-defined profiles.
+    #[serde(default)]
+    inherits: Option<String>,
     #[serde(default, rename = "wasm-bindgen")]
     wasm_bindgen: CargoRustWasmcProfileWasmBindgen,
     #[serde(default, rename = "wasm-opt")]
     wasm_opt: Option<CargoRustWasmcProfileWasmOpt>,
+    #[serde(default)]
+    wasmedgec: Option<CargoRustWasmcProfileWasmOpt>,
 }
 
-#[derive(Default, Deserialize)]
+#[derive(Clone, Default, Deserialize)]
 struct CargoRustWasmcProfileWasmBindgen {
     #[serde(default, rename = "debug-js-glue")]
     debug_js_glue: Option<bool>,
@@ -155,7 +329,7 @@ impl Crate {
 
         match old_metadata_file {
             Some(ref file_contents) => {
-                let last_updated = Self::return_stamp_file_value(&file_contents, "created")
+                let last_updated = Self::return_stamp_file_value(file_contents, "created")
                     .and_then(|t| DateTime::parse_from_str(t.as_str(), "%+").ok());
 
                 last_updated
@@ -163,7 +337,7 @@ impl Crate {
                         if current_time.signed_duration_since(last_updated).num_hours() > 24 {
                             Self::return_api_call_result(current_time).map(Some)
                         } else {
-                            Ok(Self::return_stamp_file_value(&file_contents, "version"))
+                            Ok(Self::return_stamp_file_value(file_contents, "version"))
                         }
                     })
                     .unwrap_or_else(|| Ok(None))
@@ -182,7 +356,7 @@ impl Crate {
         // "policy" as the success. This means that the 24 hours rate limiting
         // will be active regardless if the check succeeded or failed.
         match version {
-            Ok(ref version) => Self::override_stamp_file(current_time, Some(&version)).ok(),
+            Ok(ref version) => Self::override_stamp_file(current_time, Some(version)).ok(),
             Err(_) => Self::override_stamp_file(current_time, None).ok(),
         };
 
@@ -197,7 +371,6 @@ impl Crate {
 
         let mut file = fs::OpenOptions::new()
             .read(true)
-            .write(true)
             .append(true)
             .create(true)
             .open(path.with_extension("stamp"))?;
@@ -246,7 +419,7 @@ impl Crate {
 
         easy.useragent(&format!(
             "rustwasmc/{} ({})",
-            RUSTWASMC_VERSION.unwrap_or_else(|| "unknown"),
+            RUSTWASMC_VERSION.unwrap_or("unknown"),
             RUSTWASMC_REPO_URL
         ))?;
 
@@ -256,7 +429,7 @@ impl Crate {
 
         let status_code = easy.response_code()?;
 
-        if 200 <= status_code && status_code < 300 {
+        if (200..300).contains(&status_code) {
             let contents = easy.get_ref();
             let result = String::from_utf8_lossy(&contents.0);
 
@@ -287,34 +460,40 @@ impl Default for CargoRustWasmcProfileWasmOpt {
 impl CargoRustWasmcProfile {
     fn default_dev() -> Self {
         CargoRustWasmcProfile {
+            inherits: None,
             wasm_bindgen: CargoRustWasmcProfileWasmBindgen {
                 debug_js_glue: Some(true),
                 demangle_name_section: Some(true),
                 dwarf_debug_info: Some(false),
             },
             wasm_opt: None,
+            wasmedgec: Some(CargoRustWasmcProfileWasmOpt::Enabled(true)),
         }
     }
 
     fn default_release() -> Self {
         CargoRustWasmcProfile {
+            inherits: None,
             wasm_bindgen: CargoRustWasmcProfileWasmBindgen {
                 debug_js_glue: Some(false),
                 demangle_name_section: Some(true),
                 dwarf_debug_info: Some(false),
             },
             wasm_opt: Some(CargoRustWasmcProfileWasmOpt::Enabled(true)),
+            wasmedgec: Some(CargoRustWasmcProfileWasmOpt::Enabled(true)),
         }
     }
 
     fn default_profiling() -> Self {
         CargoRustWasmcProfile {
+            inherits: None,
             wasm_bindgen: CargoRustWasmcProfileWasmBindgen {
                 debug_js_glue: Some(false),
                 demangle_name_section: Some(true),
                 dwarf_debug_info: Some(false),
             },
             wasm_opt: Some(CargoRustWasmcProfileWasmOpt::Enabled(true)),
+            wasmedgec: Some(CargoRustWasmcProfileWasmOpt::Enabled(true)),
         }
     }
 
@@ -358,6 +537,9 @@ impl CargoRustWasmcProfile {
         if self.wasm_opt.is_none() {
             self.wasm_opt = defaults.wasm_opt.clone();
         }
+        if self.wasmedgec.is_none() {
+            self.wasmedgec = defaults.wasmedgec.clone();
+        }
     }
 
     /// Get this profile's configured `[wasm-bindgen.debug-js-glue]` value.
@@ -383,6 +565,16 @@ impl CargoRustWasmcProfile {
             CargoRustWasmcProfileWasmOpt::ExplicitArgs(s) => Some(s.clone()),
         }
     }
+
+    /// Get this profile's configured arguments for `wasmedgec`, if AOT
+    /// compilation is enabled for it.
+    pub fn wasmedgec_args(&self) -> Option<Vec<String>> {
+        match self.wasmedgec.as_ref()? {
+            CargoRustWasmcProfileWasmOpt::Enabled(false) => None,
+            CargoRustWasmcProfileWasmOpt::Enabled(true) => Some(Vec::new()),
+            CargoRustWasmcProfileWasmOpt::ExplicitArgs(s) => Some(s.clone()),
+        }
+    }
 }
 
 struct NpmData {
@@ -393,6 +585,24 @@ struct NpmData {
     homepage: Option<String>, // https://docs.npmjs.com/files/package.json#homepage
 }
 
+/// What a build adds to the package.json `write_package_json` generates.
+pub struct PackageJsonOptions<'a> {
+    /// The npm scope of the package.
+    pub scope: &'a Option<String>,
+    /// No TypeScript declarations were generated.
+    pub disable_dts: bool,
+    /// List the files in a stable order.
+    pub reproducible: bool,
+    /// The third-party license files were written.
+    pub third_party_licenses: bool,
+    /// The wasm files the build produced.
+    pub artifacts: &'a [WasmArtifact],
+    /// The cargo features the package was built with.
+    pub features: &'a [String],
+    /// The WasmEdge runtime package and version range the package needs.
+    pub runtime_dependency: Option<(String, String)>,
+}
+
 #[doc(hidden)]
 pub struct ManifestAndUnsedKeys {
     pub manifest: CargoManifest,
@@ -405,7 +615,8 @@ impl CrateData {
     pub fn new(crate_path: &Path, out_name: Option<String>) -> Result<CrateData, Error> {
         let manifest_path = crate_path.join("Cargo.toml");
         if !manifest_path.is_file() {
-            bail!(
+            bail_code!(
+                ErrorCode::MissingCargoToml,
                 "crate directory is missing a `Cargo.toml` file; is `{}` the \
                  wrong directory?",
                 crate_path.display()
@@ -419,7 +630,13 @@ impl CrateData {
         let manifest_and_keys = CrateData::parse_crate_data(&manifest_path)?;
         CrateData::warn_for_unused_keys(&manifest_and_keys);
 
-        let manifest = manifest_and_keys.manifest;
+        let mut manifest = manifest_and_keys.manifest;
+        manifest
+            .package
+            .metadata
+            .rustwasmc
+            .profile
+            .resolve_custom_profiles()?;
         let current_idx = data
             .packages
             .iter()
@@ -431,6 +648,7 @@ impl CrateData {
             manifest,
             current_idx,
             out_name,
+            npm_version: None,
         })
     }
 
@@ -442,7 +660,7 @@ impl CrateData {
     /// Will return Err if the file (manifest_path) couldn't be read or
     /// if deserialize to `CargoManifest` fails.
     pub fn parse_crate_data(manifest_path: &Path) -> Result<ManifestAndUnsedKeys, Error> {
-        let manifest = fs::read_to_string(&manifest_path)
+        let manifest = fs::read_to_string(manifest_path)
             .with_context(|_| format!("failed to read: {}", manifest_path.display()))?;
         let manifest = &mut toml::Deserializer::new(&manifest);
 
@@ -479,12 +697,87 @@ impl CrateData {
     }
 
     /// Get the configured profile.
-    pub fn configured_profile(&self, profile: BuildProfile) -> &CargoRustWasmcProfile {
+    ///
+    /// A custom cargo profile without a matching
+    /// `[package.metadata.rustwasmc.profile.<name>]` table uses the release
+    /// settings.
+    pub fn configured_profile(&self, profile: &BuildProfile) -> &CargoRustWasmcProfile {
+        let profiles = &self.manifest.package.metadata.rustwasmc.profile;
         match profile {
-            BuildProfile::Dev => &self.manifest.package.metadata.rustwasmc.profile.dev,
-            BuildProfile::Profiling => &self.manifest.package.metadata.rustwasmc.profile.profiling,
-            BuildProfile::Release => &self.manifest.package.metadata.rustwasmc.profile.release,
+            BuildProfile::Dev => &profiles.dev,
+            BuildProfile::Profiling => &profiles.profiling,
+            BuildProfile::Release => &profiles.release,
+            BuildProfile::Custom(name) => profiles.custom.get(name).unwrap_or(&profiles.release),
+        }
+    }
+
+    /// Get the commands configured for `hook` in
+    /// `[package.metadata.rustwasmc.hooks]`.
+    pub fn hook_commands(&self, hook: &str) -> Vec<String> {
+        let hooks = &self.manifest.package.metadata.rustwasmc.hooks;
+        let commands = match hook {
+            "pre-build" => &hooks.pre_build,
+            "post-bindgen" => &hooks.post_bindgen,
+            "post-opt" => &hooks.post_opt,
+            "post-aot" => &hooks.post_aot,
+            "pre-package" => &hooks.pre_package,
+            _ => return Vec::new(),
+        };
+        match commands {
+            Some(CargoRustWasmcHookCommands::One(command)) => vec![command.clone()],
+            Some(CargoRustWasmcHookCommands::Many(commands)) => commands.clone(),
+            None => Vec::new(),
+        }
+    }
+
+    /// Get the cargo features mapped to the given run target and build flags
+    /// by `[package.metadata.rustwasmc.features]`.
+    pub fn mapped_features(
+        &self,
+        run_target: &str,
+        enable_aot: bool,
+        enable_ext: bool,
+        no_wasi: bool,
+    ) -> Vec<String> {
+        let mapping = &self.manifest.package.metadata.rustwasmc.features;
+        let mut keys = vec![run_target];
+        if enable_aot {
+            keys.push("enable-aot");
+        }
+        if enable_ext {
+            keys.push("enable-ext");
         }
+        if no_wasi {
+            keys.push("no-wasi");
+        }
+        keys.iter()
+            .filter_map(|key| mapping.get(*key))
+            .flat_map(|features| features.iter().cloned())
+            .collect()
+    }
+
+    /// Get the names of the crate's features that a build with `features`
+    /// activates, not counting features they enable in turn.
+    pub fn active_features(&self, features: &CargoFeatures) -> Vec<String> {
+        let pkg = &self.data.packages[self.current_idx];
+        let mut active: Vec<String> = if features.all_features {
+            pkg.features
+                .keys()
+                .filter(|name| *name != "default")
+                .cloned()
+                .collect()
+        } else {
+            let mut active = features.features.clone();
+            if !features.no_default_features {
+                if let Some(default) = pkg.features.get("default") {
+                    active.extend(default.iter().cloned());
+                }
+            }
+            active
+        };
+        active.sort();
+        active.dedup();
+        active
     }
 
     /// Check that the crate the given path is properly configured.
@@ -493,48 +786,259 @@ impl CrateData {
         Ok(())
     }
     
-    /// Check the crate type is valid.
-    /// Return whether [bin] is the only crate type.
-    pub fn check_crate_type(&self) -> Result<bool, Error> {
-        let pkg = &self.data.packages[self.current_idx];
-        let any_cdylib = pkg
+    /// Check the crate has at least one `cdylib` or `[[bin]]` target.
+    pub fn check_crate_type(&self) -> Result<(), Error> {
+        if !self.crate_targets().is_empty() {
+            return Ok(());
+        }
+        bail_code!(
+            ErrorCode::InvalidCrateType,
+            "crate-type must be cdylib or bin to compile to wasm32"
+        )
+    }
+
+    /// Get the WasmEdge npm package, and its version range, that bindings
+    /// generated for `run_target` require at runtime.
+    ///
+    /// Only `nodejs` bindings `require()` the runtime. `version` overrides the
+    /// range configured in `[package.metadata.rustwasmc.runtime]`, and
+    /// `disabled` or `add-dependency = false` opt out.
+    ///
+    /// WasmEdge only loads AOT files compiled by the same release, so with
+    /// `enable_aot` the default range is that of the `wasmedgec` rustwasmc
+    /// downloads.
+    pub fn runtime_dependency(
+        &self,
+        run_target: &str,
+        enable_ext: bool,
+        enable_aot: bool,
+        version: Option<&str>,
+        disabled: bool,
+    ) -> Option<(String, String)> {
+        let runtime = &self.manifest.package.metadata.rustwasmc.runtime;
+        if disabled || runtime.add_dependency == Some(false) {
+            return None;
+        }
+        if run_target != "nodejs" || !self.has_cdylib() {
+            return None;
+        }
+        let (name, configured) = if enable_ext {
+            ("wasmedge-extensions", &runtime.wasmedge_extensions)
+        } else {
+            ("wasmedge-core", &runtime.wasmedge_core)
+        };
+        let version = version
+            .map(String::from)
+            .or_else(|| configured.clone())
+            .unwrap_or_else(|| {
+                if enable_aot {
+                    format!("~{}", wasmedgec::VERSION)
+                } else {
+                    DEFAULT_RUNTIME_VERSION.to_string()
+                }
+            });
+        Some((name.to_string(), version))
+    }
+
+    /// Get the validated npm package name: the `name` in
+    /// `[package.metadata.rustwasmc.npm]` or the crate's name, prefixed with
+    /// `@scope/` unless the override is already scoped.
+    pub fn npm_name(&self, scope: &Option<String>) -> Result<String, Error> {
+        let overridden = self
+            .manifest
+            .package
+            .metadata
+            .rustwasmc
+            .npm
+            .get("name")
+            .map(|name| match name.as_str() {
+                Some(name) => Ok(name.to_string()),
+                None => Err(CodedError::new(
+                    ErrorCode::InvalidNpmName,
+                    "`name` in [package.metadata.rustwasmc.npm] must be a string",
+                )),
+            })
+            .transpose()?;
+        let name = overridden
+            .clone()
+            .unwrap_or_else(|| self.data.packages[self.current_idx].name.clone());
+        let npm_name = match scope {
+            Some(scope) if !name.starts_with('@') => {
+                format!("@{}/{}", scope.trim_start_matches('@'), name)
+            }
+            _ => name,
+        };
+
+        if let Err(e) = self::npm::name::validate(&npm_name) {
+            let hint = if overridden.is_some() {
+                "Fix `name` in [package.metadata.rustwasmc.npm] in Cargo.toml"
+            } else {
+                "Set a valid `name` in [package.metadata.rustwasmc.npm] in Cargo.toml"
+            };
+            let hint = if scope.is_some() {
+                format!("{}, or check the --scope", hint)
+            } else {
+                hint.to_string()
+            };
+            bail_code!(ErrorCode::InvalidNpmName, "{}. {}.", e, hint);
+        }
+        Ok(npm_name)
+    }
+
+    /// Get the version written to package.json: the one set by
+    /// `set_npm_version`, or the crate's version.
+    pub fn npm_version(&self) -> String {
+        match &self.npm_version {
+            Some(version) => version.clone(),
+            None => self.crate_version(),
+        }
+    }
+
+    /// Derive the package.json version from `version` (the crate's version
+    /// if `None`) and a `prerelease` tag, and use it for package.json.
+    ///
+    /// The prerelease tag is followed by the short hash of the git commit
+    /// the crate is at, or by a `YYYYMMDDHHMMSS` timestamp outside of git.
+    /// The timestamp is taken from `epoch` when it is set.
+    pub fn set_npm_version(
+        &mut self,
+        version: Option<&str>,
+        prerelease: Option<&str>,
+        crate_path: &Path,
+        epoch: Option<u64>,
+    ) -> Result<(), Error> {
+        let mut npm_version = match version {
+            Some(version) => version.trim_start_matches('v').to_string(),
+            None => self.crate_version(),
+        };
+        if let Some(tag) = prerelease {
+            // It would start the build metadata instead.
+            if tag.contains('+') {
+                bail_code!(
+                    ErrorCode::InvalidNpmVersion,
+                    "`{}` is not a valid prerelease tag: it can't contain `+`",
+                    tag
+                );
+            }
+            let id = match git_short_sha(crate_path) {
+                // An all-digit identifier can't have leading zeros in semver.
+                Some(sha) if sha.chars().all(|c| c.is_ascii_digit()) => format!("g{}", sha),
+                Some(sha) => sha,
+                None => {
+                    let time = epoch
+                        .and_then(|epoch| offset::Utc.timestamp_opt(epoch as i64, 0).single())
+                        .unwrap_or_else(offset::Utc::now);
+                    time.format("%Y%m%d%H%M%S").to_string()
+                }
+            };
+            // Build metadata has to stay at the end.
+            let (base, build) = match npm_version.find('+') {
+                Some(idx) => npm_version.split_at(idx),
+                None => (npm_version.as_str(), ""),
+            };
+            let separator = if base.contains('-') { '.' } else { '-' };
+            npm_version = format!("{}{}{}.{}{}", base, separator, tag, id, build);
+        }
+        if let Err(e) = Version::parse(&npm_version) {
+            bail_code!(
+                ErrorCode::InvalidNpmVersion,
+                "`{}` is not a valid npm version: {}",
+                npm_version,
+                e
+            );
+        }
+        self.npm_version = Some(npm_version);
+        Ok(())
+    }
+
+    /// Get every package the crate depends on, directly or indirectly, in the
+    /// dependency graph resolved with `features`, sorted by name and version.
+    ///
+    /// Only normal dependencies that apply to `wasm_target` are followed, as
+    /// only those end up in the wasm file: dev- and build-dependencies,
+    /// dependencies for other platforms, and what procedural macros use are
+    /// left out, as are workspace members.
+    pub fn third_party_packages(
+        &self,
+        wasm_target: &str,
+        features: &CargoFeatures,
+    ) -> Result<Vec<Package>, Error> {
+        let args = features.args();
+        if args.is_empty() {
+            return Ok(dependencies_in(&self.data, self.package_id(), wasm_target)
+                .into_iter()
+                .cloned()
+                .collect());
+        }
+        // The metadata read in `new` is resolved with the default features.
+        let data = cargo_metadata::MetadataCommand::new()
+            .manifest_path(&self.data.packages[self.current_idx].manifest_path)
+            .other_options(args)
+            .exec()?;
+        Ok(dependencies_in(&data, self.package_id(), wasm_target)
+            .into_iter()
+            .cloned()
+            .collect())
+    }
+
+    /// Get the crate's `cdylib` library and `[[bin]]` targets.
+    pub fn crate_targets(&self) -> Vec<CrateTarget> {
+        self.data.packages[self.current_idx]
             .targets
             .iter()
-            .filter(|target| target.kind.iter().any(|k| k == "cdylib"))
-            .any(|target| target.crate_types.iter().any(|s| s == "cdylib"));
-        let any_bin = pkg
-            .targets
+            .filter_map(|t| {
+                let kind = CrateTargetKind::from_cargo_kinds(&t.kind)?;
+                let name = match kind {
+                    CrateTargetKind::Lib => t.name.replace('-', "_"),
+                    CrateTargetKind::Bin => t.name.clone(),
+                    CrateTargetKind::Example => return None,
+                };
+                Some(CrateTarget { kind, name })
+            })
+            .collect()
+    }
+
+    /// Whether the crate has a `cdylib` library target.
+    pub fn has_cdylib(&self) -> bool {
+        self.crate_targets()
             .iter()
-            .filter(|target| target.kind.iter().any(|k| k == "bin"))
-            .any(|target| target.crate_types.iter().any(|s| s == "bin"));
-        if any_cdylib || any_bin {
-            return Ok(!any_cdylib);
-        }
-        bail!(
-            "crate-type must be cdylib or bin to compile to wasm32. Add the following to your \
-             Cargo.toml file:\n\n\
-             [lib]\n\
-             crate-type = [\"cdylib\", \"rlib\"]\n\
-             or\n\
-             [[bin]]"
-        )
+            .any(|t| t.kind == CrateTargetKind::Lib)
     }
 
-    /// Get the crate name for the crate at the given path.
-    pub fn crate_name(&self) -> Vec<String> {
-        let pkg = &self.data.packages[self.current_idx];
-        return pkg
+    /// Get the names of the crate's `[[bin]]` targets.
+    pub fn bin_names(&self) -> Vec<String> {
+        self.targets_of_kind("bin").map(|t| t.name.clone()).collect()
+    }
+
+    /// Get the names of the crate's examples.
+    pub fn example_names(&self) -> Vec<String> {
+        self.targets_of_kind("example").map(|t| t.name.clone()).collect()
+    }
+
+    /// Get the directories holding the sources of the crate's targets,
+    /// including examples, tests and the build script.
+    pub fn source_dirs(&self) -> Vec<PathBuf> {
+        let mut dirs: Vec<PathBuf> = self.data.packages[self.current_idx]
             .targets
             .iter()
-            .filter(|t| t.kind.iter().any(|k| k == "cdylib" || k == "bin"))
-            .map(|t| {
-                if t.kind.iter().any(|k| k == "bin") {
-                    t.name.clone()
-                } else {
-                    t.name.replace("-", "_")
-                }
-            })
+            .filter_map(|t| Path::new(&t.src_path).parent().map(Path::to_path_buf))
             .collect();
+        dirs.sort();
+        dirs.dedup();
+        // A directory inside another one is watched along with it.
+        let all = dirs.clone();
+        dirs.retain(|dir| !all.iter().any(|other| other != dir && dir.starts_with(other)));
+        dirs
+    }
+
+    fn targets_of_kind<'a>(
+        &'a self,
+        kind: &'a str,
+    ) -> impl Iterator<Item = &'a cargo_metadata::Target> + 'a {
+        self.data.packages[self.current_idx]
+            .targets
+            .iter()
+            .filter(move |t| t.kind.iter().any(|k| k == kind))
     }
 
     /// Get the prefix for output file names
@@ -550,6 +1054,47 @@ impl CrateData {
         &self.manifest.package.license
     }
 
+    /// Get the description for the crate at the given path.
+    pub fn crate_description(&self) -> &Option<String> {
+        &self.manifest.package.description
+    }
+
+    /// Get the repository URL for the crate at the given path.
+    pub fn crate_repository(&self) -> &Option<String> {
+        &self.manifest.package.repository
+    }
+
+    /// Get the cargo package id of the crate.
+    pub fn package_id(&self) -> &PackageId {
+        &self.data.packages[self.current_idx].id
+    }
+
+    /// Get the version of the crate at the given path.
+    pub fn crate_version(&self) -> String {
+        self.data.packages[self.current_idx].version.to_string()
+    }
+
+    /// Get the path of the crate's README, following the `readme` key of
+    /// Cargo.toml, including when it is inherited from the workspace. Without
+    /// the key, `README.md` in the crate directory is used if it exists.
+    /// Returns `None` for `readme = false`.
+    pub fn crate_readme(&self) -> Option<PathBuf> {
+        if self.readme_disabled() {
+            return None;
+        }
+        let pkg = &self.data.packages[self.current_idx];
+        let crate_dir = pkg.manifest_path.parent()?;
+        match &pkg.readme {
+            Some(readme) => Some(crate_dir.join(readme)),
+            None => Some(crate_dir.join("README.md")).filter(|path| path.is_file()),
+        }
+    }
+
+    /// Whether the crate opts out of a README with `readme = false`.
+    pub fn readme_disabled(&self) -> bool {
+        matches!(self.manifest.package.readme, Some(toml::Value::Boolean(false)))
+    }
+
     /// Get the license file path for the crate at the given path.
     pub fn crate_license_file(&self) -> &Option<String> {
         &self.manifest.package.license_file
@@ -570,13 +1115,18 @@ impl CrateData {
     pub fn write_package_json(
         &self,
         out_dir: &Path,
-        scope: &Option<String>,
-        disable_dts: bool
+        options: &PackageJsonOptions,
     ) -> Result<(), Error> {
         let pkg_file_path = out_dir.join("package.json");
-        let npm_data = self.to_commonjs(scope, disable_dts, out_dir);
-
-        let npm_json = serde_json::to_string_pretty(&npm_data)?;
+        let npm_name = self.npm_name(options.scope)?;
+        let npm_data = self.to_commonjs(npm_name, out_dir, options);
+
+        let mut npm_value = serde_json::to_value(&npm_data)?;
+        let mut extra = self.manifest.package.metadata.rustwasmc.npm.clone();
+        // The name override is already applied, together with the scope.
+        extra.remove("name");
+        merge_json(&mut npm_value, &serde_json::Value::Object(extra));
+        let npm_json = serde_json::to_string_pretty(&npm_value)?;
         fs::write(&pkg_file_path, npm_json)
             .with_context(|_| format!("failed to write: {}", pkg_file_path.display()))?;
         Ok(())
@@ -584,29 +1134,46 @@ impl CrateData {
 
     fn npm_data(
         &self,
-        scope: &Option<String>,
+        npm_name: String,
         include_commonjs_shim: bool,
         disable_dts: bool,
+        third_party_licenses: bool,
+        artifacts: &[WasmArtifact],
         out_dir: &Path,
     ) -> NpmData {
         let name_prefix = self.name_prefix();
-        let wasm_file = format!("{}_bg.wasm", name_prefix);
         let js_file = format!("{}.js", name_prefix);
-        let mut files = vec![wasm_file];
+        let mut files = vec![];
+
+        if self.has_cdylib() {
+            files.push(format!("{}_bg.wasm", name_prefix));
+            files.push(js_file.clone());
+            if include_commonjs_shim {
+                let js_bg_file = format!("{}_bg.js", name_prefix);
+                files.push(js_bg_file);
+            }
+        }
 
-        files.push(js_file.clone());
-        if include_commonjs_shim {
-            let js_bg_file = format!("{}_bg.js", name_prefix);
-            files.push(js_bg_file);
+        // Binaries and examples are standalone WASI commands, shipped next to
+        // the bindings. Only list those of the selected targets, as the out
+        // dir may still hold others from earlier builds.
+        for artifact in artifacts {
+            if artifact.kind != CrateTargetKind::Lib {
+                files.push(format!("{}.wasm", artifact.name));
+            }
         }
 
-        let pkg = &self.data.packages[self.current_idx];
-        let npm_name = match scope {
-            Some(s) => format!("@{}/{}", s, pkg.name),
-            None => pkg.name.clone(),
-        };
+        // Include the AOT binaries `wasmedgec` wrote next to each wasm file.
+        let aot_files: Vec<String> = files
+            .iter()
+            .filter(|f| f.ends_with(".wasm"))
+            .map(|f| Path::new(f).with_extension("so"))
+            .filter(|f| out_dir.join(f).is_file())
+            .filter_map(|f| f.to_str().map(String::from))
+            .collect();
+        files.extend(aot_files);
 
-        let dts_file = if !disable_dts {
+        let dts_file = if !disable_dts && self.has_cdylib() {
             let file = format!("{}.d.ts", name_prefix);
             files.push(file.to_string());
             Some(file)
@@ -626,6 +1193,13 @@ impl CrateData {
             }
         }
 
+        // The out dir isn't cleaned between builds, so only list the files
+        // this build writes.
+        if third_party_licenses {
+            files.push(license::THIRD_PARTY_LICENSES.to_string());
+            files.push(license::THIRD_PARTY_LICENSES_JSON.to_string());
+        }
+
         NpmData {
             name: npm_name,
             dts_file,
@@ -636,7 +1210,14 @@ impl CrateData {
     }
 
     fn license(&self) -> Option<String> {
-        self.manifest.package.license.clone().or_else(|| {
+        let license = self.manifest.package.license.as_ref().map(|license| {
+            // npm expects an SPDX expression, so normalize e.g. `MIT/Apache-2.0`.
+            match spdx::Expression::parse(license) {
+                Ok(expression) => expression.to_string(),
+                Err(_) => license.clone(),
+            }
+        });
+        license.or_else(|| {
             self.manifest.package.license_file.clone().map(|file| {
                 // When license is written in file: https://docs.npmjs.com/files/package.json#license
                 format!("SEE LICENSE IN {}", file)
@@ -644,17 +1225,59 @@ impl CrateData {
         })
     }
 
-    fn to_commonjs(&self, scope: &Option<String>, disable_dts: bool, out_dir: &Path) -> NpmPackage {
-        let data = self.npm_data(scope, true, disable_dts, out_dir);
+    fn to_commonjs(
+        &self,
+        npm_name: String,
+        out_dir: &Path,
+        options: &PackageJsonOptions,
+    ) -> NpmPackage {
+        let mut data = self.npm_data(
+            npm_name,
+            true,
+            options.disable_dts,
+            options.third_party_licenses,
+            options.artifacts,
+            out_dir,
+        );
+        if options.reproducible {
+            // `read_dir` order depends on the file system, so make it stable.
+            data.files.sort();
+            data.files.dedup();
+        }
         let pkg = &self.data.packages[self.current_idx];
 
         self.check_optional_fields();
 
+        let mut people = pkg.authors.iter().map(|author| Person::parse(author));
+        let author = people.next();
+        let contributors = people.collect();
+
+        // npm has no separate field for categories, so list them as keywords.
+        let mut keywords = pkg.keywords.clone();
+        for category in &pkg.categories {
+            if !keywords.contains(category) {
+                keywords.push(category.clone());
+            }
+        }
+
+        let mut engines = BTreeMap::new();
+        if let Some(rust_version) = self
+            .manifest
+            .package
+            .rust_version
+            .as_ref()
+            .and_then(|v| v.as_str())
+        {
+            engines.insert("rustc".to_string(), format!(">={}", rust_version));
+        }
+
         NpmPackage::CommonJSPackage(CommonJSPackage {
             name: data.name,
-            collaborators: pkg.authors.clone(),
+            author,
+            contributors,
             description: self.manifest.package.description.clone(),
-            version: pkg.version.to_string(),
+            version: self.npm_version(),
+            keywords,
             license: self.license(),
             repository: self
                 .manifest
@@ -669,6 +1292,15 @@ impl CrateData {
             main: data.main,
             homepage: data.homepage,
             types: data.dts_file,
+            dependencies: options.runtime_dependency.clone().into_iter().collect(),
+            engines,
+            rustwasmc: if options.features.is_empty() {
+                None
+            } else {
+                Some(BuildMetadata {
+                    features: options.features.to_vec(),
+                })
+            },
         })
     }
 
@@ -692,3 +1324,453 @@ impl CrateData {
         };
     }
 }
+
+/// Merge `value` into `target`. Objects are merged key by key, anything else
+/// replaces the target.
+fn merge_json(target: &mut serde_json::Value, value: &serde_json::Value) {
+    match (target, value) {
+        (serde_json::Value::Object(target), serde_json::Value::Object(value)) => {
+            for (key, value) in value {
+                merge_json(
+                    target.entry(key.clone()).or_insert(serde_json::Value::Null),
+                    value,
+                );
+            }
+        }
+        (target, value) => *target = value.clone(),
+    }
+}
+
+/// The packages `root` depends on in `data`, as described by
+/// `CrateData::third_party_packages`.
+fn dependencies_in<'a>(data: &'a Metadata, root: &PackageId, wasm_target: &str) -> Vec<&'a Package> {
+    let resolve = match &data.resolve {
+        Some(resolve) => resolve,
+        None => return Vec::new(),
+    };
+    let package = |id| data.packages.iter().find(|pkg| &pkg.id == id);
+
+    let root = match package(root) {
+        Some(root) => root,
+        None => return Vec::new(),
+    };
+    let mut seen = BTreeSet::new();
+    let mut queue = vec![root];
+    let mut found = Vec::new();
+    while let Some(pkg) = queue.pop() {
+        let node = match resolve.nodes.iter().find(|node| node.id == pkg.id) {
+            Some(node) => node,
+            None => continue,
+        };
+        for dep in node.dependencies.iter().filter_map(package) {
+            let used = pkg.dependencies.iter().any(|d| {
+                let applies = match &d.target {
+                    Some(platform) => {
+                        wasm_target::platform_matches(&platform.to_string(), wasm_target)
+                    }
+                    None => true,
+                };
+                d.name == dep.name && d.kind == DependencyKind::Normal && applies
+            });
+            let proc_macro = dep
+                .targets
+                .iter()
+                .any(|t| t.kind.iter().any(|kind| kind == "proc-macro"));
+            if !used || proc_macro || !seen.insert(&dep.id.repr) {
+                continue;
+            }
+            queue.push(dep);
+            if !data.workspace_members.contains(&dep.id) {
+                found.push(dep);
+            }
+        }
+    }
+    found.sort_by(|a, b| (&a.name, &a.version).cmp(&(&b.name, &b.version)));
+    found
+}
+
+fn git_short_sha(crate_path: &Path) -> Option<String> {
+    child::git_output(crate_path, &["rev-parse", "--short", "HEAD"])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn profiles(toml: &str) -> Result<CargoRustWasmcProfiles, Error> {
+        let mut profiles: CargoRustWasmcProfiles = toml::from_str(toml).unwrap();
+        profiles.resolve_custom_profiles()?;
+        Ok(profiles)
+    }
+
+    fn invalid_profile_message(toml: &str) -> String {
+        let e = profiles(toml).err().expect("the profiles should be invalid");
+        assert_eq!(::error::code_of(&e), Some(ErrorCode::InvalidProfile));
+        e.to_string()
+    }
+
+    #[test]
+    fn custom_profiles_inherit_from_release() {
+        let profiles = profiles(
+            "[faas]\n\
+             wasm-bindgen = { debug-js-glue = true }\n\
+             [plain]\n",
+        )
+        .unwrap();
+        let faas = &profiles.custom["faas"];
+        assert!(faas.wasm_bindgen_debug_js_glue());
+        assert!(faas.wasm_bindgen_demangle_name_section());
+        assert_eq!(faas.wasm_opt_args(), Some(vec!["-O".to_string()]));
+        let plain = &profiles.custom["plain"];
+        assert!(!plain.wasm_bindgen_debug_js_glue());
+        assert_eq!(plain.wasmedgec_args(), Some(Vec::new()));
+    }
+
+    #[test]
+    fn custom_profiles_inherit_in_chains() {
+        let profiles = profiles(
+            "[dev]\n\
+             wasm-opt = [\"-O1\"]\n\
+             [base]\n\
+             inherits = \"dev\"\n\
+             wasmedgec = [\"--generic-binary\"]\n\
+             [faas]\n\
+             inherits = \"base\"\n\
+             wasm-bindgen = { dwarf-debug-info = true }\n",
+        )
+        .unwrap();
+        let faas = &profiles.custom["faas"];
+        assert!(faas.wasm_bindgen_dwarf_debug_info());
+        // From `dev`, through `base`.
+        assert!(faas.wasm_bindgen_debug_js_glue());
+        assert_eq!(faas.wasm_opt_args(), Some(vec!["-O1".to_string()]));
+        // From `base`.
+        assert_eq!(faas.wasmedgec_args(), Some(vec!["--generic-binary".to_string()]));
+    }
+
+    #[test]
+    fn rejects_inheritance_cycles() {
+        let message = invalid_profile_message("[faas]\ninherits = \"faas\"\n");
+        assert!(message.contains("inherits from itself: faas -> faas"), "{}", message);
+
+        let message = invalid_profile_message(
+            "[a]\ninherits = \"b\"\n[b]\ninherits = \"c\"\n[c]\ninherits = \"a\"\n",
+        );
+        assert!(message.contains("a -> b -> c -> a"), "{}", message);
+    }
+
+    #[test]
+    fn rejects_unknown_parents() {
+        let message = invalid_profile_message("[faas]\ninherits = \"missing\"\n");
+        assert!(
+            message.contains("`faas` inherits from `missing`, but there is no"),
+            "{}",
+            message
+        );
+    }
+
+    /// A crate named `demo` in `dir`, with `manifest` after its `[package]`
+    /// table and an empty file at each of the `sources`.
+    fn crate_with(dir: &Path, manifest: &str, sources: &[&str]) -> CrateData {
+        fs::write(
+            dir.join("Cargo.toml"),
+            format!("[package]\nname = \"demo\"\nversion = \"0.1.0\"\n{}", manifest),
+        )
+        .unwrap();
+        for source in sources {
+            let path = dir.join(source);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, "").unwrap();
+        }
+        CrateData::new(dir, None).unwrap()
+    }
+
+    fn demo_crate(dir: &Path) -> CrateData {
+        crate_with(dir, "", &["src/lib.rs"])
+    }
+
+    fn invalid_version(
+        data: &mut CrateData,
+        dir: &Path,
+        version: Option<&str>,
+        prerelease: Option<&str>,
+    ) {
+        let e = data.set_npm_version(version, prerelease, dir, Some(0)).unwrap_err();
+        assert_eq!(::error::code_of(&e), Some(ErrorCode::InvalidNpmVersion));
+    }
+
+    #[test]
+    fn sets_the_npm_version() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut data = demo_crate(dir.path());
+        assert_eq!(data.npm_version(), "0.1.0");
+
+        data.set_npm_version(Some("v1.2.3-beta.1"), None, dir.path(), None).unwrap();
+        assert_eq!(data.npm_version(), "1.2.3-beta.1");
+        data.set_npm_version(None, None, dir.path(), None).unwrap();
+        assert_eq!(data.npm_version(), "0.1.0");
+
+        invalid_version(&mut data, dir.path(), Some("1.2"), None);
+        invalid_version(&mut data, dir.path(), Some("latest"), None);
+        invalid_version(&mut data, dir.path(), None, Some("bad tag"));
+        invalid_version(&mut data, dir.path(), None, Some("nightly+1"));
+        assert_eq!(data.npm_version(), "0.1.0");
+    }
+
+    #[test]
+    fn timestamps_prereleases_outside_git() {
+        let dir = tempfile::tempdir().unwrap();
+        if git_short_sha(dir.path()).is_some() {
+            // The temporary directory is inside a git repository.
+            return;
+        }
+        let mut data = demo_crate(dir.path());
+
+        data.set_npm_version(None, Some("nightly"), dir.path(), Some(1_600_000_000))
+            .unwrap();
+        assert_eq!(data.npm_version(), "0.1.0-nightly.20200913122640");
+
+        // The tag goes before the build metadata, after any prerelease.
+        data.set_npm_version(Some("1.0.0-rc.1+abc"), Some("dev"), dir.path(), Some(0))
+            .unwrap();
+        assert_eq!(data.npm_version(), "1.0.0-rc.1.dev.19700101000000+abc");
+
+        // Without an epoch the current time is used.
+        data.set_npm_version(None, Some("nightly"), dir.path(), None).unwrap();
+        let version = data.npm_version();
+        let timestamp = version.trim_start_matches("0.1.0-nightly.");
+        assert_eq!(timestamp.len(), 14);
+        assert!(timestamp.chars().all(|c| c.is_ascii_digit()));
+    }
+
+    #[test]
+    fn tags_prereleases_with_the_git_commit() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut data = demo_crate(dir.path());
+        let git = |args: &[&str]| {
+            std::process::Command::new("git")
+                .current_dir(dir.path())
+                .env("GIT_AUTHOR_NAME", "test")
+                .env("GIT_AUTHOR_EMAIL", "test@example.com")
+                .env("GIT_COMMITTER_NAME", "test")
+                .env("GIT_COMMITTER_EMAIL", "test@example.com")
+                .args(args)
+                .output()
+                .map(|output| output.status.success())
+                .unwrap_or(false)
+        };
+        if !git(&["init", "-q"]) {
+            // git isn't installed.
+            return;
+        }
+        assert!(git(&["add", "-A"]));
+        assert!(git(&["commit", "-qm", "initial"]));
+
+        let sha = git_short_sha(dir.path()).unwrap();
+        let id = if sha.chars().all(|c| c.is_ascii_digit()) {
+            format!("g{}", sha)
+        } else {
+            sha
+        };
+        data.set_npm_version(None, Some("nightly"), dir.path(), Some(0)).unwrap();
+        assert_eq!(data.npm_version(), format!("0.1.0-nightly.{}", id));
+    }
+
+    fn features(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn maps_targets_and_flags_to_features() {
+        let dir = tempfile::tempdir().unwrap();
+        let data = crate_with(
+            dir.path(),
+            "[features]\n\
+             default = [\"log\"]\n\
+             log = []\n\
+             node = []\n\
+             aot = []\n\
+             ext = []\n\
+             web = []\n\
+             [package.metadata.rustwasmc.features]\n\
+             nodejs = [\"node\"]\n\
+             enable-aot = [\"aot\"]\n\
+             enable-ext = [\"ext\", \"log\"]\n\
+             no-wasi = [\"web\"]\n",
+            &["src/lib.rs"],
+        );
+        assert!(data.mapped_features("wasmedge", false, false, false).is_empty());
+        assert_eq!(data.mapped_features("nodejs", false, false, false), features(&["node"]));
+        assert_eq!(
+            data.mapped_features("wasmedge", false, true, false),
+            features(&["ext", "log"])
+        );
+        assert_eq!(
+            data.mapped_features("nodejs", true, true, true),
+            features(&["node", "aot", "ext", "log", "web"])
+        );
+        assert_eq!(data.mapped_features("deno", true, false, false), features(&["aot"]));
+    }
+
+    #[test]
+    fn lists_the_active_features() {
+        let dir = tempfile::tempdir().unwrap();
+        let data = crate_with(
+            dir.path(),
+            "[features]\ndefault = [\"log\"]\nlog = []\nnode = [\"log\"]\nweb = []\n",
+            &["src/lib.rs"],
+        );
+        let active = |names: &[&str], all_features: bool, no_default_features: bool| {
+            data.active_features(&CargoFeatures {
+                features: features(names),
+                all_features,
+                no_default_features,
+            })
+        };
+        assert_eq!(active(&[], false, false), features(&["log"]));
+        assert_eq!(active(&["web", "log"], false, false), features(&["log", "web"]));
+        assert!(active(&[], false, true).is_empty());
+        // Features enabled by other features aren't listed.
+        assert_eq!(active(&["node"], false, true), features(&["node"]));
+        assert_eq!(active(&[], true, false), features(&["log", "node", "web"]));
+    }
+
+    #[test]
+    fn lists_the_library_and_binaries_of_a_mixed_crate() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(
+            dir.path().join("Cargo.toml"),
+            "[package]\nname = \"mixed-app\"\nversion = \"0.1.0\"\n\
+             [lib]\ncrate-type = [\"cdylib\", \"rlib\"]\n",
+        )
+        .unwrap();
+        for source in &["src/lib.rs", "src/main.rs", "src/bin/tool.rs", "examples/demo.rs"] {
+            let path = dir.path().join(source);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, "").unwrap();
+        }
+        let data = CrateData::new(dir.path(), None).unwrap();
+
+        let mut targets: Vec<(CrateTargetKind, String)> = data
+            .crate_targets()
+            .into_iter()
+            .map(|target| (target.kind, target.name))
+            .collect();
+        targets.sort_by(|a, b| a.1.cmp(&b.1));
+        assert_eq!(
+            targets,
+            vec![
+                (CrateTargetKind::Bin, "mixed-app".to_string()),
+                // The wasm file of the library is named with underscores.
+                (CrateTargetKind::Lib, "mixed_app".to_string()),
+                (CrateTargetKind::Bin, "tool".to_string()),
+            ]
+        );
+        assert!(data.has_cdylib());
+        assert!(data.check_crate_type().is_ok());
+        let mut bins = data.bin_names();
+        bins.sort();
+        assert_eq!(bins, vec!["mixed-app", "tool"]);
+        assert_eq!(data.example_names(), vec!["demo"]);
+    }
+
+    #[test]
+    fn needs_a_cdylib_or_a_binary() {
+        let dir = tempfile::tempdir().unwrap();
+        let data = crate_with(dir.path(), "", &["src/lib.rs", "examples/demo.rs"]);
+        assert!(data.crate_targets().is_empty());
+        let e = data.check_crate_type().unwrap_err();
+        assert_eq!(::error::code_of(&e), Some(ErrorCode::InvalidCrateType));
+
+        let dir = tempfile::tempdir().unwrap();
+        let data = crate_with(dir.path(), "", &["src/main.rs"]);
+        assert!(!data.has_cdylib());
+        assert!(data.check_crate_type().is_ok());
+    }
+
+    fn dependency(name: &str, version: &str) -> Option<(String, String)> {
+        Some((name.to_string(), version.to_string()))
+    }
+
+    #[test]
+    fn declares_the_runtime_nodejs_bindings_need() {
+        let dir = tempfile::tempdir().unwrap();
+        let data = crate_with(dir.path(), "[lib]\ncrate-type = [\"cdylib\"]\n", &["src/lib.rs"]);
+        let aot = format!("~{}", wasmedgec::VERSION);
+
+        let core = |ext, aot, version| data.runtime_dependency("nodejs", ext, aot, version, false);
+        assert_eq!(core(false, false, None), dependency("wasmedge-core", DEFAULT_RUNTIME_VERSION));
+        assert_eq!(
+            core(true, false, None),
+            dependency("wasmedge-extensions", DEFAULT_RUNTIME_VERSION)
+        );
+        // AOT files only load in the WasmEdge release that compiled them.
+        assert_eq!(core(false, true, None), dependency("wasmedge-core", &aot));
+        assert_eq!(core(true, true, None), dependency("wasmedge-extensions", &aot));
+        assert_eq!(core(true, true, Some("0.9.1")), dependency("wasmedge-extensions", "0.9.1"));
+
+        assert_eq!(data.runtime_dependency("nodejs", false, true, None, true), None);
+        assert_eq!(data.runtime_dependency("nodejs", true, false, Some("1"), true), None);
+        for target in &["wasmedge", "deno"] {
+            assert_eq!(data.runtime_dependency(target, true, true, None, false), None);
+        }
+    }
+
+    #[test]
+    fn reads_the_runtime_configuration() {
+        let dir = tempfile::tempdir().unwrap();
+        let data = crate_with(
+            dir.path(),
+            "[lib]\ncrate-type = [\"cdylib\"]\n\
+             [package.metadata.rustwasmc.runtime]\n\
+             wasmedge-core = \"^0.8.1\"\n\
+             wasmedge-extensions = \"=0.8.2\"\n",
+            &["src/lib.rs"],
+        );
+        assert_eq!(
+            data.runtime_dependency("nodejs", false, true, None, false),
+            dependency("wasmedge-core", "^0.8.1")
+        );
+        assert_eq!(
+            data.runtime_dependency("nodejs", true, true, None, false),
+            dependency("wasmedge-extensions", "=0.8.2")
+        );
+        assert_eq!(
+            data.runtime_dependency("nodejs", true, false, Some("^0.9"), false),
+            dependency("wasmedge-extensions", "^0.9")
+        );
+
+        let dir = tempfile::tempdir().unwrap();
+        let data = crate_with(
+            dir.path(),
+            "[lib]\ncrate-type = [\"cdylib\"]\n\
+             [package.metadata.rustwasmc.runtime]\n\
+             add-dependency = false\n",
+            &["src/lib.rs"],
+        );
+        assert_eq!(data.runtime_dependency("nodejs", true, true, Some("1"), false), None);
+
+        // Binaries don't get bindings that load the runtime.
+        let dir = tempfile::tempdir().unwrap();
+        let data = crate_with(dir.path(), "", &["src/main.rs"]);
+        assert_eq!(data.runtime_dependency("nodejs", false, false, None, false), None);
+    }
+
+    #[test]
+    fn finds_the_readme() {
+        let dir = tempfile::tempdir().unwrap();
+        let data = demo_crate(dir.path());
+        assert_eq!(data.crate_readme(), None);
+        assert!(!data.readme_disabled());
+
+        let data = crate_with(dir.path(), "", &["src/lib.rs", "README.md"]);
+        assert_eq!(data.crate_readme(), Some(dir.path().join("README.md")));
+
+        let data = crate_with(dir.path(), "readme = \"docs/intro.md\"\n", &["src/lib.rs"]);
+        assert_eq!(data.crate_readme(), Some(dir.path().join("docs/intro.md")));
+
+        let data = crate_with(dir.path(), "readme = false\n", &["src/lib.rs", "README.md"]);
+        assert_eq!(data.crate_readme(), None);
+        assert!(data.readme_disabled());
+    }
+}
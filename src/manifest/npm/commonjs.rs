@@ -1,13 +1,20 @@
+use manifest::npm::person::Person;
 use manifest::npm::repository::Repository;
+use std::collections::BTreeMap;
+use manifest::npm::BuildMetadata;
 
 #[derive(Serialize)]
 pub struct CommonJSPackage {
     pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub author: Option<Person>,
     #[serde(skip_serializing_if = "Vec::is_empty")]
-    pub collaborators: Vec<String>,
+    pub contributors: Vec<Person>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub description: Option<String>,
     pub version: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub keywords: Vec<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub license: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -19,4 +26,10 @@ pub struct CommonJSPackage {
     pub homepage: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub types: Option<String>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub dependencies: BTreeMap<String, String>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub engines: BTreeMap<String, String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rustwasmc: Option<BuildMetadata>,
 }
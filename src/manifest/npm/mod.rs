@@ -1,8 +1,16 @@
 mod commonjs;
+pub mod name;
+pub mod person;
 pub mod repository;
 
 pub use self::commonjs::CommonJSPackage;
 
+/// How the package was built, recorded under the `rustwasmc` key.
+#[derive(Serialize)]
+pub struct BuildMetadata {
+    pub features: Vec<String>,
+}
+
 #[derive(Serialize)]
 #[serde(untagged)]
 pub enum NpmPackage {
@@ -0,0 +1,136 @@
+use error::ErrorCode;
+use failure::Error;
+
+const MAX_LENGTH: usize = 214;
+const BLACKLIST: &[&str] = &["node_modules", "favicon.ico"];
+/// The Node.js core modules, which npm doesn't allow as new package names.
+/// Those starting with `_` or containing `/` are rejected anyway.
+const NODE_BUILTINS: &[&str] = &[
+    "assert", "async_hooks", "buffer", "child_process", "cluster", "console", "constants",
+    "crypto", "dgram", "diagnostics_channel", "dns", "domain", "events", "fs", "http", "http2",
+    "https", "inspector", "module", "net", "os", "path", "perf_hooks", "process", "punycode",
+    "querystring", "readline", "repl", "stream", "string_decoder", "sys", "timers", "tls",
+    "trace_events", "tty", "url", "util", "v8", "vm", "wasi", "worker_threads", "zlib",
+];
+
+/// Check `name` against the npm package naming rules. `name` may be scoped,
+/// as in `@scope/name`.
+pub fn validate(name: &str) -> Result<(), Error> {
+    if name.len() > MAX_LENGTH {
+        bail_code!(
+            ErrorCode::InvalidNpmName,
+            "npm package name `{}` is longer than {} characters",
+            name,
+            MAX_LENGTH
+        );
+    }
+    if let Some(scoped) = name.strip_prefix('@') {
+        let mut parts = scoped.splitn(2, '/');
+        let scope = parts.next().unwrap_or("");
+        match parts.next() {
+            Some(package) => {
+                validate_part(name, scope, "scope")?;
+                validate_part(name, package, "name")
+            }
+            None => bail_code!(ErrorCode::InvalidNpmName, "npm package name `{}` has a scope but no name", name),
+        }
+    } else {
+        if BLACKLIST.contains(&name) {
+            bail_code!(ErrorCode::InvalidNpmName, "npm package name `{}` is not allowed", name);
+        }
+        if NODE_BUILTINS.contains(&name) {
+            bail_code!(
+                ErrorCode::InvalidNpmName,
+                "npm package name `{}` is not allowed: it is a Node.js core module name",
+                name
+            );
+        }
+        validate_part(name, name, "name")
+    }
+}
+
+fn validate_part(name: &str, part: &str, what: &str) -> Result<(), Error> {
+    if part.is_empty() {
+        bail_code!(ErrorCode::InvalidNpmName, "npm package name `{}` has an empty {}", name, what);
+    }
+    if part.starts_with('.') || part.starts_with('_') {
+        bail_code!(
+            ErrorCode::InvalidNpmName,
+            "npm package name `{}` is invalid: the {} can't start with `.` or `_`",
+            name,
+            what
+        );
+    }
+    if part.chars().any(|c| c.is_ascii_uppercase()) {
+        bail_code!(
+            ErrorCode::InvalidNpmName,
+            "npm package name `{}` is invalid: the {} must be lowercase",
+            name,
+            what
+        );
+    }
+    if let Some(c) = part
+        .chars()
+        .find(|c| !(c.is_ascii_alphanumeric() || *c == '-' || *c == '.' || *c == '_'))
+    {
+        bail_code!(
+            ErrorCode::InvalidNpmName,
+            "npm package name `{}` is invalid: the {} contains `{}`, which is not URL-safe",
+            name,
+            what,
+            c
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rejects(name: &str, message: &str) {
+        let error = validate(name).unwrap_err().to_string();
+        assert!(error.contains(message), "`{}`: {}", name, error);
+    }
+
+    #[test]
+    fn accepts_valid_names() {
+        for name in &["demo", "demo-fn", "demo_fn", "demo.js", "d3", "@scope/demo", "@my-org/a.b_c"] {
+            assert!(validate(name).is_ok(), "{}", name);
+        }
+        // Core module names are only reserved without a scope.
+        assert!(validate("@scope/http").is_ok());
+        assert!(validate(&"a".repeat(MAX_LENGTH)).is_ok());
+    }
+
+    #[test]
+    fn rejects_reserved_names() {
+        rejects("node_modules", "is not allowed");
+        rejects("favicon.ico", "is not allowed");
+        for name in &["http", "fs", "events", "worker_threads"] {
+            rejects(name, "Node.js core module");
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_names() {
+        rejects(&"a".repeat(MAX_LENGTH + 1), "longer than 214 characters");
+        rejects("", "has an empty name");
+        rejects("@scope", "has a scope but no name");
+        rejects("@/demo", "has an empty scope");
+        rejects("@scope/", "has an empty name");
+        rejects(".demo", "can't start with `.` or `_`");
+        rejects("@_scope/demo", "the scope can't start");
+        rejects("Demo", "must be lowercase");
+        rejects("@scope/Demo", "the name must be lowercase");
+        rejects("de mo", "contains ` `");
+        rejects("demo/fn", "contains `/`");
+        rejects("@scope/a/b", "contains `/`");
+    }
+
+    #[test]
+    fn reports_the_error_code() {
+        let error = validate("http").unwrap_err();
+        assert_eq!(::error::code_of(&error), Some(ErrorCode::InvalidNpmName));
+    }
+}
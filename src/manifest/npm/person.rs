@@ -0,0 +1,39 @@
+/// A package.json `author` or `contributors` entry.
+#[derive(Serialize)]
+pub struct Person {
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub email: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+}
+
+impl Person {
+    /// Parse a Cargo `authors` entry of the form `Name <email> (url)`, where
+    /// the email and url are optional.
+    pub fn parse(author: &str) -> Person {
+        fn delimited(s: &str, open: char, close: char) -> Option<(usize, usize)> {
+            let start = s.find(open)?;
+            let end = start + s[start..].find(close)?;
+            Some((start, end))
+        }
+
+        let mut name = author.to_string();
+        let mut email = None;
+        let mut url = None;
+        if let Some((start, end)) = delimited(&name, '(', ')') {
+            url = Some(name[start + 1..end].trim().to_string());
+            name.replace_range(start..=end, "");
+        }
+        if let Some((start, end)) = delimited(&name, '<', '>') {
+            email = Some(name[start + 1..end].trim().to_string());
+            name.replace_range(start..=end, "");
+        }
+
+        Person {
+            name: name.trim().to_string(),
+            email: email.filter(|e| !e.is_empty()),
+            url: url.filter(|u| !u.is_empty()),
+        }
+    }
+}
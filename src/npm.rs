@@ -0,0 +1,453 @@
+//! Publishing packages to an npm-compatible registry.
+
+use base64;
+use failure::{Error, ResultExt};
+use pack::Tarball;
+use reqwest::header::{AUTHORIZATION, CONTENT_TYPE};
+use serde_json::{json, Map, Value};
+use std::env;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// The registry used when none is configured.
+pub const DEFAULT_REGISTRY: &str = "https://registry.npmjs.org/";
+
+/// Who can install a published package.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Access {
+    /// Anyone can install the package.
+    Public,
+    /// Only members of the scope can install the package.
+    Restricted,
+}
+
+impl FromStr for Access {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "public" => Ok(Access::Public),
+            "restricted" => Ok(Access::Restricted),
+            _ => bail!("Unknown access level: {}. Use `public` or `restricted`.", s),
+        }
+    }
+}
+
+impl fmt::Display for Access {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Access::Public => write!(f, "public"),
+            Access::Restricted => write!(f, "restricted"),
+        }
+    }
+}
+
+/// Settings read from `.npmrc` files.
+///
+/// The crate's own `.npmrc` takes precedence over the user's `~/.npmrc`.
+/// `${VAR}` references are expanded from the environment.
+#[derive(Debug, Default)]
+pub struct Npmrc {
+    entries: Vec<(String, String)>,
+}
+
+impl Npmrc {
+    /// Read the `.npmrc` files that apply to the crate at `crate_path`.
+    pub fn load(crate_path: &Path) -> Result<Npmrc, Error> {
+        let mut paths = vec![crate_path.join(".npmrc")];
+        match env::var_os("NPM_CONFIG_USERCONFIG") {
+            Some(path) => paths.push(PathBuf::from(path)),
+            None => paths.extend(dirs::home_dir().map(|home| home.join(".npmrc"))),
+        }
+
+        let mut npmrc = Npmrc::default();
+        for path in paths {
+            if path.is_file() {
+                let contents = fs::read_to_string(&path)
+                    .with_context(|_| format!("failed to read: {}", path.display()))?;
+                npmrc.parse(&contents, |name| env::var(name).ok());
+            }
+        }
+        Ok(npmrc)
+    }
+
+    /// Add the settings in `contents`, expanding `${VAR}` references with
+    /// `vars`.
+    fn parse<F>(&mut self, contents: &str, vars: F)
+    where
+        F: Fn(&str) -> Option<String>,
+    {
+        for line in contents.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+                continue;
+            }
+            if let Some(eq) = line.find('=') {
+                let key = line[..eq].trim().to_string();
+                let value = line[eq + 1..].trim().trim_matches('"');
+                self.entries.push((key, expand_env(value, &vars)));
+            }
+        }
+    }
+
+    /// Get the first value set for `key`.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.entries
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// The registry configured for `scope`, falling back to the default
+    /// registry setting.
+    pub fn registry(&self, scope: Option<&str>) -> Option<&str> {
+        scope
+            .and_then(|scope| self.get(&format!("@{}:registry", scope)))
+            .or_else(|| self.get("registry"))
+    }
+
+    /// The `_authToken` configured for `registry`. Like npm, this matches
+    /// the registry's host and the longest configured prefix of its path.
+    pub fn auth_token(&self, registry: &str) -> Option<&str> {
+        let mut prefix = nerf_dart(registry);
+        loop {
+            if let Some(token) = self.get(&format!("{}:_authToken", prefix)) {
+                return Some(token);
+            }
+            // Drop the last path segment, keeping the trailing `/`.
+            let trimmed = prefix.trim_end_matches('/');
+            match trimmed.rfind('/') {
+                Some(idx) if idx > 1 => prefix = trimmed[..=idx].to_string(),
+                _ => return None,
+            }
+        }
+    }
+}
+
+/// Expand `${VAR}` references in an `.npmrc` value, looking each name up
+/// with `vars`. Unset variables expand to nothing.
+fn expand_env<F>(value: &str, vars: F) -> String
+where
+    F: Fn(&str) -> Option<String>,
+{
+    let mut out = String::new();
+    let mut rest = value;
+    while let Some(start) = rest.find("${") {
+        match rest[start..].find('}') {
+            Some(len) => {
+                out.push_str(&rest[..start]);
+                let name = &rest[start + 2..start + len];
+                out.push_str(&vars(name).unwrap_or_default());
+                rest = &rest[start + len + 1..];
+            }
+            None => break,
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// The `//host/path/` form npm uses to key credentials for a registry.
+fn nerf_dart(registry: &str) -> String {
+    let without_scheme = match registry.find("://") {
+        Some(idx) => &registry[idx + 1..],
+        None => registry,
+    };
+    let mut nerfed = without_scheme.to_string();
+    if !nerfed.ends_with('/') {
+        nerfed.push('/');
+    }
+    nerfed
+}
+
+/// The scope of an npm package name, without the `@`.
+pub fn package_scope(name: &str) -> Option<&str> {
+    name.strip_prefix('@')
+        .and_then(|scoped| scoped.split('/').next())
+}
+
+/// Publish a packed tarball to `registry`.
+///
+/// `manifest` is the `package.json` that was packed into `tarball`.
+pub fn publish(
+    registry: &str,
+    token: Option<&str>,
+    manifest: &Value,
+    tarball: &Tarball,
+    tag: &str,
+    access: Option<Access>,
+) -> Result<(), Error> {
+    let registry = if registry.ends_with('/') {
+        registry.to_string()
+    } else {
+        format!("{}/", registry)
+    };
+    let name = &tarball.name;
+    let version = &tarball.version;
+    let tarball_name = format!("{}-{}.tgz", name, version);
+    let contents = fs::read(&tarball.path)
+        .with_context(|_| format!("failed to read: {}", tarball.path.display()))?;
+
+    let mut version_manifest = match manifest {
+        Value::Object(map) => map.clone(),
+        _ => Map::new(),
+    };
+    version_manifest.insert("_id".to_string(), json!(format!("{}@{}", name, version)));
+    version_manifest.insert(
+        "dist".to_string(),
+        json!({
+            "shasum": tarball.shasum,
+            "integrity": tarball.integrity,
+            "tarball": format!("{}{}/-/{}", registry, name, tarball_name),
+        }),
+    );
+
+    let mut versions = Map::new();
+    versions.insert(version.clone(), Value::Object(version_manifest));
+    let mut dist_tags = Map::new();
+    dist_tags.insert(tag.to_string(), json!(version));
+    let mut attachments = Map::new();
+    attachments.insert(
+        tarball_name,
+        json!({
+            "content_type": "application/octet-stream",
+            "data": base64::encode(&contents),
+            "length": contents.len(),
+        }),
+    );
+
+    let body = json!({
+        "_id": name,
+        "name": name,
+        "description": manifest.get("description").cloned().unwrap_or(Value::Null),
+        "dist-tags": dist_tags,
+        "versions": versions,
+        "access": access.map(|a| a.to_string()),
+        "_attachments": attachments,
+    });
+
+    let url = format!("{}{}", registry, name.replacen('/', "%2f", 1));
+    let client = reqwest::Client::new();
+    let mut request = client
+        .put(&url)
+        .header(CONTENT_TYPE, "application/json")
+        .body(serde_json::to_string(&body)?);
+    if let Some(token) = token {
+        request = request.header(AUTHORIZATION, format!("Bearer {}", token));
+    }
+    let mut response = request
+        .send()
+        .with_context(|_| format!("failed to connect to the registry at {}", registry))?;
+
+    if !response.status().is_success() {
+        let text = response.text().unwrap_or_default();
+        let reason = serde_json::from_str::<Value>(&text)
+            .ok()
+            .and_then(|v| v.get("error").and_then(|e| e.as_str()).map(String::from))
+            .unwrap_or(text);
+        bail!(
+            "Publishing {}@{} to {} failed with {}: {}",
+            name,
+            version,
+            registry,
+            response.status(),
+            reason.trim()
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pack;
+    use std::collections::HashMap;
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::sync::mpsc;
+    use std::thread;
+    use tempfile;
+
+    /// A request received by `MockRegistry`.
+    struct Received {
+        method: String,
+        path: String,
+        headers: HashMap<String, String>,
+        body: Value,
+    }
+
+    /// A registry stand-in on 127.0.0.1 that accepts one publish.
+    struct MockRegistry {
+        url: String,
+        received: mpsc::Receiver<Received>,
+    }
+
+    impl MockRegistry {
+        fn start(path: &str) -> MockRegistry {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let url = format!("http://{}{}", listener.local_addr().unwrap(), path);
+            let (sender, received) = mpsc::channel();
+            thread::spawn(move || {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                let mut parts = line.split_whitespace();
+                let method = parts.next().unwrap().to_string();
+                let path = parts.next().unwrap().to_string();
+                let mut headers = HashMap::new();
+                loop {
+                    line.clear();
+                    reader.read_line(&mut line).unwrap();
+                    let header = line.trim_end();
+                    if header.is_empty() {
+                        break;
+                    }
+                    let colon = header.find(':').unwrap();
+                    headers.insert(
+                        header[..colon].to_ascii_lowercase(),
+                        header[colon + 1..].trim().to_string(),
+                    );
+                }
+                let length = headers["content-length"].parse().unwrap();
+                let mut body = vec![0; length];
+                reader.read_exact(&mut body).unwrap();
+                stream
+                    .write_all(b"HTTP/1.1 201 Created\r\nContent-Length: 2\r\nConnection: close\r\n\r\n{}")
+                    .unwrap();
+                sender
+                    .send(Received {
+                        method,
+                        path,
+                        headers,
+                        body: serde_json::from_slice(&body).unwrap(),
+                    })
+                    .unwrap();
+            });
+            MockRegistry { url, received }
+        }
+    }
+
+    fn packed(out_dir: &Path, name: &str) -> (Tarball, Value) {
+        let manifest = json!({
+            "name": name,
+            "version": "1.2.3",
+            "description": "A test package",
+            "files": ["demo.wasm"],
+        });
+        fs::write(out_dir.join("package.json"), manifest.to_string()).unwrap();
+        fs::write(out_dir.join("demo.wasm"), b"\0asm\x01\0\0\0").unwrap();
+        let tarball = pack::pack(out_dir, &out_dir.join("dist")).unwrap();
+        (tarball, manifest)
+    }
+
+    #[test]
+    fn publishes_unscoped_package() {
+        let dir = tempfile::tempdir().unwrap();
+        let (tarball, manifest) = packed(dir.path(), "demo");
+        let registry = MockRegistry::start("/");
+
+        publish(&registry.url, Some("t0ken"), &manifest, &tarball, "next", None).unwrap();
+
+        let received = registry.received.recv().unwrap();
+        assert_eq!(received.method, "PUT");
+        assert_eq!(received.path, "/demo");
+        assert_eq!(received.headers["authorization"], "Bearer t0ken");
+        assert_eq!(received.headers["content-type"], "application/json");
+        let body = received.body;
+        assert_eq!(body["name"], "demo");
+        assert_eq!(body["description"], "A test package");
+        assert_eq!(body["dist-tags"], json!({ "next": "1.2.3" }));
+        assert_eq!(body["access"], Value::Null);
+        let version = &body["versions"]["1.2.3"];
+        assert_eq!(version["_id"], "demo@1.2.3");
+        assert_eq!(version["dist"]["shasum"], json!(tarball.shasum));
+        assert_eq!(version["dist"]["integrity"], json!(tarball.integrity));
+        assert_eq!(
+            version["dist"]["tarball"],
+            json!(format!("{}demo/-/demo-1.2.3.tgz", registry.url))
+        );
+
+        let attachment = &body["_attachments"]["demo-1.2.3.tgz"];
+        let contents = fs::read(&tarball.path).unwrap();
+        assert_eq!(attachment["content_type"], "application/octet-stream");
+        assert_eq!(attachment["length"], json!(contents.len()));
+        let data = base64::decode(attachment["data"].as_str().unwrap()).unwrap();
+        assert_eq!(data, contents);
+    }
+
+    #[test]
+    fn publishes_scoped_package_with_npmrc_token() {
+        let dir = tempfile::tempdir().unwrap();
+        let (tarball, manifest) = packed(dir.path(), "@scope/demo");
+        // The registry lives under a path, and the token is configured for
+        // the host, so the nerf-dart lookup has to drop the path.
+        let registry = MockRegistry::start("/npm/");
+        let host = registry.url.trim_start_matches("http:").trim_end_matches("npm/");
+
+        let mut npmrc = Npmrc::default();
+        npmrc.parse(
+            &format!(
+                "@scope:registry={}\n{}:_authToken=${{PUBLISH_TOKEN}}\n",
+                registry.url, host
+            ),
+            |name| match name {
+                "PUBLISH_TOKEN" => Some("from-env".to_string()),
+                _ => None,
+            },
+        );
+        let url = npmrc.registry(package_scope(&tarball.name)).unwrap();
+        assert_eq!(url, registry.url);
+        let token = npmrc.auth_token(url);
+        assert_eq!(token, Some("from-env"));
+
+        publish(url, token, &manifest, &tarball, "latest", Some(Access::Restricted)).unwrap();
+
+        let received = registry.received.recv().unwrap();
+        assert_eq!(received.method, "PUT");
+        assert_eq!(received.path, "/npm/@scope%2fdemo");
+        assert_eq!(received.headers["authorization"], "Bearer from-env");
+        let body = received.body;
+        assert_eq!(body["_id"], "@scope/demo");
+        assert_eq!(body["dist-tags"], json!({ "latest": "1.2.3" }));
+        assert_eq!(body["access"], "restricted");
+        assert_eq!(
+            body["versions"]["1.2.3"]["dist"]["tarball"],
+            json!(format!("{}@scope/demo/-/@scope/demo-1.2.3.tgz", registry.url))
+        );
+        let attachment = &body["_attachments"]["@scope/demo-1.2.3.tgz"];
+        let data = base64::decode(attachment["data"].as_str().unwrap()).unwrap();
+        assert_eq!(data, fs::read(&tarball.path).unwrap());
+    }
+
+    #[test]
+    fn auth_token_uses_longest_matching_prefix() {
+        let mut npmrc = Npmrc::default();
+        npmrc.parse(
+            "//registry.example.com/:_authToken=host\n\
+             //registry.example.com/team/:_authToken=team\n",
+            |_| None,
+        );
+        assert_eq!(npmrc.auth_token("https://registry.example.com/team/npm/"), Some("team"));
+        assert_eq!(npmrc.auth_token("https://registry.example.com/other"), Some("host"));
+        assert_eq!(npmrc.auth_token("https://elsewhere.example.com/"), None);
+    }
+
+    #[test]
+    fn expands_environment_variables() {
+        let vars = |name: &str| match name {
+            "VAR" => Some("value".to_string()),
+            _ => None,
+        };
+        assert_eq!(expand_env("a-${VAR}-b", vars), "a-value-b");
+        assert_eq!(expand_env("${VAR}${VAR}", vars), "valuevalue");
+        assert_eq!(expand_env("${UNSET}", vars), "");
+        assert_eq!(expand_env("${unterminated", vars), "${unterminated");
+
+        let mut npmrc = Npmrc::default();
+        npmrc.parse("# comment\n; comment\ntoken = \"${VAR}\"\n", vars);
+        assert_eq!(npmrc.get("token"), Some("value"));
+    }
+}
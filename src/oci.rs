@@ -0,0 +1,442 @@
+//! Packaging WASI binaries as OCI images.
+//!
+//! The image has a `wasi/wasm` platform and the binary's `.wasm` as its
+//! entrypoint, so it can be run by containerd wasm shims. When an AOT `.so`
+//! was compiled by `wasmedgec` it is added as a second layer.
+
+use failure::{Error, ResultExt};
+use hex;
+use manifest::CrateData;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use tar::{Builder, EntryType, Header};
+
+const MANIFEST_MEDIA_TYPE: &str = "application/vnd.oci.image.manifest.v1+json";
+const CONFIG_MEDIA_TYPE: &str = "application/vnd.oci.image.config.v1+json";
+const LAYER_MEDIA_TYPE: &str = "application/vnd.oci.image.layer.v1.tar";
+
+/// Pick the binary to package: the only one in `bins`, or the only binary of
+/// the crate when `bins` is empty.
+pub fn select_bin(crate_data: &CrateData, bins: &[String]) -> Result<String, Error> {
+    let candidates = if bins.is_empty() {
+        crate_data.bin_names()
+    } else {
+        bins.to_vec()
+    };
+    match candidates.len() {
+        1 => Ok(candidates[0].clone()),
+        0 => bail!("An OCI image can only be built for a crate with a [[bin]] target."),
+        _ => bail!(
+            "An OCI image holds one binary, but the crate has several: {}. Select one with `--bin`.",
+            candidates.join(", ")
+        ),
+    }
+}
+
+/// Where the image is written when no destination is given: an `oci`
+/// directory, or `<bin>.oci.tar` for an archive, in the out dir.
+pub fn default_destination(out_dir: &Path, bin: &str, archive: bool) -> PathBuf {
+    if archive {
+        out_dir.join(format!("{}.oci.tar", bin))
+    } else {
+        out_dir.join("oci")
+    }
+}
+
+/// A WASI binary to package as an OCI image.
+#[derive(Debug)]
+pub struct OciImage {
+    /// The image reference, e.g. `registry.example.com/app:1.0`.
+    pub reference: String,
+    /// The binary's `.wasm` file.
+    pub wasm: PathBuf,
+    /// The AOT `.so` compiled from `wasm`, if any.
+    pub aot: Option<PathBuf>,
+    /// Annotations added to the image manifest.
+    pub annotations: BTreeMap<String, String>,
+    /// The modification time of every file in the layers.
+    pub mtime: u64,
+}
+
+impl OciImage {
+    /// Describe the image for the binary `bin`, whose `.wasm` was copied to
+    /// `out_dir` by `rustwasmc build`.
+    pub fn for_bin(
+        crate_data: &CrateData,
+        out_dir: &Path,
+        bin: &str,
+        reference: &str,
+        mtime: u64,
+    ) -> Result<OciImage, Error> {
+        let wasm = out_dir.join(bin).with_extension("wasm");
+        if !wasm.is_file() {
+            bail!(
+                "{} does not exist. Run `rustwasmc build` first.",
+                wasm.display()
+            );
+        }
+        let aot = Some(wasm.with_extension("so")).filter(|so| so.is_file());
+
+        let mut annotations = BTreeMap::new();
+        let mut annotate = |key: &str, value: &Option<String>| {
+            if let Some(value) = value {
+                annotations.insert(format!("org.opencontainers.image.{}", key), value.clone());
+            }
+        };
+        annotate("title", &Some(bin.to_string()));
+        annotate("version", &Some(crate_data.crate_version()));
+        annotate("description", crate_data.crate_description());
+        annotate("licenses", crate_data.crate_license());
+        annotate("source", crate_data.crate_repository());
+        // Tells crun and other WasmEdge-aware runtimes to run the image as wasm.
+        annotations.insert(
+            "module.wasm.image/variant".to_string(),
+            "compat-smart".to_string(),
+        );
+
+        Ok(OciImage {
+            reference: reference.to_string(),
+            wasm,
+            aot,
+            annotations,
+            mtime,
+        })
+    }
+
+    /// The tag of the image reference, defaulting to `latest`.
+    pub fn tag(&self) -> &str {
+        let name = match self.reference.rfind('/') {
+            Some(idx) => &self.reference[idx + 1..],
+            None => &self.reference,
+        };
+        let name = name.split('@').next().unwrap_or(name);
+        match name.rfind(':') {
+            Some(idx) => &name[idx + 1..],
+            None => "latest",
+        }
+    }
+
+    /// Write the image as an OCI image layout directory at `dest`.
+    pub fn write_layout(&self, dest: &Path) -> Result<(), Error> {
+        for (path, contents) in self.layout()? {
+            let path = dest.join(path);
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::write(&path, contents)
+                .with_context(|_| format!("failed to write: {}", path.display()))?;
+        }
+        Ok(())
+    }
+
+    /// Write the image as a tarball of an OCI image layout at `dest`, as
+    /// accepted by `ctr images import`.
+    pub fn write_archive(&self, dest: &Path) -> Result<(), Error> {
+        let file =
+            File::create(dest).with_context(|_| format!("failed to create: {}", dest.display()))?;
+        let mut tar = Builder::new(file);
+        for (path, contents) in self.layout()? {
+            append(&mut tar, &path, &contents, 0o644, self.mtime)?;
+        }
+        tar.into_inner()?;
+        Ok(())
+    }
+
+    /// The files of the image layout, in the order they should be written.
+    fn layout(&self) -> Result<Vec<(String, Vec<u8>)>, Error> {
+        let mut blobs = Vec::new();
+        let mut layers = Vec::new();
+        let mut entrypoint = None;
+        for path in Some(&self.wasm).into_iter().chain(self.aot.as_ref()) {
+            let name = path.file_name().unwrap().to_string_lossy().to_string();
+            let contents =
+                fs::read(path).with_context(|_| format!("failed to read: {}", path.display()))?;
+            let mut layer = Builder::new(Vec::new());
+            append(&mut layer, &name, &contents, 0o755, self.mtime)?;
+            let layer = layer.into_inner()?;
+            layers.push(descriptor(LAYER_MEDIA_TYPE, &layer));
+            blobs.push(layer);
+            entrypoint.get_or_insert(format!("/{}", name));
+        }
+
+        let diff_ids: Vec<Value> = layers.iter().map(|l| l["digest"].clone()).collect();
+        let config = serde_json::to_vec(&json!({
+            "architecture": "wasm",
+            "os": "wasi",
+            "config": {
+                "Entrypoint": [entrypoint],
+            },
+            "rootfs": {
+                "type": "layers",
+                "diff_ids": diff_ids,
+            },
+        }))?;
+        let config_descriptor = descriptor(CONFIG_MEDIA_TYPE, &config);
+        blobs.push(config);
+
+        let manifest = serde_json::to_vec(&json!({
+            "schemaVersion": 2,
+            "mediaType": MANIFEST_MEDIA_TYPE,
+            "config": config_descriptor,
+            "layers": layers,
+            "annotations": self.annotations,
+        }))?;
+        let mut manifest_descriptor = descriptor(MANIFEST_MEDIA_TYPE, &manifest);
+        manifest_descriptor["platform"] = json!({ "architecture": "wasm", "os": "wasi" });
+        manifest_descriptor["annotations"] = json!({
+            "io.containerd.image.name": self.reference,
+            "org.opencontainers.image.ref.name": self.tag(),
+        });
+        blobs.push(manifest);
+
+        let index = serde_json::to_vec_pretty(&json!({
+            "schemaVersion": 2,
+            "manifests": [manifest_descriptor],
+        }))?;
+
+        let mut files = vec![(
+            "oci-layout".to_string(),
+            br#"{"imageLayoutVersion":"1.0.0"}"#.to_vec(),
+        )];
+        for blob in blobs {
+            files.push((format!("blobs/sha256/{}", sha256(&blob)), blob));
+        }
+        files.push(("index.json".to_string(), index));
+        Ok(files)
+    }
+}
+
+fn sha256(contents: &[u8]) -> String {
+    hex::encode(Sha256::digest(contents))
+}
+
+fn descriptor(media_type: &str, contents: &[u8]) -> Value {
+    json!({
+        "mediaType": media_type,
+        "digest": format!("sha256:{}", sha256(contents)),
+        "size": contents.len(),
+    })
+}
+
+fn append<W: std::io::Write>(
+    tar: &mut Builder<W>,
+    path: &str,
+    contents: &[u8],
+    mode: u32,
+    mtime: u64,
+) -> Result<(), Error> {
+    let mut header = Header::new_ustar();
+    header.set_entry_type(EntryType::Regular);
+    header.set_size(contents.len() as u64);
+    header.set_mode(mode);
+    header.set_mtime(mtime);
+    header.set_uid(0);
+    header.set_gid(0);
+    tar.append_data(&mut header, path, contents)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+    use tar::Archive;
+    use tempfile;
+
+    fn demo_crate(dir: &Path) -> CrateData {
+        fs::write(
+            dir.join("Cargo.toml"),
+            "[package]\n\
+             name = \"demo\"\n\
+             version = \"1.2.3\"\n\
+             description = \"A demo\"\n\
+             license = \"MIT\"\n\
+             repository = \"https://github.com/example/demo\"\n",
+        )
+        .unwrap();
+        fs::create_dir_all(dir.join("src")).unwrap();
+        fs::write(dir.join("src/main.rs"), "fn main() {}").unwrap();
+        CrateData::new(dir, None).unwrap()
+    }
+
+    fn image(reference: &str) -> OciImage {
+        OciImage {
+            reference: reference.to_string(),
+            wasm: PathBuf::from("app.wasm"),
+            aot: None,
+            annotations: BTreeMap::new(),
+            mtime: 0,
+        }
+    }
+
+    /// Read the blob `descriptor` points to, checking its digest and size.
+    fn blob<'a>(files: &'a BTreeMap<String, Vec<u8>>, descriptor: &Value) -> &'a [u8] {
+        let digest = descriptor["digest"].as_str().unwrap();
+        let path = format!("blobs/sha256/{}", digest.trim_start_matches("sha256:"));
+        let contents = &files[&path];
+        assert_eq!(format!("sha256:{}", sha256(contents)), digest);
+        assert_eq!(descriptor["size"], contents.len());
+        contents
+    }
+
+    /// The name, mode, mtime and contents of the single file in `layer`.
+    fn layer_file(layer: &[u8]) -> (String, u32, u64, Vec<u8>) {
+        let mut archive = Archive::new(layer);
+        let mut entries = archive.entries().unwrap();
+        let mut entry = entries.next().unwrap().unwrap();
+        let name = entry.path().unwrap().display().to_string();
+        let mode = entry.header().mode().unwrap();
+        let mtime = entry.header().mtime().unwrap();
+        let mut contents = Vec::new();
+        entry.read_to_end(&mut contents).unwrap();
+        assert!(entries.next().is_none());
+        (name, mode, mtime, contents)
+    }
+
+    #[test]
+    fn reads_the_tag_of_the_reference() {
+        assert_eq!(image("app").tag(), "latest");
+        assert_eq!(image("app:1.0").tag(), "1.0");
+        assert_eq!(image("host:5000/app").tag(), "latest");
+        assert_eq!(image("host:5000/team/app:2").tag(), "2");
+        assert_eq!(image("app@sha256:0123abcd").tag(), "latest");
+        assert_eq!(image("host:5000/app:2@sha256:0123abcd").tag(), "2");
+    }
+
+    #[test]
+    fn lays_out_a_wasi_image() {
+        let dir = tempfile::tempdir().unwrap();
+        let crate_data = demo_crate(dir.path());
+        let out_dir = dir.path().join("pkg");
+        fs::create_dir(&out_dir).unwrap();
+        fs::write(out_dir.join("demo.wasm"), b"\0asm").unwrap();
+
+        let image =
+            OciImage::for_bin(&crate_data, &out_dir, "demo", "host:5000/demo:1.2", 1_600_000_000)
+                .unwrap();
+        assert_eq!(image.aot, None);
+        let files: BTreeMap<String, Vec<u8>> = image.layout().unwrap().into_iter().collect();
+        assert_eq!(files["oci-layout"], br#"{"imageLayoutVersion":"1.0.0"}"#.to_vec());
+        // The blobs of the layer, config and manifest.
+        assert_eq!(files.len(), 5);
+
+        let index: Value = serde_json::from_slice(&files["index.json"]).unwrap();
+        assert_eq!(index["schemaVersion"], 2);
+        let manifests = index["manifests"].as_array().unwrap();
+        assert_eq!(manifests.len(), 1);
+        let descriptor = &manifests[0];
+        assert_eq!(descriptor["mediaType"], MANIFEST_MEDIA_TYPE);
+        assert_eq!(descriptor["platform"], json!({ "architecture": "wasm", "os": "wasi" }));
+        assert_eq!(
+            descriptor["annotations"],
+            json!({
+                "io.containerd.image.name": "host:5000/demo:1.2",
+                "org.opencontainers.image.ref.name": "1.2",
+            })
+        );
+
+        let manifest: Value = serde_json::from_slice(blob(&files, descriptor)).unwrap();
+        assert_eq!(manifest["mediaType"], MANIFEST_MEDIA_TYPE);
+        assert_eq!(manifest["config"]["mediaType"], CONFIG_MEDIA_TYPE);
+        assert_eq!(
+            manifest["annotations"],
+            json!({
+                "module.wasm.image/variant": "compat-smart",
+                "org.opencontainers.image.description": "A demo",
+                "org.opencontainers.image.licenses": "MIT",
+                "org.opencontainers.image.source": "https://github.com/example/demo",
+                "org.opencontainers.image.title": "demo",
+                "org.opencontainers.image.version": "1.2.3",
+            })
+        );
+
+        let config: Value = serde_json::from_slice(blob(&files, &manifest["config"])).unwrap();
+        assert_eq!(config["architecture"], "wasm");
+        assert_eq!(config["os"], "wasi");
+        assert_eq!(config["config"]["Entrypoint"], json!(["/demo.wasm"]));
+
+        let layers = manifest["layers"].as_array().unwrap();
+        assert_eq!(layers.len(), 1);
+        assert_eq!(layers[0]["mediaType"], LAYER_MEDIA_TYPE);
+        assert_eq!(config["rootfs"]["type"], "layers");
+        assert_eq!(config["rootfs"]["diff_ids"], json!([layers[0]["digest"]]));
+        assert_eq!(
+            layer_file(blob(&files, &layers[0])),
+            ("demo.wasm".to_string(), 0o755, 1_600_000_000, b"\0asm".to_vec())
+        );
+    }
+
+    #[test]
+    fn adds_the_aot_binary_as_a_second_layer() {
+        let dir = tempfile::tempdir().unwrap();
+        let crate_data = demo_crate(dir.path());
+        fs::write(dir.path().join("demo.wasm"), b"\0asm").unwrap();
+        fs::write(dir.path().join("demo.so"), b"\x7fELF").unwrap();
+
+        let image = OciImage::for_bin(&crate_data, dir.path(), "demo", "demo", 0).unwrap();
+        assert_eq!(image.aot, Some(dir.path().join("demo.so")));
+        let files: BTreeMap<String, Vec<u8>> = image.layout().unwrap().into_iter().collect();
+        let index: Value = serde_json::from_slice(&files["index.json"]).unwrap();
+        assert_eq!(
+            index["manifests"][0]["annotations"]["org.opencontainers.image.ref.name"],
+            "latest"
+        );
+        let manifest: Value =
+            serde_json::from_slice(blob(&files, &index["manifests"][0])).unwrap();
+        let config: Value = serde_json::from_slice(blob(&files, &manifest["config"])).unwrap();
+        // The wasm file stays the entrypoint.
+        assert_eq!(config["config"]["Entrypoint"], json!(["/demo.wasm"]));
+
+        let layers = manifest["layers"].as_array().unwrap();
+        assert_eq!(layers.len(), 2);
+        assert_eq!(
+            config["rootfs"]["diff_ids"],
+            json!([layers[0]["digest"], layers[1]["digest"]])
+        );
+        assert_eq!(layer_file(blob(&files, &layers[0])).0, "demo.wasm");
+        assert_eq!(
+            layer_file(blob(&files, &layers[1])),
+            ("demo.so".to_string(), 0o755, 0, b"\x7fELF".to_vec())
+        );
+    }
+
+    #[test]
+    fn needs_the_built_wasm() {
+        let dir = tempfile::tempdir().unwrap();
+        let crate_data = demo_crate(dir.path());
+        let e = OciImage::for_bin(&crate_data, dir.path(), "demo", "demo", 0).unwrap_err();
+        assert!(e.to_string().contains("Run `rustwasmc build` first"));
+    }
+
+    #[test]
+    fn writes_the_layout_and_the_archive() {
+        let dir = tempfile::tempdir().unwrap();
+        let crate_data = demo_crate(dir.path());
+        fs::write(dir.path().join("demo.wasm"), b"\0asm").unwrap();
+        let image = OciImage::for_bin(&crate_data, dir.path(), "demo", "demo:1", 7).unwrap();
+        let layout = image.layout().unwrap();
+
+        let dest = dir.path().join("oci");
+        image.write_layout(&dest).unwrap();
+        for (path, contents) in &layout {
+            assert_eq!(&fs::read(dest.join(path)).unwrap(), contents);
+        }
+
+        let archive_path = dir.path().join("demo.oci.tar");
+        image.write_archive(&archive_path).unwrap();
+        let mut archive = Archive::new(File::open(&archive_path).unwrap());
+        let mut entries = Vec::new();
+        for entry in archive.entries().unwrap() {
+            let mut entry = entry.unwrap();
+            assert_eq!(entry.header().mtime().unwrap(), 7);
+            let path = entry.path().unwrap().display().to_string();
+            let mut contents = Vec::new();
+            entry.read_to_end(&mut contents).unwrap();
+            entries.push((path, contents));
+        }
+        assert_eq!(entries, layout);
+    }
+}
@@ -0,0 +1,315 @@
+//! Packing the out dir into an npm tarball.
+//!
+//! The tarball packs the same files as `npm pack`, and like npm puts every
+//! entry under `package/` with mode 0644, owner 0 and npm's fixed mtime. The
+//! bytes are not the same as npm's: entries are sorted by path rather than in
+//! npm's walk order, and the compression differs. Packing the same files
+//! twice gives the same bytes.
+
+use base64;
+use failure::{Error, ResultExt};
+use flate2::write::GzEncoder;
+use flate2::{Compression, GzBuilder};
+use hex;
+use semver::Version;
+use serde_json::Value;
+use sha1::Sha1;
+use sha2::{Digest, Sha512};
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::path::{Component, Path, PathBuf};
+use tar::{Builder, EntryType, Header};
+use walkdir::WalkDir;
+
+/// The modification time `npm pack` gives every entry: 1985-10-26T08:15:00Z.
+const NPM_MTIME: u64 = 499_162_500;
+
+/// A tarball written by `pack`.
+#[derive(Debug)]
+pub struct Tarball {
+    /// Where the tarball was written.
+    pub path: PathBuf,
+    /// The package name.
+    pub name: String,
+    /// The package version.
+    pub version: String,
+    /// The packed files, relative to the out dir, and their sizes.
+    pub files: Vec<(String, u64)>,
+    /// The size of the tarball.
+    pub size: u64,
+    /// The hex SHA-1 of the tarball, as used for npm's `shasum`.
+    pub shasum: String,
+    /// The subresource integrity string of the tarball, using SHA-512.
+    pub integrity: String,
+}
+
+impl Tarball {
+    /// The total size of the packed files.
+    pub fn unpacked_size(&self) -> u64 {
+        self.files.iter().map(|(_, size)| size).sum()
+    }
+}
+
+/// The file name `npm pack` uses for a package: `@scope/name` becomes
+/// `scope-name-<version>.tgz`.
+pub fn tarball_name(name: &str, version: &str) -> String {
+    let name = name.trim_start_matches('@').replace("/", "-");
+    format!("{}-{}.tgz", name, version)
+}
+
+/// Validate the `package.json` in `out_dir` and pack the files it lists into
+/// a tarball in `destination`.
+pub fn pack(out_dir: &Path, destination: &Path) -> Result<Tarball, Error> {
+    let manifest = read_package_json(out_dir)?;
+    let name = string_field(&manifest, "name")?;
+    let version = string_field(&manifest, "version")?;
+    Version::parse(&version)
+        .with_context(|_| format!("`version` in package.json is not valid semver: {}", version))?;
+
+    let files = collect_files(out_dir, &manifest)?;
+
+    let path = destination.join(tarball_name(&name, &version));
+    fs::create_dir_all(destination)?;
+    write_tarball(out_dir, &files, &path)?;
+
+    let contents =
+        fs::read(&path).with_context(|_| format!("failed to read: {}", path.display()))?;
+    let mut sha1 = Sha1::new();
+    sha1.input(&contents);
+    let shasum = hex::encode(sha1.result());
+    let integrity = format!("sha512-{}", base64::encode(&Sha512::digest(&contents)));
+
+    Ok(Tarball {
+        path,
+        name,
+        version,
+        files,
+        size: contents.len() as u64,
+        shasum,
+        integrity,
+    })
+}
+
+/// Read and parse the `package.json` in `out_dir`.
+pub fn read_package_json(out_dir: &Path) -> Result<Value, Error> {
+    let path = out_dir.join("package.json");
+    if !path.is_file() {
+        bail!(
+            "There is no package.json in {}. Run `rustwasmc build` first.",
+            out_dir.display()
+        );
+    }
+    let contents =
+        fs::read_to_string(&path).with_context(|_| format!("failed to read: {}", path.display()))?;
+    let manifest: Value = serde_json::from_str(&contents)
+        .with_context(|_| format!("failed to parse: {}", path.display()))?;
+    if !manifest.is_object() {
+        bail!("{} must contain a JSON object", path.display());
+    }
+    Ok(manifest)
+}
+
+fn string_field(manifest: &Value, field: &str) -> Result<String, Error> {
+    match manifest.get(field).and_then(|v| v.as_str()) {
+        Some(value) if !value.is_empty() => Ok(value.to_string()),
+        _ => bail!("package.json is missing the `{}` field", field),
+    }
+}
+
+/// Collect `package.json`, the entries of `files`, and any README or LICENSE
+/// file in the out dir, sorted by path.
+fn collect_files(out_dir: &Path, manifest: &Value) -> Result<Vec<(String, u64)>, Error> {
+    let mut files = BTreeMap::new();
+    let mut add = |path: &Path| -> Result<(), Error> {
+        let relative = path.strip_prefix(out_dir)?;
+        let name = relative
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        files.insert(name, fs::metadata(path)?.len());
+        Ok(())
+    };
+
+    add(&out_dir.join("package.json"))?;
+
+    let listed = match manifest.get("files") {
+        None => Vec::new(),
+        Some(Value::Array(listed)) => listed.clone(),
+        Some(_) => bail!("`files` in package.json must be an array"),
+    };
+    for entry in listed {
+        let entry = match entry.as_str() {
+            Some(entry) => entry,
+            None => bail!("`files` in package.json must only contain strings"),
+        };
+        let relative = Path::new(entry);
+        if relative
+            .components()
+            .any(|c| !matches!(c, Component::Normal(_) | Component::CurDir))
+        {
+            bail!("`files` entry `{}` must be inside the package", entry);
+        }
+        let path = out_dir.join(relative);
+        if path.is_dir() {
+            for file in WalkDir::new(&path) {
+                let file = file?;
+                if file.file_type().is_file() {
+                    add(file.path())?;
+                }
+            }
+        } else if path.is_file() {
+            add(&path)?;
+        } else {
+            bail!(
+                "`files` entry `{}` in package.json does not exist in {}",
+                entry,
+                out_dir.display()
+            );
+        }
+    }
+
+    // npm always includes these, whether or not they are listed.
+    for file in fs::read_dir(out_dir)? {
+        let file = file?;
+        let name = file.file_name().to_string_lossy().to_uppercase();
+        let always = name.starts_with("README")
+            || name.starts_with("LICENSE")
+            || name.starts_with("LICENCE");
+        if always && file.file_type()?.is_file() {
+            add(&file.path())?;
+        }
+    }
+
+    if let Some(main) = manifest.get("main").and_then(|v| v.as_str()) {
+        let main = main.trim_start_matches("./");
+        if !files.contains_key(main) {
+            bail!("`main` in package.json is `{}`, which is not packed", main);
+        }
+    }
+
+    Ok(files.into_iter().collect())
+}
+
+fn write_tarball(out_dir: &Path, files: &[(String, u64)], path: &Path) -> Result<(), Error> {
+    let file =
+        File::create(path).with_context(|_| format!("failed to create: {}", path.display()))?;
+    // Leave the gzip header's timestamp at zero.
+    let gz: GzEncoder<File> = GzBuilder::new().write(file, Compression::best());
+    let mut tar = Builder::new(gz);
+
+    for (name, _) in files {
+        let contents = fs::read(out_dir.join(name))?;
+        let mut header = Header::new_ustar();
+        header.set_entry_type(EntryType::Regular);
+        header.set_size(contents.len() as u64);
+        header.set_mode(0o644);
+        header.set_mtime(NPM_MTIME);
+        header.set_uid(0);
+        header.set_gid(0);
+        tar.append_data(&mut header, format!("package/{}", name), &contents[..])?;
+    }
+
+    tar.into_inner()?.finish()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::read::GzDecoder;
+    use std::io::Read;
+    use tar::Archive;
+    use tempfile;
+
+    fn write_package(dir: &Path) {
+        fs::create_dir_all(dir.join("b")).unwrap();
+        fs::write(
+            dir.join("package.json"),
+            r#"{"name":"demo","version":"1.0.0","files":["demo_bg.wasm","demo.js","b"],"main":"demo.js"}"#,
+        )
+        .unwrap();
+        fs::write(dir.join("demo_bg.wasm"), "x\n").unwrap();
+        fs::write(dir.join("demo.js"), "y\n").unwrap();
+        fs::write(dir.join("b/a.txt"), "z\n").unwrap();
+        fs::write(dir.join("README.md"), "r\n").unwrap();
+        fs::write(dir.join("LICENSE"), "l\n").unwrap();
+        // Neither listed nor always included.
+        fs::write(dir.join("Zeta.md"), "A\n").unwrap();
+    }
+
+    #[test]
+    fn packing_twice_gives_the_same_bytes() {
+        let dir = tempfile::tempdir().unwrap();
+        write_package(dir.path());
+
+        let first = pack(dir.path(), &dir.path().join("first")).unwrap();
+        // Rewrite a file so that only its mtime changes.
+        fs::write(dir.path().join("demo.js"), "y\n").unwrap();
+        let second = pack(dir.path(), &dir.path().join("second")).unwrap();
+
+        assert_eq!(first.path.file_name().unwrap(), "demo-1.0.0.tgz");
+        assert_eq!(fs::read(&first.path).unwrap(), fs::read(&second.path).unwrap());
+        assert_eq!(first.shasum, second.shasum);
+        assert_eq!(first.integrity, second.integrity);
+    }
+
+    #[test]
+    fn packs_the_files_and_headers_of_npm_pack() {
+        let dir = tempfile::tempdir().unwrap();
+        write_package(dir.path());
+        let tarball = pack(dir.path(), &dir.path().join("out")).unwrap();
+
+        // For the same directory, `npm pack` (npm 10.8.2) produces the same
+        // files, in the order LICENSE, demo.js, package.json, README.md,
+        // b/a.txt, demo_bg.wasm, all with mode 0644, uid and gid 0 and mtime
+        // 1985-10-26T08:15:00Z.
+        let mut archive = Archive::new(GzDecoder::new(File::open(&tarball.path).unwrap()));
+        let mut entries = Vec::new();
+        for entry in archive.entries().unwrap() {
+            let mut entry = entry.unwrap();
+            let header = entry.header().clone();
+            assert_eq!(header.entry_type(), EntryType::Regular);
+            assert_eq!(header.mode().unwrap(), 0o644);
+            assert_eq!(header.mtime().unwrap(), NPM_MTIME);
+            assert_eq!(header.uid().unwrap(), 0);
+            assert_eq!(header.gid().unwrap(), 0);
+            assert!(header.as_ustar().is_some());
+            let mut contents = String::new();
+            entry.read_to_string(&mut contents).unwrap();
+            entries.push((entry.path().unwrap().to_string_lossy().into_owned(), contents));
+        }
+        let names: Vec<&str> = entries.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(
+            names,
+            vec![
+                "package/LICENSE",
+                "package/README.md",
+                "package/b/a.txt",
+                "package/demo.js",
+                "package/demo_bg.wasm",
+                "package/package.json",
+            ]
+        );
+        assert_eq!(entries[3].1, "y\n");
+        let package_json = fs::metadata(dir.path().join("package.json")).unwrap().len();
+        assert_eq!(tarball.unpacked_size(), 5 * 2 + package_json);
+    }
+
+    #[test]
+    fn names_tarballs_like_npm() {
+        assert_eq!(tarball_name("demo", "1.0.0"), "demo-1.0.0.tgz");
+        assert_eq!(tarball_name("@scope/demo", "0.1.0-rc.1"), "scope-demo-0.1.0-rc.1.tgz");
+    }
+
+    #[test]
+    fn rejects_files_outside_the_package() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(
+            dir.path().join("package.json"),
+            r#"{"name":"demo","version":"1.0.0","files":["../secret"]}"#,
+        )
+        .unwrap();
+        assert!(pack(dir.path(), dir.path()).is_err());
+    }
+}
@@ -0,0 +1,445 @@
+//! Describing a build without running it, for `rustwasmc build --plan`.
+//!
+//! A `BuildPlan` lists the settings a build resolved, which steps would run,
+//! where each tool would come from and the commands that would be run. Nothing
+//! is built or downloaded to make it: tools are only looked up on `PATH` and
+//! in the cache. The one exception is the list of WasmEdge wasm-bindgen
+//! builds, which is fetched to work out wasm-bindgen's download URL.
+
+use binary_install::Cache;
+use failure::Error;
+use serde_json;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::path::PathBuf;
+use std::process::Command;
+use std::str::FromStr;
+
+/// How `--plan` prints the plan.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PlanFormat {
+    /// Human-readable text.
+    Text,
+    /// A JSON object.
+    Json,
+}
+
+impl FromStr for PlanFormat {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self, Error> {
+        match s {
+            "text" => Ok(PlanFormat::Text),
+            "json" => Ok(PlanFormat::Json),
+            _ => bail!("Unknown plan format: {}", s),
+        }
+    }
+}
+
+/// What a build would do.
+#[derive(Debug, Serialize)]
+pub struct BuildPlan {
+    /// The crate directory.
+    pub crate_path: PathBuf,
+    /// The output directory.
+    pub out_dir: PathBuf,
+    /// The package name written to package.json.
+    pub package_name: String,
+    /// The version written to package.json.
+    pub package_version: String,
+    /// The install mode.
+    pub mode: String,
+    /// The name of the build profile.
+    pub profile: String,
+    /// The run target.
+    pub run_target: String,
+    /// The wasm target cargo builds for.
+    pub wasm_target: String,
+    /// The cargo features passed with `--features`.
+    pub features: Vec<String>,
+    /// Every build step, in order.
+    pub steps: Vec<PlannedStep>,
+    /// The tools the steps that run need.
+    pub tools: Vec<PlannedTool>,
+    /// The commands the steps that run would run, in order.
+    pub commands: Vec<PlannedCommand>,
+}
+
+/// A build step and whether it would run.
+#[derive(Debug, Serialize)]
+pub struct PlannedStep {
+    /// The name of the step, as accepted by `--skip-step`.
+    pub name: String,
+    /// Whether the step would do anything.
+    pub run: bool,
+    /// Why the step would not run.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+/// Where a tool would come from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ToolSource {
+    /// Found on `PATH`.
+    Path,
+    /// Downloaded by an earlier build.
+    Cache,
+    /// Would be downloaded.
+    Download,
+    /// Not installed, and the install mode forbids installing it.
+    NotInstalled,
+    /// Not installed, and there is no prebuilt copy for this platform.
+    Unsupported,
+}
+
+/// A tool the build needs.
+#[derive(Debug, Serialize)]
+pub struct PlannedTool {
+    /// The tool's binary name.
+    pub name: String,
+    /// The version that would be used, if known.
+    pub version: Option<String>,
+    /// Where the tool would come from.
+    pub source: ToolSource,
+    /// The path of the binary, if it is already installed.
+    pub path: Option<PathBuf>,
+    /// The URL the tool would be downloaded from, if known.
+    pub url: Option<String>,
+}
+
+impl PlannedTool {
+    /// Locate a tool that is downloaded from `url`, without downloading it.
+    /// `url` is `None` if there is no prebuilt copy for this platform.
+    pub fn locate(
+        cache: &Cache,
+        name: &str,
+        version: &str,
+        url: Option<String>,
+        install_permitted: bool,
+    ) -> PlannedTool {
+        if let Ok(path) = which::which(name) {
+            return PlannedTool {
+                name: name.to_string(),
+                version: None,
+                source: ToolSource::Path,
+                path: Some(path),
+                url: None,
+            };
+        }
+        PlannedTool::locate_download(cache, name, version, url, install_permitted)
+    }
+
+    /// Locate a tool that isn't usable from `PATH` in the cache, the same way
+    /// `binary_install` would, or else describe its download from `url`.
+    pub fn locate_download(
+        cache: &Cache,
+        name: &str,
+        version: &str,
+        url: Option<String>,
+        install_permitted: bool,
+    ) -> PlannedTool {
+        let mut tool = PlannedTool {
+            name: name.to_string(),
+            version: Some(version.to_string()),
+            source: ToolSource::Download,
+            path: None,
+            url: None,
+        };
+        let url = match url {
+            Some(url) => url,
+            None => {
+                tool.source = ToolSource::Unsupported;
+                return tool;
+            }
+        };
+        if let Ok(Some(dl)) = cache.download(false, name, &[name], &url) {
+            tool.source = ToolSource::Cache;
+            tool.path = dl.binary(name).ok();
+        } else if !install_permitted {
+            tool.source = ToolSource::NotInstalled;
+        }
+        tool.url = Some(url);
+        tool
+    }
+
+    /// The program to show in commands: the path if known, else the name.
+    pub fn program(&self) -> PathBuf {
+        self.path
+            .clone()
+            .unwrap_or_else(|| PathBuf::from(&self.name))
+    }
+}
+
+/// A command a step would run.
+#[derive(Debug, Serialize)]
+pub struct PlannedCommand {
+    /// The step that runs the command.
+    pub step: String,
+    /// The program.
+    pub program: String,
+    /// The arguments.
+    pub args: Vec<String>,
+    /// Environment variables set for the command.
+    pub env: BTreeMap<String, String>,
+    /// The working directory, if not the current one.
+    pub cwd: Option<PathBuf>,
+}
+
+impl PlannedCommand {
+    /// Describe `cmd`, run by `step`.
+    pub fn new(step: &str, cmd: &Command) -> PlannedCommand {
+        PlannedCommand {
+            step: step.to_string(),
+            program: cmd.get_program().to_string_lossy().into_owned(),
+            args: cmd
+                .get_args()
+                .map(|arg| arg.to_string_lossy().into_owned())
+                .collect(),
+            env: cmd
+                .get_envs()
+                .filter_map(|(key, value)| {
+                    let value = value?;
+                    Some((
+                        key.to_string_lossy().into_owned(),
+                        value.to_string_lossy().into_owned(),
+                    ))
+                })
+                .collect(),
+            cwd: cmd.get_current_dir().map(PathBuf::from),
+        }
+    }
+
+    /// The command as a shell command line.
+    pub fn command_line(&self) -> String {
+        let mut line = String::new();
+        if let Some(cwd) = &self.cwd {
+            line.push_str(&format!("cd {} && ", shell_quote(&cwd.to_string_lossy())));
+        }
+        for (key, value) in &self.env {
+            line.push_str(&format!("{}={} ", key, shell_quote(value)));
+        }
+        line.push_str(&shell_quote(&self.program));
+        for arg in &self.args {
+            line.push(' ');
+            line.push_str(&shell_quote(arg));
+        }
+        line
+    }
+}
+
+fn shell_quote(s: &str) -> String {
+    let plain = !s.is_empty()
+        && s.chars()
+            .all(|c| c.is_ascii_alphanumeric() || "_-./=:,@+%".contains(c));
+    if plain {
+        s.to_string()
+    } else {
+        format!("'{}'", s.replace('\'', "'\\''"))
+    }
+}
+
+impl BuildPlan {
+    /// Print the plan in `format` to stdout.
+    pub fn print(&self, format: PlanFormat) -> Result<(), Error> {
+        match format {
+            PlanFormat::Text => print!("{}", self.render_text()),
+            PlanFormat::Json => println!("{}", serde_json::to_string_pretty(self)?),
+        }
+        Ok(())
+    }
+
+    /// The plan as human-readable text.
+    pub fn render_text(&self) -> String {
+        let features = if self.features.is_empty() {
+            "(none)".to_string()
+        } else {
+            self.features.join(", ")
+        };
+        let mut out = String::new();
+        let _ = writeln!(out, "Crate:       {}", self.crate_path.display());
+        let _ = writeln!(out, "Output:      {}", self.out_dir.display());
+        let _ = writeln!(out, "Package:     {}@{}", self.package_name, self.package_version);
+        let _ = writeln!(out, "Mode:        {}", self.mode);
+        let _ = writeln!(out, "Profile:     {}", self.profile);
+        let _ = writeln!(out, "Run target:  {}", self.run_target);
+        let _ = writeln!(out, "Wasm target: {}", self.wasm_target);
+        let _ = writeln!(out, "Features:    {}", features);
+
+        let _ = writeln!(out, "\nSteps:");
+        for step in &self.steps {
+            match &step.reason {
+                Some(reason) => {
+                    let _ = writeln!(out, "  skip {} ({})", step.name, reason);
+                }
+                None => {
+                    let _ = writeln!(out, "  run  {}", step.name);
+                }
+            }
+        }
+
+        if !self.tools.is_empty() {
+            let _ = writeln!(out, "\nTools:");
+        }
+        for tool in &self.tools {
+            let version = tool
+                .version
+                .as_ref()
+                .map(|v| format!(" {}", v))
+                .unwrap_or_default();
+            let location = match tool.source {
+                ToolSource::Path => format!("on PATH at {}", tool.program().display()),
+                ToolSource::Cache => format!("cached at {}", tool.program().display()),
+                ToolSource::Download => match &tool.url {
+                    Some(url) => format!("downloaded from {}", url),
+                    None => "downloaded".to_string(),
+                },
+                ToolSource::NotInstalled => "not installed, and --mode forbids installing it".to_string(),
+                ToolSource::Unsupported => "not installed, and not available for this platform".to_string(),
+            };
+            let _ = writeln!(out, "  {}{}: {}", tool.name, version, location);
+        }
+
+        if !self.commands.is_empty() {
+            let _ = writeln!(out, "\nCommands:");
+        }
+        for command in &self.commands {
+            let _ = writeln!(out, "  [{}] {}", command.step, command.command_line());
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    use std::thread;
+    use tempfile;
+
+    // Not on anyone's PATH, so `locate` has to look at the cache.
+    const TOOL: &str = "rustwasmc-plan-test-tool";
+
+    #[test]
+    fn describes_a_command() {
+        let mut cmd = Command::new("cargo");
+        cmd.arg("build")
+            .arg("--release")
+            .env("SOURCE_DATE_EPOCH", "0")
+            .current_dir("/tmp/crate");
+        let planned = PlannedCommand::new("build-wasm", &cmd);
+        assert_eq!(planned.step, "build-wasm");
+        assert_eq!(planned.program, "cargo");
+        assert_eq!(planned.args, vec!["build", "--release"]);
+        assert_eq!(planned.env.len(), 1);
+        assert_eq!(planned.env["SOURCE_DATE_EPOCH"], "0");
+        assert_eq!(planned.cwd, Some(PathBuf::from("/tmp/crate")));
+        assert_eq!(
+            planned.command_line(),
+            "cd /tmp/crate && SOURCE_DATE_EPOCH=0 cargo build --release"
+        );
+
+        let planned = PlannedCommand::new("build-wasm", &Command::new("cargo"));
+        assert!(planned.env.is_empty());
+        assert_eq!(planned.cwd, None);
+        assert_eq!(planned.command_line(), "cargo");
+    }
+
+    #[test]
+    fn quotes_for_the_shell() {
+        assert_eq!(shell_quote("a-b_c/d.wasm"), "a-b_c/d.wasm");
+        assert_eq!(shell_quote("--opt=x,y:z@1+2%"), "--opt=x,y:z@1+2%");
+        assert_eq!(shell_quote(""), "''");
+        assert_eq!(shell_quote("two words"), "'two words'");
+        assert_eq!(shell_quote("$HOME"), "'$HOME'");
+        assert_eq!(shell_quote("it's"), "'it'\\''s'");
+
+        let mut cmd = Command::new("my tool");
+        cmd.arg("it's").env("FLAGS", "-a -b").current_dir("/tmp/my dir");
+        assert_eq!(
+            PlannedCommand::new("step", &cmd).command_line(),
+            "cd '/tmp/my dir' && FLAGS='-a -b' 'my tool' 'it'\\''s'"
+        );
+    }
+
+    #[test]
+    fn locates_tools_that_are_not_cached() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = Cache::at(dir.path());
+        let url = "https://example.com/tool.tar.gz".to_string();
+
+        let tool = PlannedTool::locate(&cache, TOOL, "1.0.0", None, true);
+        assert_eq!(tool.source, ToolSource::Unsupported);
+        assert_eq!(tool.url, None);
+
+        let tool = PlannedTool::locate(&cache, TOOL, "1.0.0", Some(url.clone()), true);
+        assert_eq!(tool.source, ToolSource::Download);
+        assert_eq!(tool.version.as_deref(), Some("1.0.0"));
+        assert_eq!(tool.url.as_deref(), Some(url.as_str()));
+        assert_eq!(tool.path, None);
+        assert_eq!(tool.program(), PathBuf::from(TOOL));
+
+        let tool = PlannedTool::locate(&cache, TOOL, "1.0.0", Some(url), false);
+        assert_eq!(tool.source, ToolSource::NotInstalled);
+    }
+
+    #[test]
+    fn locates_cached_tools() {
+        let url = serve_once(tarball_with(TOOL));
+        let dir = tempfile::tempdir().unwrap();
+        let cache = Cache::at(dir.path());
+        cache.download(true, TOOL, &[TOOL], &url).unwrap().unwrap();
+
+        // Cached tools are used even when installing is forbidden.
+        let tool = PlannedTool::locate(&cache, TOOL, "1.0.0", Some(url.clone()), false);
+        assert_eq!(tool.source, ToolSource::Cache);
+        assert_eq!(tool.url.as_deref(), Some(url.as_str()));
+        let path = tool.path.unwrap();
+        assert!(path.starts_with(dir.path()));
+        assert!(path.is_file());
+
+        // A different URL, such as a mirror, is cached separately.
+        let other = url.replace("tool.tar.gz", "other.tar.gz");
+        let tool = PlannedTool::locate(&cache, TOOL, "1.0.0", Some(other), true);
+        assert_eq!(tool.source, ToolSource::Download);
+    }
+
+    fn tarball_with(binary: &str) -> Vec<u8> {
+        let mut builder = tar::Builder::new(GzEncoder::new(Vec::new(), Compression::default()));
+        let contents = b"#!/bin/sh\n";
+        let mut header = tar::Header::new_gnu();
+        header.set_size(contents.len() as u64);
+        header.set_mode(0o755);
+        header.set_cksum();
+        builder
+            .append_data(&mut header, format!("dist/{}", binary), &contents[..])
+            .unwrap();
+        builder.into_inner().unwrap().finish().unwrap()
+    }
+
+    /// Serve `body` to a single request, returning its URL.
+    fn serve_once(body: Vec<u8>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = Vec::new();
+            let mut buf = [0; 1024];
+            while !request.windows(4).any(|w| w == b"\r\n\r\n") {
+                match stream.read(&mut buf) {
+                    Ok(0) | Err(_) => return,
+                    Ok(n) => request.extend_from_slice(&buf[..n]),
+                }
+            }
+            let head = format!(
+                "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                body.len()
+            );
+            let _ = stream.write_all(head.as_bytes());
+            let _ = stream.write_all(&body);
+        });
+        format!("http://{}/tool.tar.gz", addr)
+    }
+}
@@ -2,6 +2,8 @@
 
 use console::style;
 use emoji;
+use logfile;
+use reporter;
 use std::sync::atomic::{AtomicBool, AtomicU8, Ordering};
 
 #[repr(u8)]
@@ -72,14 +74,38 @@ impl ProgressOutput {
 
     /// Add an informational message.
     pub fn info(&self, message: &str) {
+        logfile::write("INFO", message);
+        if !reporter::with_current(|r| r.info(message)) {
+            self.print_info(message);
+        }
+    }
+
+    /// Add a warning message.
+    pub fn warn(&self, message: &str) {
+        logfile::write("WARN", message);
+        if !reporter::with_current(|r| r.warn(message)) {
+            self.print_warn(message);
+        }
+    }
+
+    /// Add an error message.
+    pub fn error(&self, message: &str) {
+        logfile::write("ERROR", message);
+        if !reporter::with_current(|r| r.error(message)) {
+            self.print_error(message);
+        }
+    }
+
+    /// Print an informational message to the terminal.
+    pub(crate) fn print_info(&self, message: &str) {
         if !self.quiet() && self.is_log_enabled(LogLevel::Info) {
             let info = format!("{}: {}", style("[INFO]").bold().dim(), message,);
             self.message(&info);
         }
     }
 
-    /// Add a warning message.
-    pub fn warn(&self, message: &str) {
+    /// Print a warning message to the terminal.
+    pub(crate) fn print_warn(&self, message: &str) {
         if !self.quiet() && self.is_log_enabled(LogLevel::Warn) {
             let warn = format!(
                 "{} {}: {}",
@@ -91,8 +117,8 @@ impl ProgressOutput {
         }
     }
 
-    /// Add an error message.
-    pub fn error(&self, message: &str) {
+    /// Print an error message to the terminal.
+    pub(crate) fn print_error(&self, message: &str) {
         if self.is_log_enabled(LogLevel::Error) {
             let err = format!(
                 "{} {}: {}",
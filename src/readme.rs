@@ -2,27 +2,454 @@
 
 use failure::{self, ResultExt};
 use std::fs;
-use std::path::Path;
+use std::path::{Component, Path, PathBuf};
 
+use child::git_output;
+use manifest::CrateData;
 use PBAR;
 
 /// Copy the crate's README into the `pkg` directory.
-pub fn copy_from_crate(path: &Path, out_dir: &Path) -> Result<(), failure::Error> {
+///
+/// The README is found through the `readme` key of Cargo.toml. Relative links
+/// and images in a Markdown README are rewritten to point into the crate's
+/// `repository`, at the current git commit, so they keep working on npm.
+pub fn copy_from_crate(
+    crate_data: &CrateData,
+    path: &Path,
+    out_dir: &Path,
+) -> Result<(), failure::Error> {
     assert!(
-        fs::metadata(path).ok().map_or(false, |m| m.is_dir()),
+        path.is_dir(),
         "crate directory should exist"
     );
     assert!(
-        fs::metadata(&out_dir).ok().map_or(false, |m| m.is_dir()),
+        out_dir.is_dir(),
         "crate's pkg directory should exist"
     );
 
-    let crate_readme_path = path.join("README.md");
-    let new_readme_path = out_dir.join("README.md");
-    if crate_readme_path.exists() {
-        fs::copy(&crate_readme_path, &new_readme_path).context("failed to copy README")?;
-    } else {
-        PBAR.warn("origin crate has no README");
+    let crate_readme_path = match crate_data.crate_readme() {
+        Some(readme) => readme,
+        None => {
+            if !crate_data.readme_disabled() {
+                PBAR.warn("origin crate has no README");
+            }
+            return Ok(());
+        }
+    };
+    if !crate_readme_path.is_file() {
+        bail!(
+            "The README {} set in Cargo.toml does not exist",
+            crate_readme_path.display()
+        );
+    }
+
+    let extension = crate_readme_path
+        .extension()
+        .map(|ext| format!(".{}", ext.to_string_lossy()))
+        .unwrap_or_default();
+    let new_readme_path = out_dir.join(format!("README{}", extension));
+    let is_markdown = extension.is_empty()
+        || extension.eq_ignore_ascii_case(".md")
+        || extension.eq_ignore_ascii_case(".markdown");
+
+    let base = match crate_data.crate_repository() {
+        Some(repository) if is_markdown => RepositoryBase::new(repository, &crate_readme_path),
+        _ => None,
+    };
+    match base {
+        Some(base) => {
+            let contents = fs::read_to_string(&crate_readme_path)
+                .with_context(|_| format!("failed to read: {}", crate_readme_path.display()))?;
+            fs::write(&new_readme_path, rewrite_links(&contents, &base))
+                .context("failed to copy README")?;
+        }
+        None => {
+            fs::copy(&crate_readme_path, &new_readme_path).context("failed to copy README")?;
+        }
     }
     Ok(())
 }
+
+/// Where relative links in the README point to once it is published.
+struct RepositoryBase {
+    /// Prefix for links to pages, e.g. `https://github.com/o/r/blob/<ref>/`.
+    blob: String,
+    /// Prefix for images, e.g. `https://github.com/o/r/raw/<ref>/`.
+    raw: String,
+    /// The README's directory, relative to the repository root.
+    dir: PathBuf,
+}
+
+impl RepositoryBase {
+    fn new(repository: &str, readme: &Path) -> Option<RepositoryBase> {
+        let mut url = repository.trim().trim_end_matches('/').to_string();
+        if url.starts_with("git@") {
+            // `git@github.com:owner/repo` -> `https://github.com/owner/repo`
+            url = format!("https://{}", url["git@".len()..].replacen(':', "/", 1));
+        }
+        if !url.starts_with("https://") && !url.starts_with("http://") {
+            return None;
+        }
+        let url = url.trim_end_matches(".git");
+
+        let readme_dir = readme.parent()?.canonicalize().ok()?;
+        let git_root = git_output(&readme_dir, &["rev-parse", "--show-toplevel"])
+            .and_then(|root| PathBuf::from(root).canonicalize().ok());
+        let dir = match &git_root {
+            Some(root) => readme_dir.strip_prefix(root).ok()?.to_path_buf(),
+            None => PathBuf::new(),
+        };
+        let git_ref = git_output(&readme_dir, &["rev-parse", "HEAD"]).unwrap_or_else(|| "HEAD".into());
+
+        let (blob, raw) = if url.contains("gitlab") {
+            ("-/blob", "-/raw")
+        } else {
+            ("blob", "raw")
+        };
+        Some(RepositoryBase {
+            blob: format!("{}/{}/{}/", url, blob, git_ref),
+            raw: format!("{}/{}/{}/", url, raw, git_ref),
+            dir,
+        })
+    }
+
+    /// The absolute URL for the relative link `target`, or `None` if it
+    /// isn't relative.
+    fn resolve(&self, target: &str, image: bool) -> Option<String> {
+        let is_absolute = target.is_empty()
+            || target.starts_with('#')
+            || target.starts_with("//")
+            || match target.find(':') {
+                Some(idx) => !target[..idx].contains('/'),
+                None => false,
+            };
+        if is_absolute {
+            return None;
+        }
+
+        let split = target.find(&['#', '?'][..]).unwrap_or(target.len());
+        let (link, suffix) = target.split_at(split);
+        let joined = match link.strip_prefix('/') {
+            Some(link) => PathBuf::from(link),
+            None => self.dir.join(link),
+        };
+        let mut segments: Vec<String> = Vec::new();
+        for component in joined.components() {
+            match component {
+                Component::Normal(part) => segments.push(part.to_string_lossy().into_owned()),
+                Component::ParentDir => {
+                    segments.pop();
+                }
+                _ => {}
+            }
+        }
+
+        let prefix = if image { &self.raw } else { &self.blob };
+        Some(format!("{}{}{}", prefix, segments.join("/"), suffix))
+    }
+}
+
+/// Rewrite the relative targets of inline links and images (`[text](url)`,
+/// `![alt](url)`), reference definitions (`[id]: url`) and HTML `src`/`href`
+/// attributes. Fenced and indented code blocks and code spans are left alone.
+fn rewrite_links(markdown: &str, base: &RepositoryBase) -> String {
+    let mut out = String::with_capacity(markdown.len());
+    let mut in_fence = false;
+    let mut in_indented_code = false;
+    let mut in_list = false;
+    let mut after_blank = true;
+    for (i, line) in markdown.split('\n').enumerate() {
+        if i > 0 {
+            out.push('\n');
+        }
+        let trimmed = line.trim_start();
+        if !in_fence && trimmed.is_empty() {
+            out.push_str(line);
+            after_blank = true;
+            continue;
+        }
+        let is_fence = trimmed.starts_with("```") || trimmed.starts_with("~~~");
+        // An indented line starts a code block after a blank line, unless it
+        // continues a list item.
+        let indented = indent_width(line) >= 4;
+        in_indented_code =
+            !in_fence && indented && (in_indented_code || (after_blank && !in_list));
+        if !indented {
+            in_list = is_list_item(trimmed);
+        }
+        after_blank = false;
+
+        if is_fence && !in_indented_code {
+            in_fence = !in_fence;
+        }
+        if is_fence || in_fence || in_indented_code {
+            out.push_str(line);
+        } else {
+            out.push_str(&rewrite_line(line, base));
+        }
+    }
+    out
+}
+
+fn indent_width(line: &str) -> usize {
+    let mut width = 0;
+    for c in line.chars() {
+        match c {
+            ' ' => width += 1,
+            '\t' => width += 4 - width % 4,
+            _ => break,
+        }
+    }
+    width
+}
+
+fn is_list_item(line: &str) -> bool {
+    let digits = line.len() - line.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+    let markers: &[char] = if digits > 0 { &['.', ')'] } else { &['-', '*', '+'] };
+    let rest = &line[digits..];
+    match rest.chars().next() {
+        Some(c) if markers.contains(&c) => {
+            let after = &rest[1..];
+            after.is_empty() || after.starts_with(' ') || after.starts_with('\t')
+        }
+        _ => false,
+    }
+}
+
+/// The byte ranges of the code spans in `line`, including their backticks.
+/// A span is closed by a backtick run of the same length as the one opening
+/// it; an unclosed run is literal.
+fn code_spans(line: &str) -> Vec<(usize, usize)> {
+    let bytes = line.as_bytes();
+    let run_length = |from: usize| bytes[from..].iter().take_while(|&&b| b == b'`').count();
+    let mut spans = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\\' => i += 2,
+            b'`' => {
+                let length = run_length(i);
+                let mut j = i + length;
+                let mut close = None;
+                while j < bytes.len() {
+                    if bytes[j] == b'`' {
+                        let run = run_length(j);
+                        if run == length {
+                            close = Some(j + run);
+                            break;
+                        }
+                        j += run;
+                    } else {
+                        j += 1;
+                    }
+                }
+                match close {
+                    Some(end) => {
+                        spans.push((i, end));
+                        i = end;
+                    }
+                    None => i += length,
+                }
+            }
+            _ => i += 1,
+        }
+    }
+    spans
+}
+
+fn rewrite_line(line: &str, base: &RepositoryBase) -> String {
+    // Reference definition: `[id]: url`
+    let trimmed = line.trim_start();
+    if trimmed.starts_with('[') {
+        if let Some(idx) = trimmed.find("]:") {
+            let indent = &line[..line.len() - trimmed.len()];
+            let rest = &trimmed[idx + 2..];
+            let target_start = rest.len() - rest.trim_start().len();
+            let target_end = rest[target_start..]
+                .find(char::is_whitespace)
+                .map_or(rest.len(), |end| target_start + end);
+            let target = &rest[target_start..target_end];
+            let image = is_image(target);
+            if let Some(url) = base.resolve(target, image) {
+                return format!(
+                    "{}{}{}{}{}",
+                    indent,
+                    &trimmed[..idx + 2],
+                    &rest[..target_start],
+                    url,
+                    &rest[target_end..]
+                );
+            }
+            return line.to_string();
+        }
+    }
+
+    let spans = code_spans(line);
+    let code_span_end = |idx: usize| {
+        spans
+            .iter()
+            .find(|&&(start, end)| start <= idx && idx < end)
+            .map(|&(_, end)| end)
+    };
+    let mut out = String::with_capacity(line.len());
+    let mut pos = 0;
+    loop {
+        let rest = &line[pos..];
+        let link = rest.find("](");
+        let attr = ["src=\"", "href=\""]
+            .iter()
+            .filter_map(|a| rest.find(a).map(|idx| (idx, a.len())))
+            .min();
+        let (found, start, image, close) = match (link, attr) {
+            (Some(l), Some((a, len))) if a < l => (a, a + len, None, '"'),
+            (Some(l), _) => {
+                let image = match opening_bracket(&line[..pos + l]) {
+                    Some(open) => open > 0 && line.as_bytes()[open - 1] == b'!',
+                    None => false,
+                };
+                (l, l + 2, Some(image), ')')
+            }
+            (None, Some((a, len))) => (a, a + len, None, '"'),
+            (None, None) => break,
+        };
+        if let Some(end) = code_span_end(pos + found) {
+            out.push_str(&line[pos..end]);
+            pos = end;
+            continue;
+        }
+        out.push_str(&rest[..start]);
+        pos += start;
+        let rest = &line[pos..];
+        let end = rest
+            .find(|c: char| c == close || (close == ')' && c.is_whitespace()))
+            .unwrap_or(rest.len());
+        let target = &rest[..end];
+        let image = image.unwrap_or_else(|| is_image(target));
+        match base.resolve(target, image) {
+            Some(url) => out.push_str(&url),
+            None => out.push_str(target),
+        }
+        pos += end;
+    }
+    out.push_str(&line[pos..]);
+    out
+}
+
+/// The index of the `[` matching a `]` just past the end of `text`, skipping
+/// nested brackets such as those of an image inside a link.
+fn opening_bracket(text: &str) -> Option<usize> {
+    let mut depth = 0;
+    for (idx, b) in text.bytes().enumerate().rev() {
+        match b {
+            b']' => depth += 1,
+            b'[' if depth == 0 => return Some(idx),
+            b'[' => depth -= 1,
+            _ => {}
+        }
+    }
+    None
+}
+
+fn is_image(target: &str) -> bool {
+    let path = target.split(&['#', '?'][..]).next().unwrap_or("");
+    let lower = path.to_lowercase();
+    [".png", ".jpg", ".jpeg", ".gif", ".svg", ".webp"]
+        .iter()
+        .any(|ext| lower.ends_with(ext))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn base() -> RepositoryBase {
+        RepositoryBase {
+            blob: "https://github.com/o/r/blob/abc/".to_string(),
+            raw: "https://github.com/o/r/raw/abc/".to_string(),
+            dir: PathBuf::from("crates/demo"),
+        }
+    }
+
+    fn rewrite(markdown: &str) -> String {
+        rewrite_links(markdown, &base())
+    }
+
+    #[test]
+    fn rewrites_inline_links() {
+        assert_eq!(
+            rewrite("See [the guide](docs/guide.md#intro) and [x](../x.md \"X\")."),
+            "See [the guide](https://github.com/o/r/blob/abc/crates/demo/docs/guide.md#intro) \
+             and [x](https://github.com/o/r/blob/abc/crates/x.md \"X\")."
+        );
+        assert_eq!(
+            rewrite("[root](/LICENSE) [`api`](api.md)"),
+            "[root](https://github.com/o/r/blob/abc/LICENSE) \
+             [`api`](https://github.com/o/r/blob/abc/crates/demo/api.md)"
+        );
+    }
+
+    #[test]
+    fn rewrites_images() {
+        assert_eq!(
+            rewrite("![logo](img/logo.png)"),
+            "![logo](https://github.com/o/r/raw/abc/crates/demo/img/logo.png)"
+        );
+        assert_eq!(
+            rewrite("[![ci](badge.svg)](ci.md)"),
+            concat!(
+                "[![ci](https://github.com/o/r/raw/abc/crates/demo/badge.svg)]",
+                "(https://github.com/o/r/blob/abc/crates/demo/ci.md)"
+            )
+        );
+        assert_eq!(
+            rewrite("<img src=\"img/a.png\"> <a href=\"docs/\">docs</a>"),
+            "<img src=\"https://github.com/o/r/raw/abc/crates/demo/img/a.png\"> \
+             <a href=\"https://github.com/o/r/blob/abc/crates/demo/docs\">docs</a>"
+        );
+    }
+
+    #[test]
+    fn rewrites_reference_definitions() {
+        assert_eq!(
+            rewrite("[guide]: ./docs/guide.md \"The guide\"\n[logo]: logo.svg"),
+            "[guide]: https://github.com/o/r/blob/abc/crates/demo/docs/guide.md \"The guide\"\n\
+             [logo]: https://github.com/o/r/raw/abc/crates/demo/logo.svg"
+        );
+    }
+
+    #[test]
+    fn keeps_anchors_and_absolute_urls() {
+        let markdown = "[usage](#usage) [site](https://example.com/a.md) \
+                        [mail](mailto:a@example.com) ![cdn](//cdn.example.com/a.png)\n\
+                        [home]: https://example.com";
+        assert_eq!(rewrite(markdown), markdown);
+    }
+
+    #[test]
+    fn keeps_code() {
+        let fenced = "```md\n[a](a.md)\n```\n~~~\n![b](b.png)\n~~~";
+        assert_eq!(rewrite(fenced), fenced);
+
+        let indented = "Example:\n\n    [a](a.md)\n\n    <img src=\"b.png\">\nText";
+        assert_eq!(rewrite(indented), indented);
+
+        assert_eq!(
+            rewrite("Use `[a](a.md)` or ``x ` href=\"b\"``, not [c](c.md)."),
+            "Use `[a](a.md)` or ``x ` href=\"b\"``, not \
+             [c](https://github.com/o/r/blob/abc/crates/demo/c.md)."
+        );
+        // An unclosed backtick doesn't start a code span.
+        assert_eq!(
+            rewrite("a ` [c](c.md)"),
+            "a ` [c](https://github.com/o/r/blob/abc/crates/demo/c.md)"
+        );
+    }
+
+    #[test]
+    fn rewrites_indented_list_items() {
+        assert_eq!(
+            rewrite("- item\n\n    [a](a.md)"),
+            "- item\n\n    [a](https://github.com/o/r/blob/abc/crates/demo/a.md)"
+        );
+    }
+}
@@ -0,0 +1,79 @@
+//! Reporting progress and messages to the user.
+//!
+//! The command line reports to the terminal through `PBAR`. Programs that run
+//! builds in-process can pass their own `Reporter` to a `BuildConfig`; while
+//! the build runs on the current thread, every message that would be printed
+//! by `PBAR` goes to it instead.
+
+use std::cell::RefCell;
+use std::sync::Arc;
+use PBAR;
+
+/// Receives the messages of a build.
+pub trait Reporter: Send + Sync {
+    /// An informational message.
+    fn info(&self, message: &str);
+
+    /// A warning.
+    fn warn(&self, message: &str);
+
+    /// An error that doesn't stop the build.
+    fn error(&self, message: &str);
+
+    /// The build step `name`, e.g. `build-wasm`, is starting.
+    fn step(&self, _name: &str) {}
+}
+
+/// Prints messages to the terminal, honoring `--quiet` and `--log-level`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TerminalReporter;
+
+impl Reporter for TerminalReporter {
+    fn info(&self, message: &str) {
+        PBAR.print_info(message);
+    }
+
+    fn warn(&self, message: &str) {
+        PBAR.print_warn(message);
+    }
+
+    fn error(&self, message: &str) {
+        PBAR.print_error(message);
+    }
+}
+
+thread_local! {
+    static CURRENT: RefCell<Option<Arc<dyn Reporter>>> = RefCell::new(None);
+}
+
+/// Send the messages printed through `PBAR` on this thread to `reporter`
+/// until the returned guard is dropped.
+pub fn scoped(reporter: Arc<dyn Reporter>) -> ScopedReporter {
+    let previous = CURRENT.with(|current| current.borrow_mut().replace(reporter));
+    ScopedReporter { previous }
+}
+
+/// Restores the previous reporter of the thread when dropped.
+pub struct ScopedReporter {
+    previous: Option<Arc<dyn Reporter>>,
+}
+
+impl Drop for ScopedReporter {
+    fn drop(&mut self) {
+        let previous = self.previous.take();
+        CURRENT.with(|current| *current.borrow_mut() = previous);
+    }
+}
+
+/// Call `f` with the reporter of this thread, returning whether there was
+/// one.
+pub(crate) fn with_current<F: FnOnce(&dyn Reporter)>(f: F) -> bool {
+    let reporter = CURRENT.with(|current| current.borrow().clone());
+    match reporter {
+        Some(reporter) => {
+            f(&*reporter);
+            true
+        }
+        None => false,
+    }
+}
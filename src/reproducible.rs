@@ -0,0 +1,377 @@
+//! Support for reproducible builds.
+//!
+//! When `--reproducible` is passed, every tool we spawn sees the same
+//! `SOURCE_DATE_EPOCH`, absolute paths are remapped out of the compiled wasm,
+//! and a `build-manifest.json` listing the SHA-256 of every artifact is written
+//! into the out dir so that two builds can be compared.
+//!
+//! Nothing is stripped from the output of `wasm-opt` or `wasmedgec`: they are
+//! only given `SOURCE_DATE_EPOCH`, so a version that embeds the current time
+//! anyway still makes two builds differ, which their manifests will show.
+
+use build::wasm_target;
+use child;
+use failure::{Error, ResultExt};
+use hex;
+use log::info;
+use sha2::{Digest, Sha256};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use toml;
+use walkdir::WalkDir;
+use PBAR;
+
+/// The name of the manifest written into the out dir.
+pub const BUILD_MANIFEST: &str = "build-manifest.json";
+
+/// Settings shared by every step of a reproducible build.
+#[derive(Clone, Debug)]
+pub struct Reproducible {
+    source_date_epoch: u64,
+    remap_path_prefixes: Vec<(PathBuf, String)>,
+}
+
+#[derive(Serialize)]
+struct BuildManifest {
+    source_date_epoch: u64,
+    artifacts: Vec<BuildManifestArtifact>,
+}
+
+#[derive(Serialize)]
+struct BuildManifestArtifact {
+    path: String,
+    size: u64,
+    sha256: String,
+}
+
+impl Reproducible {
+    /// Resolve the reproducible build settings for the crate at `crate_path`.
+    ///
+    /// `SOURCE_DATE_EPOCH` is taken from the environment if it is set,
+    /// otherwise from the timestamp of the last git commit, and falls back to
+    /// `0` when the crate is not in a git repository.
+    pub fn new(crate_path: &Path, workspace_root: &Path) -> Result<Reproducible, Error> {
+        let source_date_epoch = match env::var("SOURCE_DATE_EPOCH") {
+            Ok(epoch) => epoch
+                .trim()
+                .parse::<u64>()
+                .with_context(|_| format!("invalid SOURCE_DATE_EPOCH: {}", epoch))?,
+            Err(_) => git_commit_timestamp(crate_path).unwrap_or(0),
+        };
+        info!("Using SOURCE_DATE_EPOCH={}", source_date_epoch);
+
+        let mut remap_path_prefixes = vec![(workspace_root.to_path_buf(), "/build".to_string())];
+        if let Some(cargo_home) = cargo_home() {
+            remap_path_prefixes.push((cargo_home, "/cargo".to_string()));
+        }
+
+        Ok(Reproducible {
+            source_date_epoch,
+            remap_path_prefixes,
+        })
+    }
+
+    /// The `SOURCE_DATE_EPOCH` used for this build.
+    pub fn source_date_epoch(&self) -> u64 {
+        self.source_date_epoch
+    }
+
+    /// Configure a tool invocation (`wasm-opt`, `wasmedgec`, ...) so that it
+    /// doesn't embed the current time in its output.
+    pub fn configure(&self, cmd: &mut Command) {
+        cmd.env("SOURCE_DATE_EPOCH", self.source_date_epoch.to_string());
+    }
+
+    /// Configure a `cargo build` of the crate at `crate_path` for
+    /// `wasm_target`. In addition to `configure`, this appends
+    /// `--remap-path-prefix` flags to the rustflags cargo would otherwise use.
+    ///
+    /// Setting `RUSTFLAGS` makes cargo ignore the rustflags in its config
+    /// files, so when `RUSTFLAGS` isn't set, those are passed in it as well.
+    pub fn configure_cargo(&self, cmd: &mut Command, crate_path: &Path, wasm_target: &str) {
+        self.configure(cmd);
+
+        let remap: Vec<String> = self
+            .remap_path_prefixes
+            .iter()
+            .map(|(from, to)| format!("--remap-path-prefix={}={}", from.display(), to))
+            .collect();
+
+        // Cargo prefers `CARGO_ENCODED_RUSTFLAGS`, whose flags are separated
+        // by `\x1f`, to `RUSTFLAGS`.
+        if let Ok(encoded) = env::var("CARGO_ENCODED_RUSTFLAGS") {
+            let mut flags: Vec<String> = encoded
+                .split('\x1f')
+                .filter(|flag| !flag.is_empty())
+                .map(String::from)
+                .collect();
+            flags.extend(remap);
+            cmd.env("CARGO_ENCODED_RUSTFLAGS", flags.join("\x1f"));
+            return;
+        }
+
+        let mut flags = match env::var("RUSTFLAGS") {
+            Ok(rustflags) => rustflags.split_whitespace().map(String::from).collect(),
+            Err(_) => match config_rustflags(crate_path, wasm_target) {
+                Ok(flags) => flags,
+                Err(e) => {
+                    PBAR.warn(&format!(
+                        "Could not read the rustflags in the cargo config, so they are \
+                         not used in this reproducible build: {}",
+                        e
+                    ));
+                    Vec::new()
+                }
+            },
+        };
+        flags.extend(remap);
+        cmd.env("RUSTFLAGS", flags.join(" "));
+    }
+}
+
+/// The rustflags cargo would take from its configuration for a build of the
+/// crate at `crate_path` for `wasm_target`, when `RUSTFLAGS` is not set.
+fn config_rustflags(crate_path: &Path, wasm_target: &str) -> Result<Vec<String>, Error> {
+    let env_key = wasm_target.to_uppercase().replace('-', "_");
+    if let Ok(flags) = env::var(format!("CARGO_TARGET_{}_RUSTFLAGS", env_key)) {
+        return Ok(flags.split_whitespace().map(String::from).collect());
+    }
+
+    let mut configs = Vec::new();
+    for path in cargo_config_files(crate_path) {
+        let contents = fs::read_to_string(&path)
+            .with_context(|_| format!("failed to read: {}", path.display()))?;
+        let config: toml::Value = toml::from_str(&contents)
+            .with_context(|_| format!("failed to parse: {}", path.display()))?;
+        configs.push(config);
+    }
+    let flags = rustflags_from_configs(&configs, wasm_target);
+    if !flags.is_empty() {
+        return Ok(flags);
+    }
+    Ok(env::var("CARGO_BUILD_RUSTFLAGS")
+        .map(|flags| flags.split_whitespace().map(String::from).collect())
+        .unwrap_or_default())
+}
+
+/// The cargo config files that apply in `crate_path`, from the lowest
+/// precedence to the highest: `$CARGO_HOME/config`, then `.cargo/config` in
+/// each directory from the root down to `crate_path`.
+fn cargo_config_files(crate_path: &Path) -> Vec<PathBuf> {
+    let mut dirs: Vec<PathBuf> = crate_path
+        .ancestors()
+        .map(|dir| dir.join(".cargo"))
+        .collect();
+    dirs.extend(cargo_home());
+    dirs.reverse();
+
+    let mut files: Vec<PathBuf> = Vec::new();
+    for dir in dirs {
+        // Cargo reads `config` if both it and `config.toml` exist.
+        let file = match (dir.join("config"), dir.join("config.toml")) {
+            (config, _) if config.is_file() => config,
+            (_, config_toml) if config_toml.is_file() => config_toml,
+            _ => continue,
+        };
+        let canonical = file.canonicalize().unwrap_or_else(|_| file.clone());
+        if !files.contains(&canonical) {
+            files.push(canonical);
+        }
+    }
+    files
+}
+
+/// The rustflags set in cargo `configs`, ordered from the lowest precedence
+/// to the highest. Like cargo, this joins the values of every file, and uses
+/// the `target.<triple>` and `target.<cfg>` tables that apply to
+/// `wasm_target` instead of `build.rustflags` if any of them sets rustflags.
+fn rustflags_from_configs(configs: &[toml::Value], wasm_target: &str) -> Vec<String> {
+    fn flags(value: Option<&toml::Value>) -> Vec<String> {
+        match value {
+            Some(toml::Value::String(flags)) => {
+                flags.split_whitespace().map(String::from).collect()
+            }
+            Some(toml::Value::Array(flags)) => flags
+                .iter()
+                .filter_map(|flag| flag.as_str().map(String::from))
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    let mut target_flags = Vec::new();
+    let mut build_flags = Vec::new();
+    for config in configs {
+        if let Some(targets) = config.get("target").and_then(|t| t.as_table()) {
+            for (platform, table) in targets {
+                if wasm_target::platform_matches(platform, wasm_target) {
+                    target_flags.extend(flags(table.get("rustflags")));
+                }
+            }
+        }
+        build_flags.extend(flags(config.get("build").and_then(|b| b.get("rustflags"))));
+    }
+    if target_flags.is_empty() {
+        build_flags
+    } else {
+        target_flags
+    }
+}
+
+fn git_commit_timestamp(crate_path: &Path) -> Option<u64> {
+    child::git_output(crate_path, &["log", "-1", "--pretty=%ct"])?
+        .parse()
+        .ok()
+}
+
+fn cargo_home() -> Option<PathBuf> {
+    env::var_os("CARGO_HOME")
+        .map(PathBuf::from)
+        .or_else(|| dirs::home_dir().map(|home| home.join(".cargo")))
+}
+
+/// Write `build-manifest.json` into `out_dir`, listing the size and SHA-256
+/// hash of every file in it.
+pub fn write_build_manifest(out_dir: &Path, reproducible: &Reproducible) -> Result<(), Error> {
+    let mut artifacts = Vec::new();
+    for entry in WalkDir::new(out_dir).sort_by(|a, b| a.file_name().cmp(b.file_name())) {
+        let entry = entry?;
+        if !entry.file_type().is_file() {
+            continue;
+        }
+        let relative = entry.path().strip_prefix(out_dir)?;
+        if relative == Path::new(BUILD_MANIFEST) {
+            continue;
+        }
+        let contents = fs::read(entry.path())
+            .with_context(|_| format!("failed to read: {}", entry.path().display()))?;
+        artifacts.push(BuildManifestArtifact {
+            // Always use `/` so that the manifest is identical across platforms.
+            path: relative
+                .components()
+                .map(|c| c.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/"),
+            size: contents.len() as u64,
+            sha256: hex::encode(Sha256::digest(&contents)),
+        });
+    }
+
+    let manifest = BuildManifest {
+        source_date_epoch: reproducible.source_date_epoch(),
+        artifacts,
+    };
+    let manifest_path = out_dir.join(BUILD_MANIFEST);
+    fs::write(&manifest_path, serde_json::to_string_pretty(&manifest)?)
+        .with_context(|_| format!("failed to write: {}", manifest_path.display()))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::Value;
+    use tempfile;
+    use toml;
+
+    fn reproducible() -> Reproducible {
+        Reproducible {
+            source_date_epoch: 1_600_000_000,
+            remap_path_prefixes: Vec::new(),
+        }
+    }
+
+    fn write_out_dir(out_dir: &Path, wasm: &[u8]) {
+        fs::create_dir_all(out_dir.join("snippets/x")).unwrap();
+        fs::write(out_dir.join("b.wasm"), wasm).unwrap();
+        fs::write(out_dir.join("a.js"), "hello").unwrap();
+        fs::write(out_dir.join("snippets/x/y.js"), "").unwrap();
+    }
+
+    fn build_manifest(out_dir: &Path) -> String {
+        write_build_manifest(out_dir, &reproducible()).unwrap();
+        fs::read_to_string(out_dir.join(BUILD_MANIFEST)).unwrap()
+    }
+
+    #[test]
+    fn lists_every_artifact_with_its_hash() {
+        let dir = tempfile::tempdir().unwrap();
+        write_out_dir(dir.path(), b"\0asm");
+        // A manifest left by an earlier build isn't listed.
+        fs::write(dir.path().join(BUILD_MANIFEST), "{}").unwrap();
+
+        let manifest: Value = serde_json::from_str(&build_manifest(dir.path())).unwrap();
+        assert_eq!(manifest["source_date_epoch"], 1_600_000_000);
+        let artifacts = manifest["artifacts"].as_array().unwrap();
+        let paths: Vec<&str> = artifacts.iter().map(|a| a["path"].as_str().unwrap()).collect();
+        assert_eq!(paths, vec!["a.js", "b.wasm", "snippets/x/y.js"]);
+        assert_eq!(artifacts[0]["size"], 5);
+        assert_eq!(
+            artifacts[0]["sha256"],
+            "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824"
+        );
+        assert_eq!(artifacts[2]["size"], 0);
+        assert_eq!(
+            artifacts[2]["sha256"],
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+    }
+
+    #[test]
+    fn identical_builds_have_identical_manifests() {
+        let first = tempfile::tempdir().unwrap();
+        let second = tempfile::tempdir().unwrap();
+        write_out_dir(first.path(), b"\0asm");
+        write_out_dir(second.path(), b"\0asm");
+
+        let manifest = build_manifest(first.path());
+        assert_eq!(manifest, build_manifest(second.path()));
+        // Writing the manifest again doesn't change it.
+        assert_eq!(manifest, build_manifest(first.path()));
+
+        fs::write(second.path().join("b.wasm"), b"\0asm\x01").unwrap();
+        assert_ne!(manifest, build_manifest(second.path()));
+    }
+
+    fn configs(files: &[&str]) -> Vec<toml::Value> {
+        files.iter().map(|file| toml::from_str(file).unwrap()).collect()
+    }
+
+    #[test]
+    fn joins_build_rustflags_of_every_file() {
+        let configs = configs(&[
+            "[build]\nrustflags = \"-C opt-level=s\"",
+            "[build]\nrustflags = [\"-C\", \"lto\"]",
+        ]);
+        assert_eq!(
+            rustflags_from_configs(&configs, "wasm32-wasi"),
+            vec!["-C", "opt-level=s", "-C", "lto"]
+        );
+    }
+
+    #[test]
+    fn prefers_matching_target_rustflags() {
+        let configs = configs(&[r#"
+            [build]
+            rustflags = ["--cfg", "build"]
+
+            [target.wasm32-wasi]
+            rustflags = ["--cfg", "triple"]
+
+            [target.'cfg(target_arch = "wasm32")']
+            rustflags = ["--cfg", "wasm"]
+
+            [target.'cfg(windows)']
+            rustflags = ["--cfg", "windows"]
+        "#]);
+        let mut flags = rustflags_from_configs(&configs, "wasm32-wasi");
+        flags.sort();
+        assert_eq!(flags, vec!["--cfg", "--cfg", "triple", "wasm"]);
+        assert_eq!(
+            rustflags_from_configs(&configs, "wasm32-unknown-unknown"),
+            vec!["--cfg", "wasm"]
+        );
+    }
+}
@@ -0,0 +1,657 @@
+//! Serving the functions of a package over HTTP, the way the Second State
+//! FaaS calls them.
+//!
+//! Every function exported by the package's JavaScript module can be called
+//! with `POST /api/run/<wasm_id>/<function>`. The request body is the
+//! function's argument: a string, or a `Uint8Array` when the content type is
+//! `application/octet-stream`. A string result is returned as text and a
+//! `Uint8Array` result as bytes; the `/api/run/<wasm_id>/<function>/bytes`
+//! form always returns bytes. Any `wasm_id` is accepted. `GET /api/functions`
+//! lists the functions.
+//!
+//! Each call runs in a fresh Node.js process, so calls don't share state,
+//! as on the hosted service, and is stopped if it runs longer than the
+//! timeout. Request bodies are limited to 8 MB and must be sent with a
+//! `Content-Length`.
+//!
+//! Only packages built with `--target nodejs` can be served, as the functions
+//! are called through their JavaScript module, which loads the WasmEdge
+//! runtime from the `wasmedge-core` npm package. WASI binaries have no such
+//! module.
+
+use error::{CodedError, ErrorCode};
+use failure::{Error, Fail, ResultExt};
+use pack;
+use serde_json::{self, json};
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use PBAR;
+
+/// Lists the functions exported by the module in `argv[1]`.
+const LIST_SCRIPT: &str = r#"
+const m = require(process.argv[1]);
+const names = Object.keys(m).filter(
+  (k) => typeof m[k] === "function" && !k.startsWith("__wbindgen") && !k.startsWith("__wbg")
+);
+process.stdout.write(JSON.stringify(names));
+"#;
+
+/// Calls `argv[2]` of the module in `argv[1]` with stdin as a string, or as
+/// bytes if `argv[3]` is `bytes`. Writes `S` and a string result, or `B` and
+/// a byte array result, to stdout. Writes the message of an exception to
+/// stderr.
+const CALL_SCRIPT: &str = r#"
+let out;
+try {
+  const m = require(process.argv[1]);
+  const input = require("fs").readFileSync(0);
+  out = m[process.argv[2]](process.argv[3] === "bytes" ? new Uint8Array(input) : input.toString("utf8"));
+} catch (e) {
+  process.stderr.write(String(e instanceof Error ? e.message : e));
+  process.exit(1);
+}
+if (out instanceof Uint8Array) {
+  process.stdout.write("B");
+  process.stdout.write(out);
+} else {
+  process.stdout.write("S");
+  process.stdout.write(out === undefined ? "" : typeof out === "string" ? out : JSON.stringify(out));
+}
+"#;
+
+/// How long a function may run by default.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// What a function returned.
+#[derive(Debug)]
+pub enum CallOutput {
+    /// A string.
+    Text(String),
+    /// A byte array.
+    Bytes(Vec<u8>),
+}
+
+/// The error of a call that ran longer than the timeout.
+#[derive(Debug)]
+pub struct TimedOut {
+    function: String,
+    timeout: Duration,
+}
+
+impl fmt::Display for TimedOut {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} did not return within {} s",
+            self.function,
+            self.timeout.as_secs_f64()
+        )
+    }
+}
+
+impl Fail for TimedOut {}
+
+/// The functions of a built package.
+#[derive(Debug)]
+pub struct Functions {
+    node: PathBuf,
+    module: PathBuf,
+    names: Vec<String>,
+    timeout: Duration,
+}
+
+impl Functions {
+    /// Load the package in `out_dir`, running it with `node`.
+    pub fn load(out_dir: &Path, node: &Path) -> Result<Functions, Error> {
+        let manifest = pack::read_package_json(out_dir)?;
+        let module = match manifest["main"].as_str() {
+            Some(main) if main.ends_with(".js") && out_dir.join(main).is_file() => {
+                out_dir.join(main).canonicalize()?
+            }
+            _ => bail_code!(
+                ErrorCode::NotServable,
+                "the package in {} has no JavaScript module; build it with `--target nodejs`, \
+                 as WASI binaries can't be served",
+                out_dir.display()
+            ),
+        };
+        let mut functions = Functions {
+            node: node.to_path_buf(),
+            module,
+            names: Vec::new(),
+            timeout: DEFAULT_TIMEOUT,
+        };
+        let output = functions.node_command(LIST_SCRIPT, &[]).output().map_err(|e| {
+            CodedError::new(
+                ErrorCode::NotServable,
+                format!("could not run {}: {}", node.display(), e),
+            )
+        })?;
+        if !output.status.success() {
+            bail_code!(
+                ErrorCode::NotServable,
+                "could not load {}:\n{}",
+                functions.module.display(),
+                String::from_utf8_lossy(&output.stderr).trim_end()
+            );
+        }
+        functions.names = serde_json::from_slice(&output.stdout)
+            .context("failed to read the functions of the package")?;
+        Ok(functions)
+    }
+
+    /// The names of the functions.
+    pub fn names(&self) -> &[String] {
+        &self.names
+    }
+
+    /// Stop calls that run longer than `timeout`.
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    fn node_command(&self, script: &str, args: &[&str]) -> Command {
+        let mut cmd = Command::new(&self.node);
+        cmd.arg("-e").arg(script).arg(&self.module).args(args);
+        // Let the module find the runtime in the package's node_modules.
+        if let Some(dir) = self.module.parent() {
+            cmd.current_dir(dir);
+        }
+        cmd
+    }
+
+    /// Call `function` with `input`, as bytes if `bytes` is set and as a
+    /// string otherwise. A call that runs longer than the timeout is killed
+    /// and fails with `TimedOut`.
+    pub fn call(&self, function: &str, input: &[u8], bytes: bool) -> Result<CallOutput, Error> {
+        let kind = if bytes { "bytes" } else { "text" };
+        let mut child = self
+            .node_command(CALL_SCRIPT, &[function, kind])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+        // Write and read on other threads, so that a function that doesn't
+        // read its input or floods its output can still time out.
+        let mut stdin = child.stdin.take().expect("stdin should be piped");
+        let input = input.to_vec();
+        thread::spawn(move || stdin.write_all(&input));
+        let stdout = read_all(child.stdout.take().expect("stdout should be piped"));
+        let stderr = read_all(child.stderr.take().expect("stderr should be piped"));
+
+        let deadline = Instant::now() + self.timeout;
+        let status = loop {
+            if let Some(status) = child.try_wait()? {
+                break status;
+            }
+            if Instant::now() >= deadline {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Err(TimedOut {
+                    function: function.to_string(),
+                    timeout: self.timeout,
+                }
+                .into());
+            }
+            thread::sleep(Duration::from_millis(10));
+        };
+        let stdout = stdout.join().unwrap_or_default();
+        let stderr = stderr.join().unwrap_or_default();
+        if !status.success() {
+            let stderr = String::from_utf8_lossy(&stderr);
+            bail!("{} failed: {}", function, stderr.trim());
+        }
+        match stdout.split_first() {
+            Some((b'B', bytes)) => Ok(CallOutput::Bytes(bytes.to_vec())),
+            Some((b'S', text)) => Ok(CallOutput::Text(String::from_utf8_lossy(text).into_owned())),
+            _ => bail!("{} returned no result", function),
+        }
+    }
+}
+
+fn read_all<R: Read + Send + 'static>(mut reader: R) -> JoinHandle<Vec<u8>> {
+    thread::spawn(move || {
+        let mut bytes = Vec::new();
+        let _ = reader.read_to_end(&mut bytes);
+        bytes
+    })
+}
+
+/// The largest request body accepted, as functions get their input in memory.
+const MAX_BODY_SIZE: usize = 8 * 1024 * 1024;
+
+/// The largest request line and headers accepted.
+const MAX_HEADER_SIZE: u64 = 64 * 1024;
+
+/// The most connections handled at once. Each has a thread, and a call also
+/// has a Node.js process.
+const MAX_CONNECTIONS: usize = 32;
+
+/// How long a connection may take to send its request.
+const READ_TIMEOUT: Duration = Duration::from_secs(30);
+
+struct Request {
+    method: String,
+    path: String,
+    headers: HashMap<String, String>,
+    body: Vec<u8>,
+}
+
+struct Response {
+    status: u16,
+    content_type: &'static str,
+    body: Vec<u8>,
+}
+
+impl Response {
+    fn new<B: Into<Vec<u8>>>(status: u16, content_type: &'static str, body: B) -> Response {
+        Response {
+            status,
+            content_type,
+            body: body.into(),
+        }
+    }
+
+    fn text<B: Into<Vec<u8>>>(status: u16, body: B) -> Response {
+        Response::new(status, "text/plain; charset=utf-8", body)
+    }
+
+    fn write_to<W: Write>(&self, stream: &mut W) -> Result<(), Error> {
+        let reason = match self.status {
+            200 => "OK",
+            204 => "No Content",
+            400 => "Bad Request",
+            404 => "Not Found",
+            405 => "Method Not Allowed",
+            411 => "Length Required",
+            413 => "Payload Too Large",
+            503 => "Service Unavailable",
+            504 => "Gateway Timeout",
+            _ => "Internal Server Error",
+        };
+        write!(
+            stream,
+            "HTTP/1.1 {} {}\r\n\
+             Content-Type: {}\r\n\
+             Content-Length: {}\r\n\
+             Access-Control-Allow-Origin: *\r\n\
+             Access-Control-Allow-Methods: GET, POST, OPTIONS\r\n\
+             Access-Control-Allow-Headers: Content-Type\r\n\
+             Connection: close\r\n\r\n",
+            self.status,
+            reason,
+            self.content_type,
+            self.body.len()
+        )?;
+        stream.write_all(&self.body)?;
+        stream.flush()?;
+        Ok(())
+    }
+}
+
+/// Read a request from `stream`, or the response to send if it is malformed
+/// or too large. Chunked bodies aren't supported.
+fn read_request<R: Read>(stream: R) -> Result<Request, Response> {
+    let bad_request = |e: &dyn fmt::Display| Response::text(400, format!("{}\n", e));
+    let mut reader = BufReader::new(stream);
+    let mut head = (&mut reader).take(MAX_HEADER_SIZE);
+    let mut line = String::new();
+    head.read_line(&mut line).map_err(|e| bad_request(&e))?;
+    let mut parts = line.split_whitespace();
+    let (method, path) = match (parts.next(), parts.next()) {
+        (Some(method), Some(path)) => (method.to_string(), path.to_string()),
+        _ => return Err(bad_request(&format!("malformed request line: {:?}", line.trim_end()))),
+    };
+
+    let mut headers = HashMap::new();
+    loop {
+        line.clear();
+        if head.read_line(&mut line).map_err(|e| bad_request(&e))? == 0 {
+            if head.limit() == 0 {
+                return Err(Response::text(413, "Request headers are too large\n"));
+            }
+            break;
+        }
+        let header = line.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some(colon) = header.find(':') {
+            headers.insert(
+                header[..colon].trim().to_ascii_lowercase(),
+                header[colon + 1..].trim().to_string(),
+            );
+        }
+    }
+
+    // Reading a chunked body as empty would call the function without its
+    // input, so ask for a `Content-Length` instead.
+    if headers.contains_key("transfer-encoding") {
+        return Err(Response::text(
+            411,
+            "Chunked request bodies are not supported; send a Content-Length\n",
+        ));
+    }
+    let length = match headers.get("content-length") {
+        Some(length) => length
+            .parse::<usize>()
+            .map_err(|_| bad_request(&"invalid Content-Length"))?,
+        None => 0,
+    };
+    if length > MAX_BODY_SIZE {
+        return Err(Response::text(
+            413,
+            format!("The request body is larger than {} bytes\n", MAX_BODY_SIZE),
+        ));
+    }
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body).map_err(|e| bad_request(&e))?;
+    Ok(Request {
+        method,
+        path,
+        headers,
+        body,
+    })
+}
+
+fn respond(functions: &Functions, request: &Request) -> Response {
+    let path = request.path.split('?').next().unwrap_or("");
+    let segments: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+    match (request.method.as_str(), segments.as_slice()) {
+        ("OPTIONS", _) => Response::text(204, ""),
+        ("GET", []) | ("GET", ["api", "functions"]) => Response::new(
+            200,
+            "application/json",
+            json!({ "functions": functions.names() }).to_string(),
+        ),
+        ("POST", ["api", "run", _, function]) => call(functions, request, function, false),
+        ("POST", ["api", "run", _, function, "bytes"]) => call(functions, request, function, true),
+        (_, ["api", "run", ..]) => Response::text(405, "Use POST to call a function\n"),
+        _ => Response::text(404, format!("No route for {}\n", path)),
+    }
+}
+
+fn call(functions: &Functions, request: &Request, function: &str, bytes_output: bool) -> Response {
+    if !functions.names().iter().any(|name| name == function) {
+        return Response::text(404, format!("No function named {}\n", function));
+    }
+    let bytes_input = match request.headers.get("content-type") {
+        Some(content_type) => content_type.starts_with("application/octet-stream"),
+        None => false,
+    };
+    match functions.call(function, &request.body, bytes_input) {
+        Ok(CallOutput::Bytes(bytes)) => Response::new(200, "application/octet-stream", bytes),
+        Ok(CallOutput::Text(text)) if bytes_output => {
+            Response::new(200, "application/octet-stream", text)
+        }
+        Ok(CallOutput::Text(text)) => Response::text(200, text),
+        Err(e) => {
+            let status = if e.downcast_ref::<TimedOut>().is_some() { 504 } else { 500 };
+            Response::text(status, format!("{}\n", e))
+        }
+    }
+}
+
+fn handle(functions: &Functions, mut stream: TcpStream) -> Result<(), Error> {
+    let started = Instant::now();
+    stream.set_read_timeout(Some(READ_TIMEOUT))?;
+    let request = match read_request(&stream) {
+        Ok(request) => request,
+        Err(response) => return response.write_to(&mut stream),
+    };
+    let response = respond(functions, &request);
+    response.write_to(&mut stream)?;
+    PBAR.info(&format!(
+        "{} {} {} ({} ms)",
+        request.method,
+        request.path,
+        response.status,
+        started.elapsed().as_millis()
+    ));
+    Ok(())
+}
+
+/// Serve `functions` on `address` until the process is stopped.
+pub fn serve(functions: Functions, address: &str) -> Result<(), Error> {
+    let listener =
+        TcpListener::bind(address).with_context(|_| format!("failed to listen on {}", address))?;
+    serve_on(listener, functions, MAX_CONNECTIONS)
+}
+
+/// Answer the connections of `listener`, handling at most `max_connections`
+/// at once and turning away the others with a 503.
+fn serve_on(listener: TcpListener, functions: Functions, max_connections: usize) -> Result<(), Error> {
+    let functions = Arc::new(functions);
+    let connections = Arc::new(AtomicUsize::new(0));
+    for stream in listener.incoming() {
+        let mut stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                PBAR.warn(&format!("failed to accept a connection: {}", e));
+                continue;
+            }
+        };
+        if connections.fetch_add(1, Ordering::SeqCst) >= max_connections {
+            connections.fetch_sub(1, Ordering::SeqCst);
+            let _ = Response::text(503, "Too many connections\n").write_to(&mut stream);
+            continue;
+        }
+        let functions = functions.clone();
+        let connections = connections.clone();
+        thread::spawn(move || {
+            if let Err(e) = handle(&functions, stream) {
+                PBAR.warn(&format!("failed to answer a request: {}", e));
+            }
+            connections.fetch_sub(1, Ordering::SeqCst);
+        });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile;
+    use which::which;
+
+    fn parse(request: &str) -> Result<Request, Response> {
+        read_request(request.as_bytes())
+    }
+
+    fn status_of(result: Result<Request, Response>) -> u16 {
+        match result {
+            Ok(_) => panic!("the request was accepted"),
+            Err(response) => response.status,
+        }
+    }
+
+    #[test]
+    fn reads_requests() {
+        let request = parse(
+            "POST /api/run/1/say?x=1 HTTP/1.1\r\n\
+             Host: localhost\r\n\
+             Content-Type: application/octet-stream\r\n\
+             CONTENT-LENGTH: 5\r\n\r\n\
+             hello, and more",
+        )
+        .ok()
+        .unwrap();
+        assert_eq!(request.method, "POST");
+        assert_eq!(request.path, "/api/run/1/say?x=1");
+        assert_eq!(request.headers["content-type"], "application/octet-stream");
+        assert_eq!(request.body, b"hello");
+
+        let request = parse("GET /api/functions HTTP/1.1\r\n\r\n").ok().unwrap();
+        assert!(request.body.is_empty());
+    }
+
+    #[test]
+    fn rejects_malformed_requests() {
+        assert_eq!(status_of(parse("\r\n\r\n")), 400);
+        assert_eq!(status_of(parse("GET\r\n\r\n")), 400);
+        assert_eq!(status_of(parse("POST / HTTP/1.1\r\nContent-Length: x\r\n\r\n")), 400);
+        // The body is shorter than its Content-Length.
+        assert_eq!(status_of(parse("POST / HTTP/1.1\r\nContent-Length: 9\r\n\r\nabc")), 400);
+    }
+
+    #[test]
+    fn rejects_chunked_requests() {
+        let request = "POST /api/run/1/say HTTP/1.1\r\n\
+                       Transfer-Encoding: chunked\r\n\r\n\
+                       5\r\nhello\r\n0\r\n\r\n";
+        assert_eq!(status_of(parse(request)), 411);
+    }
+
+    #[test]
+    fn limits_request_sizes() {
+        let request = format!(
+            "POST / HTTP/1.1\r\nContent-Length: {}\r\n\r\n",
+            MAX_BODY_SIZE + 1
+        );
+        assert_eq!(status_of(parse(&request)), 413);
+
+        let request = format!(
+            "GET / HTTP/1.1\r\nX-Padding: {}\r\n\r\n",
+            "a".repeat(MAX_HEADER_SIZE as usize)
+        );
+        assert_eq!(status_of(parse(&request)), 413);
+    }
+
+    #[test]
+    fn writes_responses() {
+        let mut out = Vec::new();
+        Response::text(411, "send a length\n").write_to(&mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.starts_with("HTTP/1.1 411 Length Required\r\n"));
+        assert!(out.contains("\r\nContent-Type: text/plain; charset=utf-8\r\n"));
+        assert!(out.contains("\r\nContent-Length: 14\r\n"));
+        assert!(out.ends_with("\r\n\r\nsend a length\n"));
+    }
+
+    fn functions(names: &[&str]) -> Functions {
+        Functions {
+            node: PathBuf::from("node"),
+            module: PathBuf::from("/nonexistent/demo.js"),
+            names: names.iter().map(|name| name.to_string()).collect(),
+            timeout: DEFAULT_TIMEOUT,
+        }
+    }
+
+    fn route(functions: &Functions, method: &str, path: &str) -> Response {
+        let request = Request {
+            method: method.to_string(),
+            path: path.to_string(),
+            headers: HashMap::new(),
+            body: Vec::new(),
+        };
+        respond(functions, &request)
+    }
+
+    #[test]
+    fn routes_requests() {
+        let functions = functions(&["say", "add"]);
+
+        for path in &["/", "/api/functions", "/api/functions?pretty"] {
+            let response = route(&functions, "GET", path);
+            assert_eq!(response.status, 200, "{}", path);
+            assert_eq!(response.content_type, "application/json");
+            assert_eq!(response.body, br#"{"functions":["say","add"]}"#.to_vec());
+        }
+        assert_eq!(route(&functions, "OPTIONS", "/api/run/1/say").status, 204);
+        assert_eq!(route(&functions, "GET", "/api/run/1/say").status, 405);
+        assert_eq!(route(&functions, "POST", "/api/run/1/missing").status, 404);
+        assert_eq!(route(&functions, "POST", "/api/run/1/missing/bytes").status, 404);
+        assert_eq!(route(&functions, "POST", "/api/run/1").status, 405);
+        assert_eq!(route(&functions, "GET", "/elsewhere").status, 404);
+    }
+
+    #[test]
+    fn turns_away_connections_over_the_limit() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        thread::spawn(move || serve_on(listener, functions(&[]), 1));
+
+        // The first connection is accepted and waits for its request.
+        let mut first = TcpStream::connect(address).unwrap();
+        let mut second = TcpStream::connect(address).unwrap();
+        let mut response = String::new();
+        second.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 503 "), "{}", response);
+
+        first
+            .write_all(b"GET /api/functions HTTP/1.1\r\n\r\n")
+            .unwrap();
+        let mut response = String::new();
+        first.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"), "{}", response);
+    }
+
+    /// Load a package whose module exports a few plain JavaScript functions,
+    /// or `None` if Node.js isn't installed.
+    fn load_test_package(dir: &Path) -> Option<Functions> {
+        let node = which("node").ok()?;
+        fs::write(
+            dir.join("package.json"),
+            r#"{"name":"demo","version":"0.1.0","main":"demo.js"}"#,
+        )
+        .unwrap();
+        fs::write(
+            dir.join("demo.js"),
+            "exports.upper = (s) => s.toUpperCase();\n\
+             exports.reverse = (b) => b.reverse();\n\
+             exports.fail = () => { throw new Error(\"boom\"); };\n\
+             exports.hang = () => { for (;;) {} };\n",
+        )
+        .unwrap();
+        Some(Functions::load(dir, &node).unwrap())
+    }
+
+    #[test]
+    fn calls_functions() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut functions = match load_test_package(dir.path()) {
+            Some(functions) => functions,
+            None => return,
+        };
+        assert_eq!(functions.names(), &["upper", "reverse", "fail", "hang"]);
+
+        let mut request = Request {
+            method: "POST".to_string(),
+            path: "/api/run/1/upper".to_string(),
+            headers: HashMap::new(),
+            body: b"hello".to_vec(),
+        };
+        let response = respond(&functions, &request);
+        assert_eq!((response.status, response.body), (200, b"HELLO".to_vec()));
+
+        request.path = "/api/run/1/reverse".to_string();
+        request
+            .headers
+            .insert("content-type".to_string(), "application/octet-stream".to_string());
+        request.body = vec![1, 2, 3];
+        let response = respond(&functions, &request);
+        assert_eq!(response.content_type, "application/octet-stream");
+        assert_eq!((response.status, response.body), (200, vec![3, 2, 1]));
+
+        request.path = "/api/run/1/fail".to_string();
+        let response = respond(&functions, &request);
+        assert_eq!(response.status, 500);
+        assert_eq!(response.body, b"fail failed: boom\n".to_vec());
+
+        functions.set_timeout(Duration::from_millis(500));
+        request.path = "/api/run/1/hang".to_string();
+        let started = Instant::now();
+        let response = respond(&functions, &request);
+        assert_eq!(response.status, 504);
+        assert!(started.elapsed() < Duration::from_secs(10));
+    }
+}
@@ -0,0 +1,412 @@
+//! Parsing SPDX license expressions.
+//!
+//! Cargo's `license` key takes an SPDX expression such as
+//! `MIT OR Apache-2.0`, but older crates still use the deprecated `/`
+//! separator (`MIT/Apache-2.0`). `Expression` accepts both and normalizes them
+//! for package.json.
+
+use failure::Error;
+use std::fmt;
+
+/// Common SPDX license identifiers. Others are accepted, but warned about.
+const KNOWN_LICENSES: &[&str] = &[
+    "0BSD",
+    "AFL-3.0",
+    "AGPL-3.0",
+    "AGPL-3.0-only",
+    "AGPL-3.0-or-later",
+    "Apache-1.1",
+    "Apache-2.0",
+    "Artistic-2.0",
+    "BlueOak-1.0.0",
+    "BSD-1-Clause",
+    "BSD-2-Clause",
+    "BSD-2-Clause-Patent",
+    "BSD-3-Clause",
+    "BSD-3-Clause-Clear",
+    "BSD-4-Clause",
+    "BSL-1.0",
+    "CC-BY-4.0",
+    "CC-BY-SA-4.0",
+    "CC0-1.0",
+    "CDDL-1.0",
+    "CDLA-Permissive-2.0",
+    "ECL-2.0",
+    "EPL-1.0",
+    "EPL-2.0",
+    "EUPL-1.2",
+    "GPL-2.0",
+    "GPL-2.0-only",
+    "GPL-2.0-or-later",
+    "GPL-3.0",
+    "GPL-3.0-only",
+    "GPL-3.0-or-later",
+    "ISC",
+    "LGPL-2.0",
+    "LGPL-2.0-only",
+    "LGPL-2.0-or-later",
+    "LGPL-2.1",
+    "LGPL-2.1-only",
+    "LGPL-2.1-or-later",
+    "LGPL-3.0",
+    "LGPL-3.0-only",
+    "LGPL-3.0-or-later",
+    "MIT",
+    "MIT-0",
+    "MPL-1.1",
+    "MPL-2.0",
+    "MS-PL",
+    "NCSA",
+    "OFL-1.1",
+    "OpenSSL",
+    "PostgreSQL",
+    "Python-2.0",
+    "Unicode-3.0",
+    "Unicode-DFS-2016",
+    "Unlicense",
+    "UPL-1.0",
+    "W3C",
+    "WTFPL",
+    "X11",
+    "Zlib",
+];
+
+/// Common SPDX license exceptions, used after `WITH`.
+const KNOWN_EXCEPTIONS: &[&str] = &[
+    "Autoconf-exception-3.0",
+    "Bison-exception-2.2",
+    "Classpath-exception-2.0",
+    "GCC-exception-3.1",
+    "LLVM-exception",
+    "Qt-LGPL-exception-1.1",
+];
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    License(String),
+    Exception(String),
+    And,
+    Or,
+    With,
+    Open,
+    Close,
+}
+
+/// A parsed SPDX license expression.
+#[derive(Clone, Debug)]
+pub struct Expression {
+    tokens: Vec<Token>,
+}
+
+impl Expression {
+    /// Parse `expression`, accepting `/` as a deprecated spelling of `OR` and
+    /// operators in any case.
+    pub fn parse(expression: &str) -> Result<Expression, Error> {
+        let mut tokens = Vec::new();
+        let mut word = String::new();
+        let mut chars = expression.chars().peekable();
+        while let Some(c) = chars.next() {
+            let punct = match c {
+                '(' => Some(Token::Open),
+                ')' => Some(Token::Close),
+                '/' => Some(Token::Or),
+                c if c.is_whitespace() => None,
+                c if is_word_char(c) => {
+                    word.push(c);
+                    if let Some(&next) = chars.peek() {
+                        if is_word_char(next) {
+                            continue;
+                        }
+                    }
+                    let after_with = tokens.last() == Some(&Token::With);
+                    tokens.push(word_token(&word, after_with));
+                    word.clear();
+                    continue;
+                }
+                c => bail!("invalid character `{}` in license `{}`", c, expression),
+            };
+            tokens.extend(punct);
+        }
+
+        let parsed = Expression { tokens };
+        let mut pos = 0;
+        if parsed.check_or(&mut pos).is_err() || pos != parsed.tokens.len() {
+            bail!("`{}` is not a valid SPDX license expression", expression);
+        }
+        Ok(parsed)
+    }
+
+    // expression := and-expression ("OR" and-expression)*
+    fn check_or(&self, pos: &mut usize) -> Result<(), ()> {
+        self.check_and(pos)?;
+        while self.tokens.get(*pos) == Some(&Token::Or) {
+            *pos += 1;
+            self.check_and(pos)?;
+        }
+        Ok(())
+    }
+
+    // and-expression := with-expression ("AND" with-expression)*
+    fn check_and(&self, pos: &mut usize) -> Result<(), ()> {
+        self.check_with(pos)?;
+        while self.tokens.get(*pos) == Some(&Token::And) {
+            *pos += 1;
+            self.check_with(pos)?;
+        }
+        Ok(())
+    }
+
+    // with-expression := license ["WITH" exception] | "(" expression ")"
+    fn check_with(&self, pos: &mut usize) -> Result<(), ()> {
+        match self.tokens.get(*pos) {
+            Some(Token::License(_)) => {
+                *pos += 1;
+                if self.tokens.get(*pos) == Some(&Token::With) {
+                    match self.tokens.get(*pos + 1) {
+                        Some(Token::Exception(_)) => *pos += 2,
+                        _ => return Err(()),
+                    }
+                }
+                Ok(())
+            }
+            Some(Token::Open) => {
+                *pos += 1;
+                self.check_or(pos)?;
+                if self.tokens.get(*pos) != Some(&Token::Close) {
+                    return Err(());
+                }
+                *pos += 1;
+                Ok(())
+            }
+            _ => Err(()),
+        }
+    }
+
+    /// The license identifiers in the expression, in order, without
+    /// duplicates.
+    pub fn licenses(&self) -> Vec<&str> {
+        let mut licenses: Vec<&str> = Vec::new();
+        for token in &self.tokens {
+            if let Token::License(id) = token {
+                if !licenses.contains(&id.as_str()) {
+                    licenses.push(id);
+                }
+            }
+        }
+        licenses
+    }
+
+    /// The license and exception identifiers that aren't known SPDX
+    /// identifiers. `LicenseRef-` and `DocumentRef-` references are allowed.
+    pub fn unknown_ids(&self) -> Vec<&str> {
+        self.tokens
+            .iter()
+            .filter_map(|token| match token {
+                Token::License(id) => {
+                    let base = id.trim_end_matches('+');
+                    let known = KNOWN_LICENSES.contains(&base)
+                        || id.starts_with("LicenseRef-")
+                        || id.starts_with("DocumentRef-");
+                    Some(id.as_str()).filter(|_| !known)
+                }
+                Token::Exception(id) => {
+                    Some(id.as_str()).filter(|id| !KNOWN_EXCEPTIONS.contains(id))
+                }
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+// License ids, exceptions and `LicenseRef-` references are made of these.
+fn is_word_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '-' || c == '.' || c == '+' || c == ':'
+}
+
+fn word_token(word: &str, after_with: bool) -> Token {
+    match word.to_uppercase().as_str() {
+        "AND" => return Token::And,
+        "OR" => return Token::Or,
+        "WITH" => return Token::With,
+        _ => {}
+    }
+    // Use the canonical spelling of identifiers written in another case.
+    let known = if after_with {
+        KNOWN_EXCEPTIONS
+    } else {
+        KNOWN_LICENSES
+    };
+    let (base, plus) = match word.ends_with('+') {
+        true => (&word[..word.len() - 1], "+"),
+        false => (word, ""),
+    };
+    let id = known
+        .iter()
+        .find(|known| known.eq_ignore_ascii_case(base))
+        .map(|known| format!("{}{}", known, plus))
+        .unwrap_or_else(|| word.to_string());
+    if after_with {
+        Token::Exception(id)
+    } else {
+        Token::License(id)
+    }
+}
+
+impl fmt::Display for Expression {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut out = String::new();
+        for token in &self.tokens {
+            let text = match token {
+                Token::License(id) | Token::Exception(id) => id.as_str(),
+                Token::And => "AND",
+                Token::Or => "OR",
+                Token::With => "WITH",
+                Token::Open => "(",
+                Token::Close => ")",
+            };
+            if !out.is_empty() && !out.ends_with('(') && text != ")" {
+                out.push(' ');
+            }
+            out.push_str(text);
+        }
+        f.write_str(&out)
+    }
+}
+
+/// Whether `file_name` (e.g. `LICENSE-APACHE` or `LICENSE-MIT.md`) holds the
+/// text of the license `id`.
+pub fn is_license_file_for(file_name: &str, id: &str) -> bool {
+    let name = file_name.to_uppercase();
+    let name = name
+        .trim_end_matches(".MD")
+        .trim_end_matches(".TXT");
+    let suffix = match name
+        .strip_prefix("LICENSE")
+        .or_else(|| name.strip_prefix("LICENCE"))
+    {
+        Some(suffix) => suffix.trim_start_matches(&['-', '_', '.'][..]),
+        None => return false,
+    };
+    if suffix.is_empty() {
+        return false;
+    }
+
+    let id = id.trim_end_matches('+').to_uppercase();
+    // `Apache-2.0` is usually in `LICENSE-APACHE`, so also match the id
+    // without a trailing version like `-2.0`. Other suffixes, as in `MIT-0`
+    // or `BSD-3-Clause`, name a different license and are kept.
+    let unversioned = match id.rfind('-') {
+        Some(idx) if is_version(&id[idx + 1..]) => &id[..idx],
+        _ => id.as_str(),
+    };
+    suffix == id || suffix == unversioned
+}
+
+/// Whether `s` is a version with at least two parts, like `2.0` or `1.0.0`.
+fn is_version(s: &str) -> bool {
+    let parts: Vec<&str> = s.split('.').collect();
+    parts.len() >= 2
+        && parts
+            .iter()
+            .all(|part| !part.is_empty() && part.chars().all(|c| c.is_ascii_digit()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{is_license_file_for, Expression};
+
+    fn normalize(expression: &str) -> String {
+        Expression::parse(expression).unwrap().to_string()
+    }
+
+    #[test]
+    fn normalizes_slash_to_or() {
+        assert_eq!(normalize("MIT/Apache-2.0"), "MIT OR Apache-2.0");
+        assert_eq!(normalize("MIT / Apache-2.0"), "MIT OR Apache-2.0");
+        assert_eq!(normalize("Apache-2.0/ISC/MIT"), "Apache-2.0 OR ISC OR MIT");
+    }
+
+    #[test]
+    fn parses_operators_in_any_case() {
+        assert_eq!(normalize("MIT and Zlib"), "MIT AND Zlib");
+        assert_eq!(normalize("mit or apache-2.0"), "MIT OR Apache-2.0");
+        assert_eq!(
+            normalize("Apache-2.0 with LLVM-exception"),
+            "Apache-2.0 WITH LLVM-exception"
+        );
+    }
+
+    #[test]
+    fn parses_parentheses() {
+        assert_eq!(
+            normalize("(MIT OR Apache-2.0) AND Unicode-DFS-2016"),
+            "(MIT OR Apache-2.0) AND Unicode-DFS-2016"
+        );
+        assert_eq!(
+            normalize("((MIT/Apache-2.0))"),
+            "((MIT OR Apache-2.0))"
+        );
+        let expression = Expression::parse("(MIT OR Apache-2.0) AND BSD-3-Clause").unwrap();
+        assert_eq!(expression.licenses(), vec!["MIT", "Apache-2.0", "BSD-3-Clause"]);
+    }
+
+    #[test]
+    fn keeps_or_later_plus() {
+        assert_eq!(normalize("gpl-2.0+"), "GPL-2.0+");
+        let expression = Expression::parse("GPL-2.0+").unwrap();
+        assert!(expression.unknown_ids().is_empty());
+    }
+
+    #[test]
+    fn reports_unknown_ids() {
+        let expression =
+            Expression::parse("Foo-1.0 OR LicenseRef-Custom OR MIT WITH Bar-exception").unwrap();
+        assert_eq!(expression.unknown_ids(), vec!["Foo-1.0", "Bar-exception"]);
+    }
+
+    #[test]
+    fn rejects_malformed_expressions() {
+        for expression in &[
+            "",
+            "MIT OR",
+            "AND MIT",
+            "MIT Apache-2.0",
+            "(MIT",
+            "MIT)",
+            "()",
+            "MIT WITH",
+            "MIT WITH OR Apache-2.0",
+            "MIT, Apache-2.0",
+        ] {
+            assert!(
+                Expression::parse(expression).is_err(),
+                "`{}` should not parse",
+                expression
+            );
+        }
+    }
+
+    #[test]
+    fn matches_license_files() {
+        assert!(is_license_file_for("LICENSE-MIT", "MIT"));
+        assert!(is_license_file_for("license-mit.md", "MIT"));
+        assert!(is_license_file_for("LICENSE-APACHE", "Apache-2.0"));
+        assert!(is_license_file_for("LICENCE_APACHE.txt", "Apache-2.0"));
+        assert!(is_license_file_for("LICENSE-Apache-2.0", "Apache-2.0"));
+        assert!(is_license_file_for("LICENSE-MIT-0", "MIT-0"));
+        assert!(is_license_file_for("LICENSE-BSD-3-Clause", "BSD-3-Clause"));
+        assert!(is_license_file_for("LICENSE-GPL", "GPL-3.0+"));
+        assert!(!is_license_file_for("LICENSE", "MIT"));
+        assert!(!is_license_file_for("LICENSE-MIT", "Apache-2.0"));
+        assert!(!is_license_file_for("COPYING", "MIT"));
+    }
+
+    #[test]
+    fn does_not_match_other_licenses_of_a_family() {
+        assert!(!is_license_file_for("LICENSE-MIT", "MIT-0"));
+        assert!(!is_license_file_for("LICENSE-BSD", "BSD-2-Clause"));
+        assert!(!is_license_file_for("LICENSE-BSD", "BSD-3-Clause"));
+        assert!(!is_license_file_for("LICENSE-GPL", "GPL-3.0-only"));
+        assert!(!is_license_file_for("LICENSE-UNICODE", "Unicode-DFS-2016"));
+    }
+}
@@ -1,7 +1,9 @@
 //! Support for downloading and executing `wasm-opt`
 
 use crate::child;
+use crate::config;
 use crate::emoji;
+use crate::reproducible::Reproducible;
 use crate::target;
 use crate::PBAR;
 use binary_install::Cache;
@@ -9,6 +11,9 @@ use log::debug;
 use std::path::{Path, PathBuf};
 use std::process::Command;
 
+/// The version of binaryen `wasm-opt` is downloaded from.
+pub const VERSION: &str = "version_90";
+
 /// Execute `wasm-opt` over wasm binaries found in `out_dir`, downloading if
 /// necessary into `cache`. Passes `args` to each invocation of `wasm-opt`.
 pub fn run(
@@ -16,8 +21,10 @@ pub fn run(
     out_dir: &Path,
     args: &[String],
     install_permitted: bool,
+    reproducible: Option<&Reproducible>,
+    mirror: Option<&str>,
 ) -> Result<(), failure::Error> {
-    let wasm_opt = match find_wasm_opt(cache, install_permitted)? {
+    let wasm_opt = match find_wasm_opt(cache, install_permitted, mirror)? {
         WasmOpt::Found(path) => path,
         WasmOpt::CannotInstall => {
             PBAR.info("Skipping wasm-opt as no downloading was requested");
@@ -39,8 +46,7 @@ pub fn run(
         }
 
         let tmp = path.with_extension("wasm-opt.wasm");
-        let mut cmd = Command::new(&wasm_opt);
-        cmd.arg(&path).arg("-o").arg(&tmp).args(args);
+        let cmd = wasm_opt_command(&wasm_opt, &path, args, reproducible);
         child::run(cmd, "wasm-opt")?;
         std::fs::rename(&tmp, &path)?;
     }
@@ -48,6 +54,44 @@ pub fn run(
     Ok(())
 }
 
+/// The `wasm-opt` command that optimizes `wasm` into a temporary
+/// `.wasm-opt.wasm` file next to it.
+pub fn wasm_opt_command(
+    wasm_opt: &Path,
+    wasm: &Path,
+    args: &[String],
+    reproducible: Option<&Reproducible>,
+) -> Command {
+    let mut cmd = Command::new(wasm_opt);
+    cmd.arg(wasm)
+        .arg("-o")
+        .arg(wasm.with_extension("wasm-opt.wasm"))
+        .args(args);
+    if let Some(reproducible) = reproducible {
+        reproducible.configure(&mut cmd);
+    }
+    cmd
+}
+
+/// The URL of the precompiled `wasm-opt` for this platform, if there is one.
+pub fn download_url(mirror: Option<&str>) -> Option<String> {
+    let target = if target::LINUX && target::x86_64 {
+        "x86_64-linux"
+    } else if target::MACOS && target::x86_64 {
+        "x86_64-apple-darwin"
+    } else if target::WINDOWS && target::x86_64 {
+        "x86_64-windows"
+    } else {
+        return None;
+    };
+    let url = format!(
+        "https://github.com/WebAssembly/binaryen/releases/download/{vers}/binaryen-{vers}-{target}.tar.gz",
+        vers = VERSION,
+        target = target,
+    );
+    Some(config::apply_mirror(&url, mirror))
+}
+
 /// Possible results of `find_wasm_opt`
 pub enum WasmOpt {
     /// Couldn't install wasm-opt because downloads are forbidden
@@ -64,8 +108,13 @@ pub enum WasmOpt {
 /// Returns `Some` if a binary was found or it was successfully downloaded.
 /// Returns `None` if a binary wasn't found in `PATH` and this platform doesn't
 /// have precompiled binaries. Returns an error if we failed to download the
-/// binary.
-pub fn find_wasm_opt(cache: &Cache, install_permitted: bool) -> Result<WasmOpt, failure::Error> {
+/// binary. If a `mirror` is given it replaces the GitHub host in the download
+/// URL.
+pub fn find_wasm_opt(
+    cache: &Cache,
+    install_permitted: bool,
+    mirror: Option<&str>,
+) -> Result<WasmOpt, failure::Error> {
     // First attempt to look up in PATH. If found assume it works.
     if let Ok(path) = which::which("wasm-opt") {
         debug!("found wasm-opt at {:?}", path);
@@ -73,20 +122,10 @@ pub fn find_wasm_opt(cache: &Cache, install_permitted: bool) -> Result<WasmOpt,
     }
 
     // ... and if that fails download a precompiled version.
-    let target = if target::LINUX && target::x86_64 {
-        "x86_64-linux"
-    } else if target::MACOS && target::x86_64 {
-        "x86_64-apple-darwin"
-    } else if target::WINDOWS && target::x86_64 {
-        "x86_64-windows"
-    } else {
-        return Ok(WasmOpt::PlatformNotSupported);
+    let url = match download_url(mirror) {
+        Some(url) => url,
+        None => return Ok(WasmOpt::PlatformNotSupported),
     };
-    let url = format!(
-        "https://github.com/WebAssembly/binaryen/releases/download/{vers}/binaryen-{vers}-{target}.tar.gz",
-        vers = "version_90",
-        target = target,
-    );
 
     let download = |permit_install| cache.download(permit_install, "wasm-opt", &["wasm-opt"], &url);
 
@@ -1,7 +1,9 @@
 //! Support for downloading and executing `wasmedgec`
 
 use crate::child;
+use crate::config;
 use crate::emoji;
+use crate::reproducible::Reproducible;
 use crate::target;
 use crate::PBAR;
 use binary_install::Cache;
@@ -9,14 +11,20 @@ use log::debug;
 use std::path::{Path, PathBuf};
 use std::process::Command;
 
+/// The version of WasmEdge `wasmedgec` is downloaded from.
+pub const VERSION: &str = "0.8.1";
+
 /// Execute `wasmedgec` over wasm binaries found in `out_dir`, downloading if
 /// necessary into `cache`. Passes `args` to each invocation of `wasmedgec`.
 pub fn run(
     cache: &Cache,
     out_dir: &Path,
+    args: &[String],
     install_permitted: bool,
+    reproducible: Option<&Reproducible>,
+    mirror: Option<&str>,
 ) -> Result<(), failure::Error> {
-    let wasmedgec = match find_wasmedgec(cache, install_permitted)? {
+    let wasmedgec = match find_wasmedgec(cache, install_permitted, mirror)? {
         SsvmcOpt::Found(path) => path,
         SsvmcOpt::CannotInstall => {
             PBAR.info("Skipping wasmedgec as no downloading was requested");
@@ -37,9 +45,7 @@ pub fn run(
             continue;
         }
 
-        let tmp = path.with_extension("so");
-        let mut cmd = Command::new(&wasmedgec);
-        cmd.arg(&path).arg(&tmp);
+        let cmd = wasmedgec_command(&wasmedgec, &path, args, reproducible);
         if let Err(e) = child::run(cmd, "wasmedgec") {
             PBAR.info("You need Ubuntu 20.04 to compile the AOT binary. Please see https://www.secondstate.io/articles/setup-rust-nodejs/");
             return Err(e)
@@ -49,6 +55,34 @@ pub fn run(
     Ok(())
 }
 
+/// The `wasmedgec` command that compiles `wasm` to a `.so` file next to it.
+pub fn wasmedgec_command(
+    wasmedgec: &Path,
+    wasm: &Path,
+    args: &[String],
+    reproducible: Option<&Reproducible>,
+) -> Command {
+    let mut cmd = Command::new(wasmedgec);
+    cmd.args(args).arg(wasm).arg(wasm.with_extension("so"));
+    if let Some(reproducible) = reproducible {
+        reproducible.configure(&mut cmd);
+    }
+    cmd
+}
+
+/// The URL of the precompiled `wasmedgec` for this platform, if there is
+/// one.
+pub fn download_url(mirror: Option<&str>) -> Option<String> {
+    if !(target::LINUX && target::x86_64) {
+        return None;
+    }
+    let url = format!(
+        "https://github.com/WasmEdge/WasmEdge/releases/download/{vers}/WasmEdge-{vers}-manylinux2014_x86_64.tar.gz",
+        vers = VERSION,
+    );
+    Some(config::apply_mirror(&url, mirror))
+}
+
 /// Possible results of `find_wasmedgec`
 pub enum SsvmcOpt {
     /// Couldn't install wasmedgec because downloads are forbidden
@@ -65,8 +99,13 @@ pub enum SsvmcOpt {
 /// Returns `Some` if a binary was found or it was successfully downloaded.
 /// Returns `None` if a binary wasn't found in `PATH` and this platform doesn't
 /// have precompiled binaries. Returns an error if we failed to download the
-/// binary.
-pub fn find_wasmedgec(cache: &Cache, install_permitted: bool) -> Result<SsvmcOpt, failure::Error> {
+/// binary. If a `mirror` is given it replaces the GitHub host in the download
+/// URL.
+pub fn find_wasmedgec(
+    cache: &Cache,
+    install_permitted: bool,
+    mirror: Option<&str>,
+) -> Result<SsvmcOpt, failure::Error> {
     // First attempt to look up in PATH. If found assume it works.
     if let Ok(path) = which::which("wasmedgec") {
         debug!("found wasmedgec at {:?}", path);
@@ -74,15 +113,10 @@ pub fn find_wasmedgec(cache: &Cache, install_permitted: bool) -> Result<SsvmcOpt
     }
 
     // ... and if that fails download a precompiled version.
-    if target::LINUX && target::x86_64 {
-        "x86_64-linux"
-    } else {
-        return Ok(SsvmcOpt::PlatformNotSupported);
+    let url = match download_url(mirror) {
+        Some(url) => url,
+        None => return Ok(SsvmcOpt::PlatformNotSupported),
     };
-    let url = format!(
-        "https://github.com/WasmEdge/WasmEdge/releases/download/{vers}/WasmEdge-{vers}-manylinux2014_x86_64.tar.gz",
-        vers = "0.8.1",
-    );
 
     let download = |permit_install| cache.download(permit_install, "wasmedgec", &["wasmedgec"], &url);
 
@@ -0,0 +1,421 @@
+//! Rebuilding a crate when its files change, for `rustwasmc build --watch`.
+//!
+//! The source directories of every target are watched, along with
+//! Cargo.toml, Cargo.lock and rustwasmc.toml. Changes are debounced and
+//! collected into a single rebuild. When only sources changed and the last
+//! build succeeded, the steps that depend only on the manifests and the
+//! toolchain are skipped. A change to a manifest reloads the configuration
+//! and runs every step again.
+
+use chrono::Local;
+use command::build::{Build, BuildConfig, BuildOptions};
+use command::utils::{elapsed, get_crate_path};
+use emoji;
+use error;
+use failure::{Error, ResultExt};
+use notify::{self, DebouncedEvent, RecursiveMode, Watcher};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::time::{Duration, Instant};
+use PBAR;
+
+/// How long the files must be left alone before a rebuild starts.
+const DEBOUNCE: Duration = Duration::from_millis(300);
+
+/// The files that configure the build. Changing one reloads the
+/// configuration.
+const MANIFESTS: &[&str] = &["Cargo.toml", "Cargo.lock", "rustwasmc.toml"];
+
+/// The steps that don't depend on the crate's sources, skipped when only
+/// sources changed.
+const SOURCE_INDEPENDENT_STEPS: &[&str] = &[
+    "check-rustc-version",
+    "check-crate-config",
+    "check-for-wasm-target",
+    "copy-readme",
+    "copy-license",
+    "write-third-party-licenses",
+    "install-wasm-bindgen",
+];
+
+/// The files that changed since the last build.
+#[derive(Default)]
+struct Changes {
+    paths: Vec<PathBuf>,
+    manifest: bool,
+}
+
+impl Changes {
+    fn describe(&self, crate_path: &Path) -> String {
+        let first = match self.paths.first() {
+            Some(path) => path.strip_prefix(crate_path).unwrap_or(path),
+            None => return "files changed".to_string(),
+        };
+        match self.paths.len() {
+            1 => format!("{} changed", first.display()),
+            2 => format!("{} and 1 other file changed", first.display()),
+            n => format!("{} and {} other files changed", first.display(), n - 1),
+        }
+    }
+}
+
+/// What is watched for a loaded configuration.
+struct Watched {
+    crate_path: PathBuf,
+    manifest_dirs: Vec<PathBuf>,
+    source_dirs: Vec<PathBuf>,
+    ignored: Vec<PathBuf>,
+}
+
+impl Watched {
+    fn new(crate_path: &Path, build: Option<&Build>) -> Watched {
+        let crate_path = absolute(crate_path);
+        let mut manifest_dirs = vec![crate_path.clone()];
+        let mut source_dirs = Vec::new();
+        let mut ignored = Vec::new();
+        if let Some(build) = build {
+            let workspace_root = absolute(build.crate_data.workspace_root());
+            if workspace_root != crate_path {
+                manifest_dirs.push(workspace_root);
+            }
+            source_dirs = build
+                .crate_data
+                .source_dirs()
+                .iter()
+                .map(|dir| absolute(dir))
+                .filter(|dir| dir.is_dir())
+                .collect();
+            ignored.push(absolute(&build.out_dir));
+            ignored.push(absolute(build.crate_data.target_directory()));
+        }
+        Watched {
+            crate_path,
+            manifest_dirs,
+            source_dirs,
+            ignored,
+        }
+    }
+
+    fn watch<W: Watcher>(&self, watcher: &mut W) -> Result<(), Error> {
+        for dir in &self.source_dirs {
+            watcher
+                .watch(dir, RecursiveMode::Recursive)
+                .map_err(watch_error)
+                .with_context(|_| format!("failed to watch {}", dir.display()))?;
+        }
+        // A manifest directory that is also a source directory is already
+        // watched.
+        for dir in &self.manifest_dirs {
+            if self.source_dirs.iter().any(|src| dir.starts_with(src)) {
+                continue;
+            }
+            watcher
+                .watch(dir, RecursiveMode::NonRecursive)
+                .map_err(watch_error)
+                .with_context(|_| format!("failed to watch {}", dir.display()))?;
+        }
+        Ok(())
+    }
+
+    /// Whether a change to `path` is a change to a manifest, to a source, or
+    /// neither.
+    fn classify(&self, path: &Path) -> Option<bool> {
+        if self.ignored.iter().any(|dir| path.starts_with(dir)) {
+            return None;
+        }
+        let is_manifest = match (path.file_name().and_then(|name| name.to_str()), path.parent()) {
+            (Some(name), Some(dir)) => {
+                MANIFESTS.contains(&name) && self.manifest_dirs.iter().any(|m| m == dir)
+            }
+            _ => false,
+        };
+        if is_manifest {
+            Some(true)
+        } else if self.source_dirs.iter().any(|dir| path.starts_with(dir)) {
+            Some(false)
+        } else {
+            None
+        }
+    }
+}
+
+/// notify displays I/O errors with the deprecated `Error::description`, which
+/// no longer says what went wrong, so unwrap them.
+fn watch_error(e: notify::Error) -> Error {
+    match e {
+        notify::Error::Io(e) => e.into(),
+        e => e.into(),
+    }
+}
+
+fn absolute(path: &Path) -> PathBuf {
+    fs::canonicalize(path).unwrap_or_else(|_| match env::current_dir() {
+        Ok(cwd) => cwd.join(path),
+        Err(_) => path.to_path_buf(),
+    })
+}
+
+/// Build the crate, then rebuild it whenever its files change. Only returns
+/// if watching fails.
+pub fn run(opts: BuildOptions) -> Result<(), Error> {
+    let crate_path = get_crate_path(opts.path.clone())?;
+    let mut cause = "initial build".to_string();
+    loop {
+        // (Re)load the configuration and run every step.
+        let mut build = match BuildConfig::from_options(opts.clone()).build() {
+            Ok(build) => Some(build),
+            Err(e) => {
+                report_failure(&cause, &e);
+                None
+            }
+        };
+        let watched = Watched::new(&crate_path, build.as_ref());
+        let mut succeeded = match &mut build {
+            Some(build) => rebuild(build, &[], &cause),
+            None => false,
+        };
+
+        let (tx, rx) = mpsc::channel();
+        let mut watcher = notify::watcher(tx, DEBOUNCE).map_err(watch_error)?;
+        watched.watch(&mut watcher)?;
+        PBAR.info(&format!("{}Watching for changes...", emoji::RUNNER));
+
+        loop {
+            let changes = next_changes(&rx, &watched, DEBOUNCE)?;
+            cause = changes.describe(&watched.crate_path);
+            let build = match &mut build {
+                Some(build) if !changes.manifest => build,
+                _ => break,
+            };
+            succeeded = rebuild(build, skipped_steps(succeeded), &cause);
+        }
+    }
+}
+
+/// The steps a rebuild after a source change skips. They ran in the last
+/// build, unless it failed.
+fn skipped_steps(last_build_succeeded: bool) -> &'static [&'static str] {
+    if last_build_succeeded {
+        SOURCE_INDEPENDENT_STEPS
+    } else {
+        &[]
+    }
+}
+
+/// Wait for changes to watched files, then collect further changes until the
+/// files are left alone for `debounce`.
+fn next_changes(
+    rx: &Receiver<DebouncedEvent>,
+    watched: &Watched,
+    debounce: Duration,
+) -> Result<Changes, Error> {
+    let mut changes = Changes::default();
+    loop {
+        let event = if changes.paths.is_empty() {
+            rx.recv()?
+        } else {
+            match rx.recv_timeout(debounce) {
+                Ok(event) => event,
+                Err(RecvTimeoutError::Timeout) => return Ok(changes),
+                Err(RecvTimeoutError::Disconnected) => bail!("the file watcher stopped"),
+            }
+        };
+        let paths = match event {
+            DebouncedEvent::Create(path)
+            | DebouncedEvent::Write(path)
+            | DebouncedEvent::Remove(path) => vec![path],
+            DebouncedEvent::Rename(from, to) => vec![from, to],
+            // Events were lost, so anything may have changed.
+            DebouncedEvent::Rescan => {
+                changes.manifest = true;
+                vec![watched.crate_path.join("Cargo.toml")]
+            }
+            DebouncedEvent::Error(e, path) => match path {
+                Some(path) => bail!("watching {} failed: {}", path.display(), watch_error(e)),
+                None => bail!("watching files failed: {}", watch_error(e)),
+            },
+            DebouncedEvent::NoticeWrite(_)
+            | DebouncedEvent::NoticeRemove(_)
+            | DebouncedEvent::Chmod(_) => continue,
+        };
+        for path in paths {
+            if let Some(manifest) = watched.classify(&path) {
+                changes.manifest |= manifest;
+                if !changes.paths.contains(&path) {
+                    changes.paths.push(path);
+                }
+            }
+        }
+    }
+}
+
+/// Run `build` without the `skipped` steps and print a status line, returning
+/// whether it succeeded.
+fn rebuild(build: &mut Build, skipped: &[&str], cause: &str) -> bool {
+    let started = Instant::now();
+    match build.run_skipping(skipped) {
+        Ok(_) => {
+            PBAR.info(&format!(
+                "{} [{}] Built in {} ({})",
+                emoji::SPARKLE,
+                Local::now().format("%H:%M:%S"),
+                elapsed(started.elapsed()),
+                cause
+            ));
+            true
+        }
+        Err(e) => {
+            report_failure(cause, &e);
+            false
+        }
+    }
+}
+
+fn report_failure(cause: &str, error: &Error) {
+    PBAR.error(&format!(
+        "[{}] Build failed ({})\n{}",
+        Local::now().format("%H:%M:%S"),
+        cause,
+        error::render_human(error).trim_end()
+    ));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io;
+    use std::sync::mpsc::Sender;
+
+    const SHORT: Duration = Duration::from_millis(20);
+
+    fn watched() -> Watched {
+        Watched {
+            crate_path: PathBuf::from("/ws/app"),
+            manifest_dirs: vec![PathBuf::from("/ws/app"), PathBuf::from("/ws")],
+            source_dirs: vec![PathBuf::from("/ws/app/src"), PathBuf::from("/ws/app/examples")],
+            ignored: vec![PathBuf::from("/ws/app/pkg"), PathBuf::from("/ws/target")],
+        }
+    }
+
+    fn channel() -> (Sender<DebouncedEvent>, Receiver<DebouncedEvent>) {
+        mpsc::channel()
+    }
+
+    fn write(path: &str) -> DebouncedEvent {
+        DebouncedEvent::Write(PathBuf::from(path))
+    }
+
+    #[test]
+    fn classifies_changes() {
+        let watched = watched();
+        let classify = |path: &str| watched.classify(Path::new(path));
+        assert_eq!(classify("/ws/app/Cargo.toml"), Some(true));
+        assert_eq!(classify("/ws/app/rustwasmc.toml"), Some(true));
+        assert_eq!(classify("/ws/Cargo.lock"), Some(true));
+        assert_eq!(classify("/ws/app/src/lib.rs"), Some(false));
+        assert_eq!(classify("/ws/app/examples/demo/main.rs"), Some(false));
+        // Only the manifests of the crate and the workspace count.
+        assert_eq!(classify("/ws/app/src/Cargo.toml"), Some(false));
+        assert_eq!(classify("/ws/other/Cargo.toml"), None);
+        assert_eq!(classify("/ws/app/README.md"), None);
+        // The build's own output is ignored.
+        assert_eq!(classify("/ws/app/pkg/app.wasm"), None);
+        assert_eq!(classify("/ws/target/wasm32-wasi/release/app.wasm"), None);
+    }
+
+    #[test]
+    fn only_source_changes_after_a_successful_build_skip_steps() {
+        assert!(skipped_steps(false).is_empty());
+        let steps = Build::step_names();
+        for step in skipped_steps(true) {
+            assert!(steps.iter().any(|name| name == step), "{} is not a step", step);
+        }
+        for step in &["build-wasm", "copy-wasm", "run-wasm-bindgen", "create-json"] {
+            assert!(!skipped_steps(true).contains(step));
+        }
+    }
+
+    #[test]
+    fn collects_changes_until_the_files_are_left_alone() {
+        let watched = watched();
+        let (tx, rx) = channel();
+        tx.send(write("/ws/app/src/lib.rs")).unwrap();
+        tx.send(DebouncedEvent::NoticeWrite(PathBuf::from("/ws/app/src/a.rs"))).unwrap();
+        tx.send(DebouncedEvent::Chmod(PathBuf::from("/ws/app/src/b.rs"))).unwrap();
+        tx.send(write("/ws/app/pkg/app.wasm")).unwrap();
+        tx.send(write("/ws/app/src/lib.rs")).unwrap();
+        tx.send(DebouncedEvent::Rename(
+            PathBuf::from("/ws/app/src/old.rs"),
+            PathBuf::from("/ws/app/src/new.rs"),
+        ))
+        .unwrap();
+
+        let changes = next_changes(&rx, &watched, SHORT).unwrap();
+        assert!(!changes.manifest);
+        assert_eq!(
+            changes.paths,
+            vec![
+                PathBuf::from("/ws/app/src/lib.rs"),
+                PathBuf::from("/ws/app/src/old.rs"),
+                PathBuf::from("/ws/app/src/new.rs"),
+            ]
+        );
+        assert_eq!(
+            changes.describe(Path::new("/ws/app")),
+            "src/lib.rs and 2 other files changed"
+        );
+
+        // Ignored changes don't start a rebuild.
+        tx.send(write("/ws/app/pkg/app.wasm")).unwrap();
+        tx.send(DebouncedEvent::Create(PathBuf::from("/ws/Cargo.lock"))).unwrap();
+        let changes = next_changes(&rx, &watched, SHORT).unwrap();
+        assert!(changes.manifest);
+        assert_eq!(changes.paths, vec![PathBuf::from("/ws/Cargo.lock")]);
+        assert_eq!(changes.describe(Path::new("/ws/app")), "/ws/Cargo.lock changed");
+    }
+
+    #[test]
+    fn rescans_reload_the_configuration() {
+        let (tx, rx) = channel();
+        tx.send(DebouncedEvent::Rescan).unwrap();
+        let changes = next_changes(&rx, &watched(), SHORT).unwrap();
+        assert!(changes.manifest);
+        assert_eq!(changes.paths, vec![PathBuf::from("/ws/app/Cargo.toml")]);
+    }
+
+    #[test]
+    fn fails_when_watching_fails() {
+        let (tx, rx) = channel();
+        let error = notify::Error::Io(io::Error::new(io::ErrorKind::PermissionDenied, "gone"));
+        tx.send(DebouncedEvent::Error(error, Some(PathBuf::from("/ws/app/src"))))
+            .unwrap();
+        let e = next_changes(&rx, &watched(), SHORT).err().unwrap();
+        assert_eq!(e.to_string(), "watching /ws/app/src failed: gone");
+
+        let (tx, rx) = channel();
+        tx.send(write("/ws/app/src/lib.rs")).unwrap();
+        drop(tx);
+        let e = next_changes(&rx, &watched(), SHORT).err().unwrap();
+        assert_eq!(e.to_string(), "the file watcher stopped");
+    }
+
+    #[test]
+    fn describes_changes() {
+        let crate_path = Path::new("/ws/app");
+        let changes = |paths: &[&str]| Changes {
+            paths: paths.iter().map(PathBuf::from).collect(),
+            manifest: false,
+        };
+        assert_eq!(changes(&[]).describe(crate_path), "files changed");
+        assert_eq!(
+            changes(&["/ws/app/src/lib.rs"]).describe(crate_path),
+            "src/lib.rs changed"
+        );
+        assert_eq!(
+            changes(&["/ws/app/src/lib.rs", "/ws/app/Cargo.toml"]).describe(crate_path),
+            "src/lib.rs and 1 other file changed"
+        );
+    }
+}